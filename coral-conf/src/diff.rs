@@ -0,0 +1,97 @@
+//! changes between two configs
+use std::fmt::Display;
+
+use serde::Serialize;
+use toml::Table;
+use toml::Value;
+
+use crate::error::CoralRes;
+use crate::schema::redact;
+use crate::schema::Schema;
+use crate::validate::join;
+
+/// Changed leaf value at dotted `path`, `None` if missing on that side
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    pub path: String,
+    pub old: Option<Value>,
+    pub new: Option<Value>,
+}
+
+impl Change {
+    /// whether the change is at or under any of dotted `paths`
+    pub fn within(&self, paths: &[&str]) -> bool {
+        paths.iter().any(|p| {
+            self.path == *p
+                || (self.path.starts_with(p) && self.path[p.len()..].starts_with(['.', '[']))
+        })
+    }
+}
+
+impl Display for Change {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let show = |v: &Option<Value>| match v {
+            Some(v) => v.to_string(),
+            None => String::from("none"),
+        };
+        write!(
+            f,
+            "{}: {} -> {}",
+            self.path,
+            show(&self.old),
+            show(&self.new)
+        )
+    }
+}
+
+/// Changes from `old` to `new`, values of secret fields are redacted while
+/// their changes are still reported
+pub fn diff<T: Serialize + Schema>(old: &T, new: &T) -> CoralRes<Vec<Change>> {
+    let (mut old, mut new) = (Value::try_from(old)?, Value::try_from(new)?);
+    let mut paths = Vec::new();
+    if let (Value::Table(old), Value::Table(new)) = (&old, &new) {
+        changed(old, new, "", &mut paths);
+        changed(new, old, "", &mut paths);
+    }
+    paths.sort();
+    paths.dedup();
+    let schema = T::schema();
+    redact(&mut old, &schema);
+    redact(&mut new, &schema);
+    Ok(paths
+        .into_iter()
+        .map(|path| Change {
+            old: lookup(&old, &path).cloned(),
+            new: lookup(&new, &path).cloned(),
+            path,
+        })
+        .collect())
+}
+
+fn lookup<'a>(value: &'a Value, path: &str) -> Option<&'a Value> {
+    path.split('.')
+        .try_fold(value, |v, k| v.as_table().and_then(|t| t.get(k)))
+}
+
+pub(crate) fn leaves(value: &Value, path: &str, f: &mut impl FnMut(String)) {
+    match value {
+        Value::Table(table) => {
+            for (k, v) in table.iter() {
+                leaves(v, &join(path, k), f);
+            }
+        }
+        _ => f(path.to_owned()),
+    }
+}
+
+/// paths of leaves in `after` which are missing or different in `before`
+pub(crate) fn changed(before: &Table, after: &Table, path: &str, out: &mut Vec<String>) {
+    for (k, v) in after.iter() {
+        let key = join(path, k);
+        match (before.get(k), v) {
+            (Some(Value::Table(b)), Value::Table(a)) => changed(b, a, &key, out),
+            (Some(b), a) if b == a => {}
+            (_, a) => leaves(a, &key, &mut |p| out.push(p)),
+        }
+    }
+}
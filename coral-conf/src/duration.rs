@@ -0,0 +1,79 @@
+//! humantime durations in toml
+//!
+//! `std::time::Duration` is read by serde from `{ secs, nanos }` only, fields
+//! with `#[serde(default, with = "coral_conf::duration")]` also take strings
+//! such as `1m 30s` or `500ms`, and are written back as such strings. Works
+//! on `Duration` and `Option<Duration>`. [`non_zero`] is a rule of
+//! `#[validate(custom)]` for them.
+use std::time::Duration;
+
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serializer;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Repr {
+    Text(String),
+    Parts { secs: u64, nanos: u32 },
+}
+
+impl Repr {
+    fn duration<E: serde::de::Error>(self) -> Result<Duration, E> {
+        match self {
+            Repr::Text(v) => humantime::parse_duration(&v)
+                .map_err(|e| E::custom(format!("invalid duration {:?}: {}", v, e))),
+            Repr::Parts { secs, nanos } => Ok(Duration::new(secs, nanos)),
+        }
+    }
+}
+
+/// duration fields of [`serialize`] and [`deserialize`]
+pub trait HumanTime: Sized {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>;
+
+    fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>;
+}
+
+impl HumanTime for Duration {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(&humantime::format_duration(*self))
+    }
+
+    fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Repr::deserialize(deserializer)?.duration()
+    }
+}
+
+impl HumanTime for Option<Duration> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Some(v) => HumanTime::serialize(v, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Option::<Repr>::deserialize(deserializer)?
+            .map(Repr::duration)
+            .transpose()
+    }
+}
+
+pub fn serialize<T: HumanTime, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    value.serialize(serializer)
+}
+
+pub fn deserialize<'de, T: HumanTime, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    T::deserialize(deserializer)
+}
+
+/// rule of `#[validate(custom = "coral_conf::duration::non_zero")]`
+pub fn non_zero(value: &Duration) -> Result<(), &'static str> {
+    match value.is_zero() {
+        true => Err("duration should not be 0"),
+        false => Ok(()),
+    }
+}
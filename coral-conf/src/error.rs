@@ -0,0 +1,35 @@
+use std::path::PathBuf;
+
+use crate::loader::Report;
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("failed to read {0}: {1}")]
+    Read(PathBuf, std::io::Error),
+
+    #[error("failed to parse {0}: {1}")]
+    Parse(String, toml::de::Error),
+
+    #[error("include cycle at {0}")]
+    IncludeCycle(PathBuf),
+
+    #[error("invalid include in {0}, should be a path or list of paths")]
+    InvalidInclude(PathBuf),
+
+    #[error("invalid override {0}, should be key.path=value")]
+    InvalidSet(String),
+
+    #[error("invalid value {1:?} of flag --{0}")]
+    InvalidFlag(String, String),
+
+    #[error("invalid env override: {0}")]
+    Env(#[from] serde_json::Error),
+
+    #[error("failed to serialize config: {0}")]
+    Serialize(#[from] toml::ser::Error),
+
+    #[error("invalid config\n{0}")]
+    Invalid(Report),
+}
+
+pub(crate) type CoralRes<T> = Result<T, Error>;
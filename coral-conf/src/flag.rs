@@ -0,0 +1,182 @@
+//! command line flags of config fields
+//!
+//! Every scalar field, or list of scalars, of a [`Schema`] gets a flag named
+//! by its dotted path, e.g. `h2.server_conf.port` is `--h2-server-conf-port`.
+//! Fields under `oneOf` and maps have no flag, use `--set` for them.
+use serde_json::Value;
+
+use crate::error::CoralRes;
+use crate::error::Error;
+use crate::loader::Loader;
+use crate::schema::Schema;
+use crate::validate::join;
+
+#[derive(Debug, Clone)]
+pub struct Flag {
+    /// long name without leading `--`
+    pub name: String,
+    /// dotted paths assigned by the flag
+    pub paths: Vec<String>,
+    pub help: Option<String>,
+    schema: Value,
+}
+
+impl Flag {
+    /// whether the flag is a list and may be repeated
+    pub fn multiple(&self) -> bool {
+        self.schema.get("type") == Some(&Value::from("array"))
+    }
+
+    /// whether the flag is a switch, `--flag` alone means true
+    pub fn switch(&self) -> bool {
+        self.schema.get("type") == Some(&Value::from("boolean"))
+    }
+
+    /// toml value of raw flag values, typed by schema of the field
+    pub fn value<S: AsRef<str>>(&self, raws: &[S]) -> CoralRes<toml::Value> {
+        match self.schema.get("items") {
+            Some(items) if self.multiple() => raws
+                .iter()
+                .map(|raw| self.scalar(items, raw.as_ref()))
+                .collect::<CoralRes<Vec<_>>>()
+                .map(toml::Value::Array),
+            _ => match raws.last() {
+                Some(raw) => self.scalar(&self.schema, raw.as_ref()),
+                None => Err(self.invalid("")),
+            },
+        }
+    }
+
+    fn scalar(&self, schema: &Value, raw: &str) -> CoralRes<toml::Value> {
+        match schema.get("type").and_then(Value::as_str) {
+            Some("integer") => raw
+                .parse()
+                .map(toml::Value::Integer)
+                .map_err(|_| self.invalid(raw)),
+            Some("number") => raw
+                .parse()
+                .map(toml::Value::Float)
+                .map_err(|_| self.invalid(raw)),
+            Some("boolean") => raw
+                .parse()
+                .map(toml::Value::Boolean)
+                .map_err(|_| self.invalid(raw)),
+            _ => Ok(toml::Value::String(raw.to_owned())),
+        }
+    }
+
+    fn invalid(&self, raw: &str) -> Error {
+        Error::InvalidFlag(self.name.clone(), raw.to_owned())
+    }
+}
+
+/// Flags of every field of config `T`, with aliases
+#[derive(Debug, Clone)]
+pub struct Flags {
+    flags: Vec<Flag>,
+}
+
+impl Flags {
+    pub fn new<T: Schema>() -> Self {
+        let mut flags = Vec::new();
+        collect(&T::schema(), "", &mut flags);
+        Self { flags }
+    }
+
+    /// Flag `name` assigning all of `paths`, e.g. `--port` for the ports of
+    /// both h2 and h3 servers. Paths without flag are ignored.
+    pub fn alias(mut self, name: &str, paths: &[&str]) -> Self {
+        let Some(flag) = paths.first().and_then(|path| self.get_path(path)).cloned() else {
+            return self;
+        };
+        let paths: Vec<String> = paths
+            .iter()
+            .filter(|path| self.get_path(path).is_some())
+            .map(|path| (*path).to_owned())
+            .collect();
+        let names: Vec<String> = paths
+            .iter()
+            .map(|path| format!("--{}", name_of(path)))
+            .collect();
+        let help = match flag.help.as_ref() {
+            Some(help) => format!("{}, same as {}", help, names.join(" ")),
+            None => format!("same as {}", names.join(" ")),
+        };
+        let alias = Flag {
+            name: name.to_owned(),
+            paths,
+            help: Some(help),
+            schema: flag.schema,
+        };
+        self.flags.push(alias);
+        self
+    }
+
+    fn get_path(&self, path: &str) -> Option<&Flag> {
+        self.flags.iter().find(|flag| flag.paths == [path])
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Flag> {
+        self.flags.iter().find(|flag| flag.name == name)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Flag> {
+        self.flags.iter()
+    }
+
+    /// Override `loader` by raw values of each flag given by `raws`, aliases
+    /// first, so that flags of a single field win over aliases
+    pub fn apply<F>(&self, mut loader: Loader, mut raws: F) -> CoralRes<Loader>
+    where
+        F: FnMut(&Flag) -> Option<Vec<String>>,
+    {
+        let (fields, aliases): (Vec<&Flag>, Vec<&Flag>) = self
+            .flags
+            .iter()
+            .partition(|flag| flag.paths.len() == 1 && flag.name == name_of(&flag.paths[0]));
+        for flag in aliases.into_iter().chain(fields) {
+            if let Some(raws) = raws(flag) {
+                let value = flag.value(&raws)?;
+                for path in flag.paths.iter() {
+                    loader = loader.value(path, value.clone());
+                }
+            }
+        }
+        Ok(loader)
+    }
+}
+
+/// `h2.server_conf.port` into `h2-server-conf-port`
+fn name_of(path: &str) -> String {
+    path.replace(['.', '_'], "-")
+}
+
+fn collect(schema: &Value, path: &str, flags: &mut Vec<Flag>) {
+    if let Some(properties) = schema.get("properties").and_then(Value::as_object) {
+        for (k, v) in properties {
+            collect(v, &join(path, k), flags);
+        }
+        return;
+    }
+    let scalar = |schema: &Value| {
+        matches!(
+            schema.get("type").and_then(Value::as_str),
+            Some("integer" | "number" | "boolean" | "string")
+        )
+    };
+    let leaf = match schema.get("items") {
+        Some(items) => scalar(items),
+        None => scalar(schema),
+    };
+    if leaf && !path.is_empty() {
+        flags.push(Flag {
+            name: name_of(path),
+            paths: vec![path.to_owned()],
+            help: schema
+                .get("description")
+                .and_then(Value::as_str)
+                .map(ToOwned::to_owned),
+            schema: schema.clone(),
+        });
+    }
+}
@@ -1,5 +1,23 @@
 use coral_macro::env_assign_basic;
 
+pub mod diff;
+pub mod duration;
+mod error;
+pub mod flag;
+pub mod loader;
+pub mod one_or_many;
+pub mod schema;
+pub mod validate;
+pub use diff::Change;
+pub use error::Error;
+pub use flag::Flags;
+pub use loader::Loaded;
+pub use loader::Loader;
+pub use loader::Report;
+pub use loader::Source;
+pub use schema::Schema;
+pub use validate::Validate;
+
 env_assign_basic!(u8);
 env_assign_basic!(u16);
 env_assign_basic!(u32);
@@ -44,16 +62,151 @@ impl<T: serde::de::DeserializeOwned> EnvAssignToml for Vec<T> {
     }
 }
 
+impl EnvAssignToml for std::path::PathBuf {
+    fn assign(&mut self, prefix: Option<&str>) -> std::result::Result<(), serde_json::Error> {
+        if let Some(prefix) = prefix {
+            if let Ok(v) = std::env::var(prefix) {
+                *self = v.into();
+            }
+        }
+        Ok(())
+    }
+}
+
+impl EnvAssignToml for std::net::SocketAddr {
+    fn assign(&mut self, prefix: Option<&str>) -> std::result::Result<(), serde_json::Error> {
+        if let Some(prefix) = prefix {
+            if let Ok(v) = std::env::var(prefix) {
+                *self = v.parse().map_err(|e| invalid(prefix, &v, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// parse humantime string such as `1m 30s` or `500ms`
+impl EnvAssignToml for std::time::Duration {
+    fn assign(&mut self, prefix: Option<&str>) -> std::result::Result<(), serde_json::Error> {
+        if let Some(prefix) = prefix {
+            if let Ok(v) = std::env::var(prefix) {
+                *self = humantime::parse_duration(&v).map_err(|e| invalid(prefix, &v, e))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn invalid<E: std::fmt::Display>(key: &str, val: &str, err: E) -> serde_json::Error {
+    serde::de::Error::custom(format!("invalid value {:?} of {}: {}", val, key, err))
+}
+
+/// env name of map key, uppercased with non alphanumeric replaced by `_`
+fn map_key<K: std::fmt::Display>(prefix: &str, key: &K) -> String {
+    let key: String = key
+        .to_string()
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_uppercase(),
+            false => '_',
+        })
+        .collect();
+    format!("{}_{}", prefix, key)
+}
+
+macro_rules! env_assign_map {
+    ($map:ident, $($bound:path),+) => {
+        /// Merge json object of `prefix` into map, then assign each value with
+        /// `{prefix}_{KEY}`
+        impl<K, V> EnvAssignToml for std::collections::$map<K, V>
+        where
+            K: std::fmt::Display + serde::de::DeserializeOwned $(+ $bound)+,
+            V: EnvAssignToml + serde::de::DeserializeOwned,
+        {
+            fn assign(&mut self, prefix: Option<&str>) -> std::result::Result<(), serde_json::Error> {
+                if let Some(prefix) = prefix {
+                    if let Ok(v) = std::env::var(prefix) {
+                        let map: Self = serde_json::from_str(&v)?;
+                        self.extend(map);
+                    }
+                    for (k, v) in self.iter_mut() {
+                        v.assign(Some(map_key(prefix, k).as_str()))?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    };
+}
+
+env_assign_map!(HashMap, std::hash::Hash, Eq);
+env_assign_map!(BTreeMap, Ord);
+
+/// Read secret of `key` from file in `{key}_FILE` if `key` itself is unset,
+/// used by `#[env_assign(secret)]`
+pub fn secret(key: &str) -> std::result::Result<Option<String>, serde_json::Error> {
+    if std::env::var_os(key).is_some() {
+        return Ok(None);
+    }
+    let file_key = format!("{}_FILE", key);
+    match std::env::var(&file_key) {
+        Ok(path) => match std::fs::read_to_string(&path) {
+            Ok(v) => Ok(Some(v.trim_end_matches(['\r', '\n']).to_owned())),
+            Err(e) => Err(invalid(&file_key, &path, e)),
+        },
+        Err(_) => Ok(None),
+    }
+}
+
+fn str_value(value: &str, raw: bool) -> serde_json::Value {
+    match raw {
+        true => serde_json::Value::String(value.to_owned()),
+        false => serde_json::from_str(value)
+            .unwrap_or_else(|_| serde_json::Value::String(value.to_owned())),
+    }
+}
+
+/// Parse env value as plain string first, then as json, items are separated by
+/// `split` if some, used by `#[env_assign(secret)]` and `#[env_assign(split)]`
+pub fn parse_str<T: serde::de::DeserializeOwned>(
+    value: &str,
+    split: Option<&str>,
+) -> std::result::Result<T, serde_json::Error> {
+    let parse = |raw: bool| match split {
+        Some(sep) => serde_json::Value::Array(
+            value
+                .split(sep)
+                .map(str::trim)
+                .filter(|v| !v.is_empty())
+                .map(|v| str_value(v, raw))
+                .collect(),
+        ),
+        None => str_value(value, raw),
+    };
+    T::deserialize(parse(true)).or_else(|_| T::deserialize(parse(false)))
+}
+
 /// Assign values using environment variables, Commonly use `coral_macro::EnvAssign`
 ///
 /// # Example
 ///
 /// ```rust
+/// use coral_conf::EnvAssignToml;
+///
 /// let mut a = 11;
 /// std::env::set_var("CORAL_A", "13");
 /// a.assign(Some("CORAL_A")).unwrap();
 /// assert_eq!(a, 13);
 /// ```
+///
+/// The derive takes structs and enums only
+///
+/// ```compile_fail
+/// #[derive(coral_macro::EnvAssign)]
+/// union Bits {
+///     int: u32,
+///     float: f32,
+/// }
+/// ```
 pub trait EnvAssignToml {
     fn assign(&mut self, prefix: Option<&str>) -> std::result::Result<(), serde_json::Error>;
 }
@@ -0,0 +1,370 @@
+//! layered config loading
+//!
+//! Layers are applied in order: defaults, toml files (each after its
+//! `include`s), environment variables through [`EnvAssignToml`], then
+//! `--set key.path=value` overrides and typed values of command line flags.
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::path::Path;
+use std::path::PathBuf;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use toml::Table;
+use toml::Value;
+
+use crate::diff;
+use crate::error::CoralRes;
+use crate::error::Error;
+use crate::schema::redact;
+use crate::schema::Schema;
+use crate::validate::join;
+use crate::validate::Validate;
+use crate::EnvAssignToml;
+
+/// top level key of files included before the file itself
+pub static INCLUDE_KEY: &str = "include";
+
+/// Layer which supplied a config value
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Source {
+    Default,
+    File(PathBuf),
+    Env,
+    Cli,
+}
+
+impl Display for Source {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Source::Default => write!(f, "default"),
+            Source::File(path) => write!(f, "file {}", path.display()),
+            Source::Env => write!(f, "env"),
+            Source::Cli => write!(f, "cli"),
+        }
+    }
+}
+
+/// Invalid fields of config, keyed by dotted path
+#[derive(Debug, Default)]
+pub struct Report {
+    errors: Vec<(String, String, Option<Source>)>,
+}
+
+impl Report {
+    pub fn push<E: Display>(&mut self, path: &str, err: E) {
+        self.errors.push((path.to_owned(), err.to_string(), None));
+    }
+
+    /// push `res` if it is an error
+    pub fn check<E: Display>(&mut self, path: &str, res: Result<(), E>) {
+        if let Err(err) = res {
+            self.push(path, err);
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// dotted paths of invalid fields
+    pub fn paths(&self) -> impl Iterator<Item = &str> {
+        self.errors.iter().map(|(path, _, _)| path.as_str())
+    }
+
+    pub fn into_result(self) -> Result<(), Error> {
+        match self.is_empty() {
+            true => Ok(()),
+            false => Err(Error::Invalid(self)),
+        }
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (path, err, source) in self.errors.iter() {
+            match source {
+                Some(source) => writeln!(f, "  {}: {} (from {})", path, err, source)?,
+                None => writeln!(f, "  {}: {}", path, err)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Config loaded by [`Loader`] with the source of each value
+#[derive(Debug)]
+pub struct Loaded<T> {
+    pub conf: T,
+    /// source of each leaf value keyed by dotted path
+    pub sources: BTreeMap<String, Source>,
+    /// every file read, includes before the file including them
+    pub files: Vec<PathBuf>,
+}
+
+impl<T> Loaded<T> {
+    /// source of value at dotted `path`, or of its closest parent
+    pub fn source(&self, path: &str) -> Option<&Source> {
+        let mut path = path;
+        loop {
+            if let Some(source) = self.sources.get(path) {
+                return Some(source);
+            }
+            if let Some(source) = self
+                .sources
+                .range(format!("{}.", path)..)
+                .next()
+                .filter(|(k, _)| k.starts_with(&format!("{}.", path)))
+                .map(|(_, v)| v)
+            {
+                return Some(source);
+            }
+            path = &path[..path.rfind('.')?];
+        }
+    }
+
+    /// Collect every invalid field by `f`, error with all of them annotated by
+    /// their sources
+    pub fn check<F: FnOnce(&T, &mut Report)>(self, f: F) -> CoralRes<T> {
+        let mut report = Report::default();
+        f(&self.conf, &mut report);
+        for (path, _, source) in report.errors.iter_mut() {
+            *source = self.source(path).cloned();
+        }
+        report.into_result()?;
+        Ok(self.conf)
+    }
+
+    /// [`Loaded::check`] by [`Validate`] of config
+    pub fn validate(self) -> CoralRes<T>
+    where
+        T: Validate,
+    {
+        self.check(|conf, report| conf.validate("", report))
+    }
+
+    /// config in toml, secret fields of [`Schema`] redacted
+    pub fn dump(&self) -> CoralRes<String>
+    where
+        T: Serialize + Schema,
+    {
+        let mut value = Value::try_from(&self.conf)?;
+        redact(&mut value, &T::schema());
+        Ok(toml::to_string_pretty(&value)?)
+    }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct Loader {
+    env_prefix: Option<String>,
+    defaults: Option<String>,
+    files: Vec<PathBuf>,
+    sets: Vec<String>,
+    values: Vec<Table>,
+}
+
+impl Loader {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// prefix of env overrides, no env override if unset
+    pub fn env_prefix(mut self, prefix: &str) -> Self {
+        self.env_prefix = Some(prefix.to_owned());
+        self
+    }
+
+    /// defaults in toml
+    pub fn defaults(mut self, toml: &str) -> Self {
+        self.defaults = Some(toml.to_owned());
+        self
+    }
+
+    pub fn file<P: Into<PathBuf>>(mut self, path: P) -> Self {
+        self.files.push(path.into());
+        self
+    }
+
+    pub fn files<P: Into<PathBuf>, I: IntoIterator<Item = P>>(mut self, paths: I) -> Self {
+        self.files.extend(paths.into_iter().map(Into::into));
+        self
+    }
+
+    /// override of `key.path=value`, value is parsed as toml or kept as string
+    pub fn set<S: Into<String>>(mut self, kv: S) -> Self {
+        self.sets.push(kv.into());
+        self
+    }
+
+    pub fn sets<S: Into<String>, I: IntoIterator<Item = S>>(mut self, kvs: I) -> Self {
+        self.sets.extend(kvs.into_iter().map(Into::into));
+        self
+    }
+
+    /// override of dotted `path`, applied after `--set` overrides, used by
+    /// [`crate::flag::Flags`]
+    pub fn value(mut self, path: &str, value: Value) -> Self {
+        let keys: Vec<&str> = path.split('.').collect();
+        self.values.push(nest(&keys, value));
+        self
+    }
+
+    pub fn load<T>(self) -> CoralRes<Loaded<T>>
+    where
+        T: DeserializeOwned + Serialize + EnvAssignToml,
+    {
+        let mut sources = BTreeMap::new();
+        let mut table = Table::new();
+        if let Some(defaults) = self.defaults.as_ref() {
+            let layer = defaults
+                .parse::<Table>()
+                .map_err(|e| Error::Parse(String::from("defaults"), e))?;
+            merge(&mut table, layer, "", &Source::Default, &mut sources);
+        }
+        let mut layers = Vec::new();
+        for path in self.files.iter() {
+            read_file(path, &mut Vec::new(), &mut layers)?;
+        }
+        let files = layers.iter().map(|(path, _)| path.clone()).collect();
+        for (path, layer) in layers {
+            merge(&mut table, layer, "", &Source::File(path), &mut sources);
+        }
+        let mut sets = self
+            .sets
+            .iter()
+            .map(|kv| parse_set(kv))
+            .collect::<CoralRes<Vec<_>>>()?;
+        sets.extend(self.values);
+        // overrides are applied before env as well, so they can supply
+        // required fields
+        for layer in sets.iter() {
+            merge(&mut table, layer.clone(), "", &Source::Cli, &mut sources);
+        }
+        let mut conf: T = into_conf(table)?;
+        if let Some(prefix) = self.env_prefix.as_ref() {
+            let before = into_table(&conf)?;
+            conf.assign(Some(prefix))?;
+            let after = into_table(&conf)?;
+            let mut changed = Vec::new();
+            diff::changed(&before, &after, "", &mut changed);
+            for path in changed {
+                sources.retain(|k, _| !k.starts_with(&format!("{}.", path)));
+                sources.insert(path, Source::Env);
+            }
+            let mut table = after;
+            for layer in sets {
+                merge(&mut table, layer, "", &Source::Cli, &mut sources);
+            }
+            conf = into_conf(table)?;
+        }
+        Ok(Loaded {
+            conf,
+            sources,
+            files,
+        })
+    }
+}
+
+fn into_conf<T: DeserializeOwned>(table: Table) -> CoralRes<T> {
+    Value::Table(table)
+        .try_into()
+        .map_err(|e| Error::Parse(String::from("merged config"), e))
+}
+
+fn into_table<T: Serialize>(conf: &T) -> CoralRes<Table> {
+    match Value::try_from(conf)? {
+        Value::Table(table) => Ok(table),
+        _ => Ok(Table::new()),
+    }
+}
+
+/// deep merge `src` into `dst`, recording `source` of every leaf of `src`
+fn merge(
+    dst: &mut Table,
+    src: Table,
+    path: &str,
+    source: &Source,
+    sources: &mut BTreeMap<String, Source>,
+) {
+    for (k, v) in src {
+        let key = join(path, &k);
+        match (dst.get_mut(&k), v) {
+            (Some(Value::Table(d)), Value::Table(s)) => merge(d, s, &key, source, sources),
+            (_, v) => {
+                sources.retain(|p, _| p != &key && !p.starts_with(&format!("{}.", key)));
+                diff::leaves(&v, &key, &mut |p| {
+                    sources.insert(p, source.clone());
+                });
+                dst.insert(k, v);
+            }
+        }
+    }
+}
+
+/// read `path` and its includes into `layers`, includes come first
+fn read_file(
+    path: &Path,
+    stack: &mut Vec<PathBuf>,
+    layers: &mut Vec<(PathBuf, Table)>,
+) -> CoralRes<()> {
+    let canonical = path
+        .canonicalize()
+        .map_err(|e| Error::Read(path.to_owned(), e))?;
+    if stack.contains(&canonical) {
+        return Err(Error::IncludeCycle(path.to_owned()));
+    }
+    let buf = std::fs::read_to_string(path).map_err(|e| Error::Read(path.to_owned(), e))?;
+    let mut table = buf
+        .parse::<Table>()
+        .map_err(|e| Error::Parse(path.display().to_string(), e))?;
+    let includes = match table.remove(INCLUDE_KEY) {
+        None => Vec::new(),
+        Some(Value::String(v)) => vec![v],
+        Some(Value::Array(v)) => v
+            .into_iter()
+            .map(|v| match v {
+                Value::String(v) => Ok(v),
+                _ => Err(Error::InvalidInclude(path.to_owned())),
+            })
+            .collect::<CoralRes<_>>()?,
+        Some(_) => return Err(Error::InvalidInclude(path.to_owned())),
+    };
+    stack.push(canonical);
+    let dir = path.parent().unwrap_or(Path::new(""));
+    for include in includes {
+        read_file(&dir.join(include), stack, layers)?;
+    }
+    stack.pop();
+    layers.push((path.to_owned(), table));
+    Ok(())
+}
+
+/// `a.b=v` into table `{a = {b = v}}`
+fn parse_set(kv: &str) -> CoralRes<Table> {
+    let (key, raw) = kv
+        .split_once('=')
+        .ok_or_else(|| Error::InvalidSet(kv.to_owned()))?;
+    let keys: Vec<&str> = key.trim().split('.').map(str::trim).collect();
+    if keys.iter().any(|k| k.is_empty()) {
+        return Err(Error::InvalidSet(kv.to_owned()));
+    }
+    let raw = raw.trim();
+    let value = match format!("v = {}", raw).parse::<Table>() {
+        Ok(mut t) => t
+            .remove("v")
+            .unwrap_or_else(|| Value::String(raw.to_owned())),
+        Err(_) => Value::String(raw.to_owned()),
+    };
+    Ok(nest(&keys, value))
+}
+
+/// `value` at path of `keys` in a table
+fn nest(keys: &[&str], value: Value) -> Table {
+    let mut table = Table::new();
+    match keys.split_first() {
+        Some((k, [])) => table.insert((*k).to_owned(), value),
+        Some((k, rest)) => table.insert((*k).to_owned(), Value::Table(nest(rest, value))),
+        None => None,
+    };
+    table
+}
@@ -0,0 +1,67 @@
+//! a table or an array of tables in toml
+//!
+//! Fields with `#[serde(default, with = "coral_conf::one_or_many")]` take a
+//! single `[table]` as well as `[[table]]`, and are written back as arrays.
+//! Works on `Vec<T>` and `Option<Vec<T>>`.
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde::Deserializer;
+use serde::Serialize;
+use serde::Serializer;
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Repr<T> {
+    // tried first, structs of serde are read from sequences as well
+    Many(Vec<T>),
+    One(T),
+}
+
+impl<T> From<Repr<T>> for Vec<T> {
+    fn from(repr: Repr<T>) -> Self {
+        match repr {
+            Repr::Many(v) => v,
+            Repr::One(v) => vec![v],
+        }
+    }
+}
+
+/// fields of [`serialize`] and [`deserialize`]
+pub trait OneOrMany: Sized {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error>;
+
+    fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error>;
+}
+
+impl<T: Serialize + DeserializeOwned> OneOrMany for Vec<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        Serialize::serialize(self, serializer)
+    }
+
+    fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Repr::deserialize(deserializer).map(Vec::from)
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> OneOrMany for Option<Vec<T>> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Some(v) => OneOrMany::serialize(v, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Option::<Repr<T>>::deserialize(deserializer)?.map(Vec::from))
+    }
+}
+
+pub fn serialize<T: OneOrMany, S: Serializer>(value: &T, serializer: S) -> Result<S::Ok, S::Error> {
+    value.serialize(serializer)
+}
+
+pub fn deserialize<'de, T: OneOrMany, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<T, D::Error> {
+    T::deserialize(deserializer)
+}
@@ -0,0 +1,164 @@
+//! json schema of config, commonly derived by `coral_macro::Schema`
+//!
+//! Secret fields are marked `writeOnly` and redacted by [`redact`].
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+
+use serde_json::json;
+use serde_json::Value;
+
+/// placeholder of redacted secrets
+pub static REDACTED: &str = "******";
+
+pub trait Schema {
+    fn schema() -> Value;
+
+    /// whether the value may be missing
+    fn optional() -> bool {
+        false
+    }
+}
+
+macro_rules! schema_integer {
+    ($($ty:ty),+) => {
+        $(
+            impl Schema for $ty {
+                fn schema() -> Value {
+                    json!({ "type": "integer", "minimum": <$ty>::MIN, "maximum": <$ty>::MAX })
+                }
+            }
+        )+
+    };
+}
+
+schema_integer!(u8, u16, u32, u64, usize, i8, i16, i32, i64);
+
+impl Schema for f32 {
+    fn schema() -> Value {
+        json!({ "type": "number" })
+    }
+}
+
+impl Schema for f64 {
+    fn schema() -> Value {
+        json!({ "type": "number" })
+    }
+}
+
+impl Schema for bool {
+    fn schema() -> Value {
+        json!({ "type": "boolean" })
+    }
+}
+
+impl Schema for String {
+    fn schema() -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl Schema for std::path::PathBuf {
+    fn schema() -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl Schema for std::net::SocketAddr {
+    fn schema() -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl Schema for std::net::IpAddr {
+    fn schema() -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+/// humantime string, for fields of [`crate::duration`]
+impl Schema for std::time::Duration {
+    fn schema() -> Value {
+        json!({ "type": "string" })
+    }
+}
+
+impl<T: Schema> Schema for Option<T> {
+    fn schema() -> Value {
+        T::schema()
+    }
+
+    fn optional() -> bool {
+        true
+    }
+}
+
+impl<T: Schema> Schema for Vec<T> {
+    fn schema() -> Value {
+        json!({ "type": "array", "items": T::schema() })
+    }
+}
+
+impl<K, V: Schema> Schema for HashMap<K, V> {
+    fn schema() -> Value {
+        json!({ "type": "object", "additionalProperties": V::schema() })
+    }
+}
+
+impl<K, V: Schema> Schema for BTreeMap<K, V> {
+    fn schema() -> Value {
+        json!({ "type": "object", "additionalProperties": V::schema() })
+    }
+}
+
+/// schema document of config `T`
+pub fn root<T: Schema>(title: &str) -> Value {
+    let mut schema = T::schema();
+    if let Value::Object(map) = &mut schema {
+        map.insert(
+            String::from("$schema"),
+            json!("https://json-schema.org/draft/2020-12/schema"),
+        );
+        map.insert(String::from("title"), json!(title));
+    }
+    schema
+}
+
+/// extend `schema` by `keywords` if both are objects, used by derive
+pub fn extend(mut schema: Value, keywords: Value) -> Value {
+    if let (Value::Object(map), Value::Object(keywords)) = (&mut schema, keywords) {
+        map.extend(keywords);
+    }
+    schema
+}
+
+/// Replace values whose schema is `writeOnly` by [`REDACTED`]
+pub fn redact(value: &mut toml::Value, schema: &Value) {
+    if schema.get("writeOnly") == Some(&Value::Bool(true)) {
+        *value = toml::Value::String(REDACTED.to_owned());
+        return;
+    }
+    if let Some(Value::Array(schemas)) = schema.get("oneOf") {
+        for schema in schemas {
+            redact(value, schema);
+        }
+    }
+    match value {
+        toml::Value::Table(table) => {
+            let properties = schema.get("properties").and_then(Value::as_object);
+            let additional = schema.get("additionalProperties");
+            for (k, v) in table.iter_mut() {
+                if let Some(schema) = properties.and_then(|p| p.get(k)).or(additional) {
+                    redact(v, schema);
+                }
+            }
+        }
+        toml::Value::Array(items) => {
+            if let Some(schema) = schema.get("items") {
+                for item in items.iter_mut() {
+                    redact(item, schema);
+                }
+            }
+        }
+        _ => {}
+    }
+}
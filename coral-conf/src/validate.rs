@@ -0,0 +1,83 @@
+//! rules of `coral_macro::Validate`
+use std::fmt::Display;
+use std::path::Path;
+
+use crate::loader::Report;
+
+/// Validate config, pushing every invalid field to `report` with dotted path
+/// under `path`, commonly derived by `coral_macro::Validate`
+pub trait Validate {
+    fn validate(&self, path: &str, report: &mut Report);
+}
+
+impl<T: Validate> Validate for Option<T> {
+    fn validate(&self, path: &str, report: &mut Report) {
+        if let Some(this) = self {
+            this.validate(path, report);
+        }
+    }
+}
+
+impl<T: Validate> Validate for Vec<T> {
+    fn validate(&self, path: &str, report: &mut Report) {
+        for (ix, item) in self.iter().enumerate() {
+            item.validate(&format!("{}[{}]", path, ix), report);
+        }
+    }
+}
+
+/// dotted path of `key` under `path`
+pub fn join(path: &str, key: &str) -> String {
+    match path.is_empty() {
+        true => key.to_owned(),
+        false => format!("{}.{}", path, key),
+    }
+}
+
+pub fn range<T: PartialOrd + Display>(
+    value: &T,
+    min: Option<T>,
+    max: Option<T>,
+) -> Result<(), String> {
+    let below = min.as_ref().filter(|min| value < min);
+    let above = max.as_ref().filter(|max| value > max);
+    match (below, above) {
+        (Some(min), _) => Err(format!("{} is less than {}", value, min)),
+        (_, Some(max)) => Err(format!("{} is greater than {}", value, max)),
+        _ => Ok(()),
+    }
+}
+
+pub fn file<P: AsRef<Path>>(value: &P) -> Result<(), String> {
+    match std::fs::metadata(value.as_ref()) {
+        Ok(meta) if meta.is_file() => Ok(()),
+        Ok(_) => Err(format!("{} is not a file", value.as_ref().display())),
+        Err(e) => Err(format!("{}: {}", value.as_ref().display(), e)),
+    }
+}
+
+pub fn dir<P: AsRef<Path>>(value: &P) -> Result<(), String> {
+    match std::fs::metadata(value.as_ref()) {
+        Ok(meta) if meta.is_dir() => Ok(()),
+        Ok(_) => Err(format!("{} is not a directory", value.as_ref().display())),
+        Err(e) => Err(format!("{}: {}", value.as_ref().display(), e)),
+    }
+}
+
+pub fn one_of<S: AsRef<str>>(value: &S, options: &[&str]) -> Result<(), String> {
+    match options.contains(&value.as_ref()) {
+        true => Ok(()),
+        false => Err(format!(
+            "{} is not one of {}",
+            value.as_ref(),
+            options.join(", ")
+        )),
+    }
+}
+
+pub fn port(value: &u16) -> Result<(), String> {
+    match *value {
+        0 => Err(String::from("port should not be 0")),
+        _ => Ok(()),
+    }
+}
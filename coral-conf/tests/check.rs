@@ -63,3 +63,196 @@ fn test_env_assign() {
     a.assign(Some("CORAL_A")).unwrap();
     assert_eq!(a, 13);
 }
+
+#[derive(Deserialize, EnvAssign, Debug)]
+struct Port(u16);
+
+#[derive(Deserialize, EnvAssign, Debug)]
+struct Pair(String, u32);
+
+#[derive(Deserialize, EnvAssign, Debug)]
+enum Upstream {
+    Addr(std::net::SocketAddr, u32),
+    Named { host: String, port: Port },
+}
+
+#[derive(Deserialize, EnvAssign, Debug)]
+struct ShapeConf {
+    port: Port,
+    pair: Pair,
+    addr: std::net::SocketAddr,
+    dir: std::path::PathBuf,
+    timeout: std::time::Duration,
+    labels: std::collections::HashMap<String, String>,
+    limits: std::collections::BTreeMap<String, SubStu>,
+    up1: Upstream,
+    up2: Upstream,
+}
+
+#[test]
+fn test_shapes() {
+    let toml_str = r#"
+        port = 80
+        pair = ["a", 1]
+        addr = "127.0.0.1:80"
+        dir = "/tmp"
+        timeout = { secs = 1, nanos = 0 }
+        labels = { env = "dev", "team-name" = "coral" }
+        [limits.api]
+        val = 10
+        [up1]
+        Addr = ["127.0.0.1:80", 1]
+        [up2.Named]
+        host = "localhost"
+        port = 80
+    "#;
+    let mut conf: ShapeConf = toml::from_str(toml_str).unwrap();
+    std::env::set_var("CORAL_SHAPE_PORT", "8080");
+    std::env::set_var("CORAL_SHAPE_PAIR_0", "b");
+    std::env::set_var("CORAL_SHAPE_PAIR_1", "2");
+    std::env::set_var("CORAL_SHAPE_ADDR", "[::1]:443");
+    std::env::set_var("CORAL_SHAPE_DIR", "/var/lib/coral");
+    std::env::set_var("CORAL_SHAPE_TIMEOUT", "1m 30s");
+    std::env::set_var("CORAL_SHAPE_LABELS", r#"{"region": "us"}"#);
+    std::env::set_var("CORAL_SHAPE_LABELS_TEAM_NAME", "net");
+    std::env::set_var("CORAL_SHAPE_LIMITS_API_VAL", "20");
+    std::env::set_var("CORAL_SHAPE_UP1_ADDR_1", "3");
+    std::env::set_var("CORAL_SHAPE_UP2_NAMED_PORT", "8443");
+    conf.assign(Some("CORAL_SHAPE")).unwrap();
+    assert_eq!(conf.port.0, 8080);
+    assert_eq!((conf.pair.0.as_str(), conf.pair.1), ("b", 2));
+    assert_eq!(conf.addr, "[::1]:443".parse().unwrap());
+    assert_eq!(conf.dir, std::path::PathBuf::from("/var/lib/coral"));
+    assert_eq!(conf.timeout, std::time::Duration::from_secs(90));
+    assert_eq!(conf.labels["env"], "dev");
+    assert_eq!(conf.labels["team-name"], "net");
+    assert_eq!(conf.labels["region"], "us");
+    assert_eq!(conf.limits["api"].val, 20);
+    assert!(matches!(conf.up1, Upstream::Addr(_, 3)));
+    assert!(matches!(conf.up2, Upstream::Named { ref port, .. } if port.0 == 8443));
+
+    std::env::set_var("CORAL_SHAPE_TIMEOUT", "soon");
+    let err = conf.assign(Some("CORAL_SHAPE")).unwrap_err();
+    assert!(err.to_string().contains("CORAL_SHAPE_TIMEOUT"));
+}
+
+#[derive(Deserialize, EnvAssign, Debug)]
+enum Auth {
+    Basic {
+        user: String,
+        #[env_assign(secret)]
+        password: Option<String>,
+    },
+}
+
+#[derive(Deserialize, EnvAssign, Debug)]
+struct AttrConf {
+    #[env_assign(rename = "LISTEN")]
+    addr: String,
+    #[env_assign(skip)]
+    fixed: u16,
+    #[env_assign(secret)]
+    token: String,
+    #[env_assign(split = ",")]
+    alpn: Option<Vec<String>>,
+    #[env_assign(split = ";")]
+    ports: Vec<u16>,
+    auth: Auth,
+}
+
+#[test]
+fn test_field_attr() {
+    let toml_str = r#"
+        addr = "0.0.0.0:80"
+        fixed = 1
+        token = ""
+        ports = [80]
+        [auth.Basic]
+        user = "coral"
+    "#;
+    let mut conf: AttrConf = toml::from_str(toml_str).unwrap();
+    let dir = std::env::temp_dir().join(format!("coral-conf-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("token"), "s3cret\n").unwrap();
+    std::fs::write(dir.join("password"), "passwd").unwrap();
+    std::env::set_var("CORAL_ATTR_LISTEN", "0.0.0.0:443");
+    std::env::set_var("CORAL_ATTR_FIXED", "2");
+    std::env::set_var("CORAL_ATTR_TOKEN_FILE", dir.join("token"));
+    std::env::set_var("CORAL_ATTR_ALPN", "h2, http/1.1");
+    std::env::set_var("CORAL_ATTR_PORTS", "80;443");
+    std::env::set_var("CORAL_ATTR_AUTH_BASIC_PASSWORD_FILE", dir.join("password"));
+    conf.assign(Some("CORAL_ATTR")).unwrap();
+    assert_eq!(conf.addr, "0.0.0.0:443");
+    assert_eq!(conf.fixed, 1);
+    assert_eq!(conf.token, "s3cret");
+    assert_eq!(conf.alpn.unwrap(), ["h2", "http/1.1"]);
+    assert_eq!(conf.ports, [80, 443]);
+    assert!(matches!(conf.auth, Auth::Basic { password: Some(ref v), .. } if v == "passwd"));
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[derive(Deserialize, serde::Serialize, EnvAssign, Debug)]
+struct DurationConf {
+    #[serde(with = "coral_conf::duration")]
+    timeout: std::time::Duration,
+    #[serde(default, with = "coral_conf::duration")]
+    idle: Option<std::time::Duration>,
+    #[serde(default, with = "coral_conf::duration")]
+    keep_alive: Option<std::time::Duration>,
+}
+
+#[test]
+fn test_humantime() {
+    let toml_str = r#"
+        timeout = "1m 30s"
+        idle = { secs = 1, nanos = 500000000 }
+    "#;
+    let mut conf: DurationConf = toml::from_str(toml_str).unwrap();
+    assert_eq!(conf.timeout, std::time::Duration::from_secs(90));
+    assert_eq!(conf.idle, Some(std::time::Duration::from_millis(1500)));
+    assert_eq!(conf.keep_alive, None);
+    std::env::set_var("CORAL_DURATION_KEEP_ALIVE", "500ms");
+    conf.keep_alive = Some(Default::default());
+    conf.assign(Some("CORAL_DURATION")).unwrap();
+    assert_eq!(conf.keep_alive, Some(std::time::Duration::from_millis(500)));
+
+    let dumped = toml::to_string(&conf).unwrap();
+    assert!(dumped.contains(r#"timeout = "1m 30s""#));
+    assert!(dumped.contains(r#"idle = "1s 500ms""#));
+    let parsed: DurationConf = toml::from_str(&dumped).unwrap();
+    assert_eq!(parsed.idle, conf.idle);
+
+    let err = toml::from_str::<DurationConf>(r#"timeout = "soon""#).unwrap_err();
+    assert!(err.to_string().contains("invalid duration"));
+}
+
+#[derive(Deserialize, serde::Serialize, EnvAssign, Debug)]
+struct MountConf {
+    path: String,
+}
+
+#[derive(Deserialize, serde::Serialize, EnvAssign, Debug)]
+struct MountsConf {
+    #[serde(default, with = "coral_conf::one_or_many")]
+    mounts: Option<Vec<MountConf>>,
+}
+
+#[test]
+fn test_one_or_many() {
+    let one: MountsConf = toml::from_str("[mounts]\npath = \"/\"").unwrap();
+    let mounts = one.mounts.unwrap();
+    assert_eq!(mounts.len(), 1);
+    assert_eq!(mounts[0].path, "/");
+
+    let many: MountsConf =
+        toml::from_str("[[mounts]]\npath = \"/\"\n[[mounts]]\npath = \"/docs\"").unwrap();
+    let dumped = toml::to_string(&many).unwrap();
+    assert!(dumped.contains("[[mounts]]"));
+    let parsed: MountsConf = toml::from_str(&dumped).unwrap();
+    let paths: Vec<_> = parsed.mounts.unwrap().into_iter().map(|v| v.path).collect();
+    assert_eq!(paths, ["/", "/docs"]);
+
+    let none: MountsConf = toml::from_str("").unwrap();
+    assert!(none.mounts.is_none());
+    assert!(toml::from_str::<MountsConf>("mounts = 1").is_err());
+}
@@ -0,0 +1,93 @@
+use coral_conf::EnvAssignToml;
+use coral_conf::Flags;
+use coral_conf::Loader;
+use coral_conf::Source;
+use coral_macro::EnvAssign;
+use coral_macro::Schema;
+use serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Debug)]
+struct ServerConf {
+    /// listening port
+    port: u16,
+    domain: Option<String>,
+    insecure: Option<bool>,
+}
+
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Debug)]
+struct Conf {
+    name: String,
+    h2: ServerConf,
+    h3: ServerConf,
+    alpn: Option<Vec<String>>,
+}
+
+#[test]
+fn test_flags() {
+    let flags = Flags::new::<Conf>()
+        .alias("port", &["h2.port", "h3.port"])
+        .alias("missing", &["h2.missing"]);
+    let names: Vec<&str> = flags.iter().map(|flag| flag.name.as_str()).collect();
+    assert_eq!(
+        names,
+        [
+            "alpn",
+            "h2-domain",
+            "h2-insecure",
+            "h2-port",
+            "h3-domain",
+            "h3-insecure",
+            "h3-port",
+            "name",
+            "port"
+        ]
+    );
+    let port = flags.get("port").unwrap();
+    assert_eq!(port.paths, ["h2.port", "h3.port"]);
+    assert_eq!(
+        port.help.as_deref(),
+        Some("listening port, same as --h2-port --h3-port")
+    );
+    assert!(flags.get("alpn").unwrap().multiple());
+    assert!(flags.get("h2-insecure").unwrap().switch());
+    assert!(matches!(
+        flags.get("h2-port").unwrap().value(&["http"]),
+        Err(coral_conf::Error::InvalidFlag(..))
+    ));
+
+    std::env::set_var("CORAL_FLAG_NAME", "env");
+    std::env::set_var("CORAL_FLAG_H3_DOMAIN", "env.test");
+    let given = [
+        ("port", vec!["8080"]),
+        ("h3-port", vec!["8443"]),
+        ("h2-domain", vec!["flag.test"]),
+        ("h2-insecure", vec!["true"]),
+        ("alpn", vec!["h2", "http/1.1"]),
+        ("name", vec!["123"]),
+    ];
+    let loader = Loader::new()
+        .env_prefix("CORAL_FLAG")
+        .defaults("name = \"default\"\nh3 = { port = 443, domain = \"default.test\" }");
+    let loader = flags
+        .apply(loader, |flag| {
+            given
+                .iter()
+                .find(|(name, _)| *name == flag.name)
+                .map(|(_, raws)| raws.iter().map(ToString::to_string).collect())
+        })
+        .unwrap();
+    let loaded = loader.load::<Conf>().unwrap();
+    assert_eq!(loaded.conf.name, "123");
+    assert_eq!(loaded.conf.h2.port, 8080);
+    assert_eq!(loaded.conf.h3.port, 8443);
+    assert_eq!(loaded.conf.h2.domain.as_deref(), Some("flag.test"));
+    assert_eq!(loaded.conf.h3.domain.as_deref(), Some("env.test"));
+    assert_eq!(loaded.conf.h2.insecure, Some(true));
+    assert_eq!(
+        loaded.conf.alpn,
+        Some(vec![String::from("h2"), String::from("http/1.1")])
+    );
+    assert_eq!(loaded.source("name"), Some(&Source::Cli));
+    assert_eq!(loaded.source("h3.domain"), Some(&Source::Env));
+}
@@ -0,0 +1,104 @@
+use coral_conf::EnvAssignToml;
+use coral_conf::Loader;
+use coral_conf::Source;
+use coral_macro::EnvAssign;
+use serde::Deserialize;
+use serde::Serialize;
+
+#[derive(Deserialize, Serialize, EnvAssign, Debug)]
+struct ServerConf {
+    port: u16,
+    domain: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, EnvAssign, Debug)]
+struct Conf {
+    name: String,
+    workers: usize,
+    server: ServerConf,
+    alpn: Vec<String>,
+}
+
+#[test]
+fn test_layers() {
+    let dir = std::env::temp_dir().join(format!("coral-loader-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("base.toml"),
+        r#"
+        name = "base"
+        alpn = ["h2"]
+        [server]
+        port = 80
+        "#,
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("app.toml"),
+        r#"
+        include = "base.toml"
+        name = "app"
+        [server]
+        domain = "coral.test"
+        "#,
+    )
+    .unwrap();
+    std::env::set_var("CORAL_LOADER_SERVER_PORT", "8080");
+    std::env::set_var("CORAL_LOADER_WORKERS", "4");
+    let loaded = Loader::new()
+        .env_prefix("CORAL_LOADER")
+        .defaults("workers = 1")
+        .file(dir.join("app.toml"))
+        .set("workers=8")
+        .set("server.domain = other.test")
+        .load::<Conf>()
+        .unwrap();
+    assert_eq!(loaded.conf.name, "app");
+    assert_eq!(loaded.conf.alpn, ["h2"]);
+    assert_eq!(loaded.conf.server.port, 8080);
+    assert_eq!(loaded.conf.server.domain.as_deref(), Some("other.test"));
+    assert_eq!(loaded.conf.workers, 8);
+    assert_eq!(
+        loaded.source("name"),
+        Some(&Source::File(dir.join("app.toml")))
+    );
+    assert_eq!(
+        loaded.source("alpn"),
+        Some(&Source::File(dir.join("base.toml")))
+    );
+    assert_eq!(loaded.source("server.port"), Some(&Source::Env));
+    assert_eq!(loaded.source("server.domain"), Some(&Source::Cli));
+    assert_eq!(loaded.source("workers"), Some(&Source::Cli));
+
+    let err = loaded
+        .check(|conf, report| {
+            report.check("server.port", Err::<(), _>("port is in use"));
+            report.check::<&str>("name", Ok(()));
+            if conf.workers > 4 {
+                report.push("workers", "too many workers");
+            }
+        })
+        .unwrap_err();
+    match err {
+        coral_conf::Error::Invalid(report) => {
+            assert_eq!(
+                report.paths().collect::<Vec<_>>(),
+                ["server.port", "workers"]
+            );
+            let text = report.to_string();
+            assert!(text.contains("server.port: port is in use (from env)"));
+            assert!(text.contains("workers: too many workers (from cli)"));
+        }
+        err => panic!("unexpected {:?}", err),
+    }
+
+    std::fs::write(dir.join("cycle.toml"), r#"include = ["cycle.toml"]"#).unwrap();
+    let err = Loader::new()
+        .file(dir.join("cycle.toml"))
+        .load::<Conf>()
+        .unwrap_err();
+    assert!(matches!(err, coral_conf::Error::IncludeCycle(_)));
+    let err = Loader::new().set("name").load::<Conf>().unwrap_err();
+    assert!(matches!(err, coral_conf::Error::InvalidSet(_)));
+    std::fs::remove_dir_all(dir).unwrap();
+}
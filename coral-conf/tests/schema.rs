@@ -0,0 +1,141 @@
+use coral_conf::EnvAssignToml;
+use coral_macro::EnvAssign;
+use coral_macro::Schema;
+use coral_macro::Validate;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::json;
+
+#[derive(Deserialize, Serialize, EnvAssign, Schema)]
+struct Single {
+    host: String,
+    #[env_assign(secret)]
+    password: Option<String>,
+}
+
+#[derive(Deserialize, Serialize, EnvAssign, Schema)]
+enum Redis {
+    Single(Single),
+    Cluster(Vec<Single>),
+}
+
+#[derive(Deserialize, Serialize, EnvAssign, Schema)]
+enum Format {
+    Json,
+    Human,
+}
+
+/// root config
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate)]
+struct Conf {
+    /// listen port
+    #[validate(port)]
+    port: u16,
+    #[validate(range(min = 0.0, max = 1.0))]
+    ratio: Option<f64>,
+    #[validate(one_of("a", "b"))]
+    mode: String,
+    format: Format,
+    redis: Option<Redis>,
+    #[env_assign(secret)]
+    token: String,
+}
+
+#[test]
+fn test_schema() {
+    let schema = coral_conf::schema::root::<Conf>("conf");
+    assert_eq!(
+        schema["$schema"],
+        "https://json-schema.org/draft/2020-12/schema"
+    );
+    assert_eq!(schema["title"], "conf");
+    assert_eq!(schema["description"], "root config");
+    assert_eq!(schema["type"], "object");
+    assert_eq!(
+        schema["required"],
+        json!(["port", "mode", "format", "token"])
+    );
+    let props = &schema["properties"];
+    assert_eq!(
+        props["port"],
+        json!({
+            "type": "integer",
+            "description": "listen port",
+            "minimum": 1,
+            "maximum": 65535,
+        })
+    );
+    assert_eq!(props["ratio"]["minimum"], 0.0);
+    assert_eq!(props["ratio"]["maximum"], 1.0);
+    assert_eq!(props["mode"]["enum"], json!(["a", "b"]));
+    assert_eq!(
+        props["format"]["oneOf"],
+        json!([{ "const": "Json" }, { "const": "Human" }])
+    );
+    assert_eq!(props["token"]["writeOnly"], true);
+    let single = &props["redis"]["oneOf"][0]["properties"]["Single"];
+    assert_eq!(single["required"], json!(["host"]));
+    assert_eq!(single["properties"]["password"]["writeOnly"], true);
+}
+
+#[test]
+fn test_dump() {
+    let toml_str = r#"
+        port = 80
+        mode = "a"
+        format = "Json"
+        token = "t0ken"
+        [[redis.Cluster]]
+        host = "a"
+        password = "pa55"
+        [[redis.Cluster]]
+        host = "b"
+    "#;
+    let loaded = coral_conf::Loader::new()
+        .defaults(toml_str)
+        .load::<Conf>()
+        .unwrap();
+    let dump = loaded.dump().unwrap();
+    assert!(!dump.contains("t0ken"));
+    assert!(!dump.contains("pa55"));
+    let value: toml::Table = dump.parse().unwrap();
+    assert_eq!(value["token"].as_str(), Some(coral_conf::schema::REDACTED));
+    assert_eq!(value["port"].as_integer(), Some(80));
+    let cluster = value["redis"]["Cluster"].as_array().unwrap();
+    assert_eq!(
+        cluster[0]["password"].as_str(),
+        Some(coral_conf::schema::REDACTED)
+    );
+    assert_eq!(cluster[0]["host"].as_str(), Some("a"));
+    assert!(cluster[1].get("password").is_none());
+}
+
+#[test]
+fn test_diff() {
+    let load = |toml_str: &str| {
+        coral_conf::Loader::new()
+            .defaults(toml_str)
+            .load::<Conf>()
+            .unwrap()
+            .conf
+    };
+    let old = load("port = 80\nmode = \"a\"\nformat = \"Json\"\ntoken = \"t0\"");
+    let new = load("port = 80\nmode = \"b\"\nformat = \"Json\"\ntoken = \"t1\"\nratio = 0.5");
+    let changes = coral_conf::diff::diff(&old, &new).unwrap();
+    let changes: Vec<String> = changes.iter().map(ToString::to_string).collect();
+    assert_eq!(
+        changes,
+        vec![
+            "mode: \"a\" -> \"b\"",
+            "ratio: none -> 0.5",
+            "token: \"******\" -> \"******\"",
+        ]
+    );
+    let change = coral_conf::Change {
+        path: String::from("h2.server_conf.port"),
+        old: None,
+        new: None,
+    };
+    assert!(change.within(&["h2.server_conf"]));
+    assert!(!change.within(&["h2.server"]));
+}
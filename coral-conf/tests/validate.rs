@@ -0,0 +1,95 @@
+use coral_conf::Report;
+use coral_conf::Validate;
+use coral_macro::Validate;
+
+#[derive(Validate)]
+struct TlsConf {
+    #[validate(dir)]
+    ca: Option<String>,
+    #[validate(file)]
+    cert: String,
+}
+
+fn even(value: &u32) -> Result<(), String> {
+    match value % 2 {
+        0 => Ok(()),
+        _ => Err(format!("{} is odd", value)),
+    }
+}
+
+#[derive(Validate)]
+#[validate(custom = "Conf::check_workers")]
+struct Conf {
+    #[validate(port)]
+    port: u16,
+    #[validate(range(min = 0.0, max = 1.0))]
+    ratio: Option<f64>,
+    #[validate(one_of("json", "human"))]
+    format: String,
+    #[validate(range(min = 1), custom = "even")]
+    workers: u32,
+    #[validate(nested)]
+    tls: TlsConf,
+    #[validate(nested)]
+    backup: Option<TlsConf>,
+    #[validate(nested)]
+    upstreams: Vec<TlsConf>,
+}
+
+impl Conf {
+    fn check_workers(&self) -> Result<(), &'static str> {
+        match self.workers > 64 {
+            true => Err("too many workers"),
+            false => Ok(()),
+        }
+    }
+}
+
+#[test]
+fn test_validate() {
+    let dir = std::env::temp_dir();
+    let conf = Conf {
+        port: 0,
+        ratio: Some(1.5),
+        format: String::from("proto"),
+        workers: 65,
+        tls: TlsConf {
+            ca: Some(dir.display().to_string()),
+            cert: String::from("/coral/missing.crt"),
+        },
+        backup: None,
+        upstreams: vec![TlsConf {
+            ca: Some(String::from("/coral/missing")),
+            cert: String::from("/coral/missing.crt"),
+        }],
+    };
+    let mut report = Report::default();
+    conf.validate("", &mut report);
+    assert_eq!(
+        report.paths().collect::<Vec<_>>(),
+        [
+            "port",
+            "ratio",
+            "format",
+            "workers",
+            "tls.cert",
+            "upstreams[0].ca",
+            "upstreams[0].cert",
+            ""
+        ]
+    );
+    let text = report.to_string();
+    assert!(text.contains("ratio: 1.5 is greater than 1"));
+    assert!(text.contains("format: proto is not one of json, human"));
+    assert!(text.contains("workers: 65 is odd"));
+    assert!(text.contains("too many workers"));
+    assert!(report.into_result().is_err());
+
+    let conf = TlsConf {
+        ca: None,
+        cert: concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml").to_owned(),
+    };
+    let mut report = Report::default();
+    conf.validate("tls", &mut report);
+    assert!(report.is_empty());
+}
@@ -1,5 +1,8 @@
 use thiserror::Error;
 
+use crate::logs::otlp::MAX_BATCH_SIZE;
+use crate::logs::otlp::MAX_INTERVAL;
+
 pub(crate) type CoralRes<T> = Result<T, Error>;
 
 #[derive(Error, Debug)]
@@ -16,6 +19,30 @@ pub enum Error {
     #[error("prost error")]
     ProstErr(#[from] prost::EncodeError),
 
-    #[error("invalid log directory")]
-    InvalidLogDir,
+    #[error("tonic transport error")]
+    TonicErr(#[from] tonic::transport::Error),
+
+    #[error("serde json error")]
+    JsonErr(#[from] serde_json::Error),
+
+    #[error("invalid log format {0}")]
+    InvalidLogFormat(String),
+
+    #[error("missing otlp endpoint")]
+    MissingOtlpEndpoint,
+
+    #[error("invalid sample ratio {0}, should be in [0, 1]")]
+    InvalidSampleRatio(f64),
+
+    #[error("invalid batch size {0}, should be in [1, {}]", MAX_BATCH_SIZE)]
+    InvalidBatchSize(usize),
+
+    #[error("invalid interval {0:?}, should be in (0, {:?}]", MAX_INTERVAL)]
+    InvalidInterval(std::time::Duration),
+
+    #[error("metric {0} registered with another type")]
+    MetricConflict(String),
+
+    #[error("{0}")]
+    ConfErr(#[from] coral_conf::Error),
 }
@@ -3,35 +3,125 @@ pub mod logs;
 pub mod metrics;
 pub mod traces;
 
+use std::time::Duration;
+
 use opentelemetry::KeyValue;
 
 use crate::error::CoralRes;
 use crate::error::Error;
 use coral_conf::EnvAssignToml;
 use coral_macro::EnvAssign;
+use coral_macro::Schema;
+use coral_macro::Validate;
 use serde::Deserialize;
+use serde::Serialize;
 
-#[derive(Deserialize, EnvAssign, Debug, Clone)]
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate, Debug, Clone)]
+#[validate(custom = "Self::check_otel")]
 pub struct LogConf {
+    #[validate(dir)]
     dir: Option<String>,
     prefix: Option<String>,
     rotation: Option<String>,
+    /// max level, default info if logging to file and debug otherwise
+    #[validate(one_of("trace", "debug", "info", "warn", "error"))]
+    level: Option<String>,
     otel_endpoint: Option<String>,
+    #[env_assign(split = ",")]
     otel_kvs: Option<Vec<String>>,
+    /// proto, json, logfmt or human
+    #[validate(custom = "Self::check_format")]
+    format: Option<String>,
+    /// export logs to opentelemetry collector
+    #[validate(nested)]
+    otel_logs: Option<OtelLogConf>,
+    /// push metrics to `otel_endpoint`
+    #[validate(nested)]
+    otel_metrics: Option<OtelMetricConf>,
+    /// sampling and reporting of traces
+    #[validate(nested)]
+    otel_traces: Option<OtelTraceConf>,
+}
+
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate, Debug, Clone)]
+pub struct OtelLogConf {
+    /// collector address, use `otel_endpoint` if none
+    endpoint: Option<String>,
+    /// max records in one export request, default 512, at most 65536
+    #[validate(custom = "logs::otlp::check_batch_size")]
+    batch_size: Option<usize>,
+    /// export interval, default 1s, at most 1h
+    #[serde(default, with = "coral_conf::duration")]
+    #[validate(custom = "logs::otlp::check_interval")]
+    interval: Option<Duration>,
+}
+
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate, Debug, Clone)]
+pub struct OtelMetricConf {
+    /// push interval, default 10s
+    #[serde(default, with = "coral_conf::duration")]
+    #[validate(custom = "coral_conf::duration::non_zero")]
+    interval: Option<Duration>,
+}
+
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate, Debug, Clone)]
+pub struct OtelTraceConf {
+    /// ratio of sampled root spans in `[0, 1]`, default 1
+    #[validate(range(min = 0.0, max = 1.0))]
+    sample_ratio: Option<f64>,
+    /// follow sampling decision of upstream, default true
+    parent_based: Option<bool>,
+    /// record unsampled requests, report them on server error
+    always_on_error: Option<bool>,
+    /// report interval of the batch exporter
+    #[serde(default, with = "coral_conf::duration")]
+    #[validate(custom = "coral_conf::duration::non_zero")]
+    report_interval: Option<Duration>,
+}
+
+impl OtelTraceConf {
+    fn sampler(&self) -> traces::Sampler {
+        traces::Sampler::new(
+            self.sample_ratio.unwrap_or(1.0),
+            self.parent_based.unwrap_or(true),
+            self.always_on_error.unwrap_or(false),
+        )
+    }
 }
 
 impl LogConf {
+    /// validate without installing logger, same as [`coral_conf::Validate`]
     pub fn check(&self) -> CoralRes<()> {
-        if let Some(dir) = self.dir.as_ref() {
-            if !std::fs::metadata(dir)?.is_dir() {
-                return Err(Error::InvalidLogDir);
-            }
+        let mut report = coral_conf::Report::default();
+        coral_conf::Validate::validate(self, "", &mut report);
+        Ok(report.into_result()?)
+    }
+
+    fn check_format(format: &str) -> CoralRes<()> {
+        format.parse::<logs::LogFormat>().map(|_| ())
+    }
+
+    /// collector endpoints required by enabled exporters
+    fn check_otel(&self) -> CoralRes<()> {
+        if self.otel_logs.is_some() {
+            self.otel_logs_endpoint()?;
+        }
+        if self.otel_metrics.is_some() && self.otel_endpoint.is_none() {
+            return Err(Error::MissingOtlpEndpoint);
         }
-        self.set_log()?;
         Ok(())
     }
 
-    fn set_log(&self) -> CoralRes<()> {
+    /// install global logger, call after [`LogConf::check`] or validation
+    pub fn init(&self) -> CoralRes<()> {
+        self.init_on(|| {})
+    }
+
+    /// [`LogConf::init`] running `on_start` first on the log writer thread,
+    /// e.g. to pin it to a core
+    pub fn init_on<F: FnOnce() + Send + 'static>(&self, on_start: F) -> CoralRes<()> {
+        let otlp = self.otel_log_sink()?;
+        let level = self.max_level();
         if self.dir.is_some() && self.prefix.is_some() {
             let path = std::path::Path::new(self.dir.as_ref().unwrap());
             let file = path.join(self.prefix.as_ref().unwrap());
@@ -40,24 +130,93 @@ impl LogConf {
                 .write(true)
                 .append(true)
                 .open(file)?;
-            logs::set_proto_logger(fd, log::Level::Info)?;
+            let format = self.log_format()?.unwrap_or(logs::LogFormat::Proto);
+            logs::set_format_logger(fd, level, format, otlp, on_start)?;
         } else {
-            logs::set_stdout_logger()?;
+            let format = self.log_format()?.unwrap_or(logs::LogFormat::Human);
+            logs::set_format_logger(std::io::stdout(), level, format, otlp, on_start)?;
         }
         Ok(())
     }
 
+    /// apply `level` to the installed logger, e.g. on reload
+    pub fn set_level(&self) {
+        log::set_max_level(self.max_level().to_level_filter());
+    }
+
+    fn max_level(&self) -> log::Level {
+        let default = match self.dir.is_some() && self.prefix.is_some() {
+            true => log::Level::Info,
+            false => log::Level::Debug,
+        };
+        self.level
+            .as_ref()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(default)
+    }
+
+    fn otel_logs_endpoint(&self) -> CoralRes<String> {
+        self.otel_logs
+            .as_ref()
+            .and_then(|v| v.endpoint.as_ref())
+            .or(self.otel_endpoint.as_ref())
+            .cloned()
+            .ok_or(Error::MissingOtlpEndpoint)
+    }
+
+    fn otel_log_sink(&self) -> CoralRes<Option<logs::otlp::OtlpLogSink>> {
+        match self.otel_logs.as_ref() {
+            Some(conf) => {
+                let resource = self
+                    .get_otel_kvs()
+                    .into_iter()
+                    .map(|kv| (kv.key.to_string(), kv.value.to_string()))
+                    .collect();
+                Ok(Some(logs::otlp::OtlpLogSink::new(
+                    self.otel_logs_endpoint()?,
+                    resource,
+                    conf.batch_size,
+                    conf.interval,
+                )?))
+            }
+            None => Ok(None),
+        }
+    }
+
+    fn log_format(&self) -> CoralRes<Option<logs::LogFormat>> {
+        self.format
+            .as_ref()
+            .map(|v| v.parse::<logs::LogFormat>())
+            .transpose()
+    }
+
     pub fn set_traces(&self) -> Option<impl FnOnce()> {
         if let Some(endpoint) = self.otel_endpoint.as_ref() {
             let otel_kvs = self.get_otel_kvs();
             let endpoint = endpoint.to_owned();
-            let t = Some(move || traces::otel_trace(endpoint, otel_kvs));
+            let (sampler, interval) = match self.otel_traces.as_ref() {
+                Some(conf) => (conf.sampler(), conf.report_interval),
+                None => (traces::Sampler::default(), None),
+            };
+            let t = Some(move || traces::otel_trace(endpoint, otel_kvs, sampler, interval));
             t
         } else {
             None
         }
     }
 
+    pub fn set_metrics(&self) -> Option<impl FnOnce()> {
+        match (self.otel_endpoint.as_ref(), self.otel_metrics.as_ref()) {
+            (Some(endpoint), Some(conf)) => {
+                let otel_kvs = self.get_otel_kvs();
+                let endpoint = endpoint.to_owned();
+                let interval = conf.interval.unwrap_or(Duration::from_secs(10));
+                Some(move || metrics::otel_metrics(endpoint, otel_kvs, interval))
+            }
+            _ => None,
+        }
+    }
+
     fn get_otel_kvs(&self) -> Vec<KeyValue> {
         let mut kvs = Vec::new();
         if let Some(otel_kvs) = self.otel_kvs.as_ref() {
@@ -1,4 +1,5 @@
 use bytes::BufMut;
+use fastrace::collector::SpanContext;
 use prost::Message;
 
 use super::logs_proto;
@@ -122,6 +123,210 @@ impl super::logger::Convert for logs_proto::Record {
     }
 }
 
+/// trace_id and span_id of the current local parent span, in hex
+fn trace_context() -> Option<(String, String)> {
+    SpanContext::current_local_parent().map(|span| {
+        (
+            format!("{:032x}", span.trace_id.0),
+            format!("{:016x}", span.span_id.0),
+        )
+    })
+}
+
+#[derive(Default)]
+struct JsonValue(serde_json::Value);
+
+impl<'v> log::kv::VisitValue<'v> for JsonValue {
+    fn visit_any(&mut self, value: log::kv::Value) -> Result<(), log::kv::Error> {
+        self.0 = serde_json::Value::String(value.to_string());
+        Ok(())
+    }
+
+    fn visit_null(&mut self) -> Result<(), log::kv::Error> {
+        self.0 = serde_json::Value::Null;
+        Ok(())
+    }
+
+    fn visit_u64(&mut self, value: u64) -> Result<(), log::kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_i64(&mut self, value: i64) -> Result<(), log::kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_u128(&mut self, value: u128) -> Result<(), log::kv::Error> {
+        self.0 = match u64::try_from(value) {
+            Ok(v) => v.into(),
+            Err(_) => value.to_string().into(),
+        };
+        Ok(())
+    }
+
+    fn visit_i128(&mut self, value: i128) -> Result<(), log::kv::Error> {
+        self.0 = match i64::try_from(value) {
+            Ok(v) => v.into(),
+            Err(_) => value.to_string().into(),
+        };
+        Ok(())
+    }
+
+    fn visit_f64(&mut self, value: f64) -> Result<(), log::kv::Error> {
+        self.0 = serde_json::Number::from_f64(value)
+            .map(serde_json::Value::Number)
+            .unwrap_or_else(|| value.to_string().into());
+        Ok(())
+    }
+
+    fn visit_bool(&mut self, value: bool) -> Result<(), log::kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+
+    fn visit_str(&mut self, value: &str) -> Result<(), log::kv::Error> {
+        self.0 = value.into();
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+struct JsonFields(serde_json::Map<String, serde_json::Value>);
+
+impl<'kvs> log::kv::VisitSource<'kvs> for JsonFields {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        let mut val = JsonValue::default();
+        value.visit(&mut val)?;
+        self.0.insert(key.to_string(), val.0);
+        Ok(())
+    }
+}
+
+/// newline-delimited json, one object per record
+#[derive(Default)]
+pub struct Json;
+
+impl super::logger::Convert for Json {
+    fn to_bytes(&mut self, record: &log::Record) -> CoralRes<Vec<u8>> {
+        let current = std::thread::current();
+        let mut fields = JsonFields::default();
+        record.key_values().visit(&mut fields)?;
+        let mut obj = fields.0;
+        obj.insert("timestamp".into(), chrono::Local::now().to_rfc3339().into());
+        obj.insert("level".into(), record.level().as_str().into());
+        obj.insert("target".into(), record.target().into());
+        if let Some(name) = current.name() {
+            obj.insert("thread_name".into(), name.into());
+        }
+        if let Some(file) = record.file() {
+            obj.insert("file".into(), file.into());
+        }
+        if let Some(line) = record.line() {
+            obj.insert("line".into(), line.into());
+        }
+        obj.insert("msg".into(), record.args().to_string().into());
+        if let Some((trace_id, span_id)) = trace_context() {
+            obj.insert("trace_id".into(), trace_id.into());
+            obj.insert("span_id".into(), span_id.into());
+        }
+        let mut buf = serde_json::to_vec(&obj)?;
+        buf.push(b'\n');
+        Ok(buf)
+    }
+}
+
+/// append `key=value` to logfmt line, quote value if necessary
+fn logfmt_pair(buf: &mut String, key: &str, val: &str) {
+    if !buf.is_empty() {
+        buf.push(' ');
+    }
+    buf.push_str(key);
+    buf.push('=');
+    let need_quote = val.is_empty()
+        || val
+            .chars()
+            .any(|c| c <= ' ' || c == '=' || c == '"' || c == '\\');
+    if !need_quote {
+        buf.push_str(val);
+        return;
+    }
+    buf.push('"');
+    for c in val.chars() {
+        match c {
+            '"' => buf.push_str("\\\""),
+            '\\' => buf.push_str("\\\\"),
+            '\n' => buf.push_str("\\n"),
+            '\r' => buf.push_str("\\r"),
+            '\t' => buf.push_str("\\t"),
+            _ => buf.push(c),
+        }
+    }
+    buf.push('"');
+}
+
+#[derive(Default)]
+struct LogfmtFields {
+    line: String,
+    trace_id: bool,
+}
+
+impl<'kvs> log::kv::VisitSource<'kvs> for LogfmtFields {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        // replaced by the hex trace context below
+        if self.trace_id && key.as_str() == "trace_id" {
+            return Ok(());
+        }
+        logfmt_pair(&mut self.line, key.as_str(), &value.to_string());
+        Ok(())
+    }
+}
+
+/// logfmt, one line of `key=value` pairs per record
+#[derive(Default)]
+pub struct Logfmt;
+
+impl super::logger::Convert for Logfmt {
+    fn to_bytes(&mut self, record: &log::Record) -> CoralRes<Vec<u8>> {
+        let current = std::thread::current();
+        let context = trace_context();
+        let mut fields = LogfmtFields {
+            line: String::with_capacity(256),
+            trace_id: context.is_some(),
+        };
+        let line = &mut fields.line;
+        logfmt_pair(line, "timestamp", &chrono::Local::now().to_rfc3339());
+        logfmt_pair(line, "level", record.level().as_str());
+        logfmt_pair(line, "target", record.target());
+        if let Some(name) = current.name() {
+            logfmt_pair(line, "thread_name", name);
+        }
+        if let Some(file) = record.file() {
+            logfmt_pair(line, "file", file);
+        }
+        if let Some(ln) = record.line() {
+            logfmt_pair(line, "line", &ln.to_string());
+        }
+        logfmt_pair(line, "msg", &record.args().to_string());
+        record.key_values().visit(&mut fields)?;
+        let mut line = fields.line;
+        if let Some((trace_id, span_id)) = context {
+            logfmt_pair(&mut line, "trace_id", &trace_id);
+            logfmt_pair(&mut line, "span_id", &span_id);
+        }
+        line.push('\n');
+        Ok(line.into_bytes())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use bytes::BufMut;
@@ -148,4 +353,50 @@ mod test {
         let dec_record = super::logs_proto::Record::decode(&b[4..dec_len + 4]).unwrap();
         assert_eq!(dec_record.thread_name, "thread name");
     }
+
+    #[test]
+    fn test_json() {
+        use crate::logs::logger::Convert;
+        let kvs: [(&str, log::kv::Value); 2] = [("name", "coral".into()), ("age", 11u64.into())];
+        let data = super::Json
+            .to_bytes(
+                &log::Record::builder()
+                    .args(format_args!("hello {}", "json"))
+                    .level(log::Level::Info)
+                    .target("coral")
+                    .key_values(&kvs)
+                    .build(),
+            )
+            .unwrap();
+        assert_eq!(data.last(), Some(&b'\n'));
+        let obj: serde_json::Value = serde_json::from_slice(&data).unwrap();
+        assert_eq!(obj["msg"], "hello json");
+        assert_eq!(obj["level"], "INFO");
+        assert_eq!(obj["target"], "coral");
+        assert_eq!(obj["name"], "coral");
+        assert_eq!(obj["age"], 11);
+    }
+
+    #[test]
+    fn test_logfmt() {
+        use crate::logs::logger::Convert;
+        let kvs: [(&str, log::kv::Value); 2] =
+            [("name", "coral".into()), ("path", "a \"b\"".into())];
+        let data = super::Logfmt
+            .to_bytes(
+                &log::Record::builder()
+                    .args(format_args!("hello {}", "logfmt"))
+                    .level(log::Level::Warn)
+                    .target("coral")
+                    .key_values(&kvs)
+                    .build(),
+            )
+            .unwrap();
+        let line = String::from_utf8(data).unwrap();
+        assert!(line.ends_with('\n'));
+        assert!(line.contains(" level=WARN "));
+        assert!(line.contains(" msg=\"hello logfmt\""));
+        assert!(line.contains(" name=coral"));
+        assert!(line.contains(r#" path="a \"b\"""#));
+    }
 }
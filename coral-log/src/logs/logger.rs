@@ -5,11 +5,13 @@ use crossbeam_channel::Sender;
 use log::Level;
 use log::Log;
 
+use super::otlp::OtlpLogSink;
 use crate::error::CoralRes;
 
 pub struct Logger<C> {
     level: Level,
     tx: Sender<Vec<u8>>,
+    otlp: Option<OtlpLogSink>,
     _pd: PhantomData<C>,
 }
 
@@ -17,14 +19,30 @@ impl<C> Logger<C> {
     pub fn new<W: std::io::Write + Send + 'static>(
         level: Level,
         cap: Option<usize>,
-        mut writer: W,
+        writer: W,
     ) -> CoralRes<Self> {
+        Self::new_on(level, cap, writer, || {})
+    }
+
+    /// [`Logger::new`] running `on_start` first on the writer thread, e.g. to
+    /// pin it to a core
+    pub fn new_on<W, F>(
+        level: Level,
+        cap: Option<usize>,
+        mut writer: W,
+        on_start: F,
+    ) -> CoralRes<Self>
+    where
+        W: std::io::Write + Send + 'static,
+        F: FnOnce() + Send + 'static,
+    {
         let cap = match cap {
             Some(c) => c,
             None => 4096,
         };
         let (tx, rx) = bounded::<Vec<u8>>(cap);
         std::thread::spawn(move || {
+            on_start();
             loop {
                 match rx.recv() {
                     Ok(chunk) => {
@@ -41,9 +59,16 @@ impl<C> Logger<C> {
         Ok(Self {
             level,
             tx,
+            otlp: None,
             _pd: PhantomData,
         })
     }
+
+    /// also export records to opentelemetry collector
+    pub fn with_otlp(mut self, sink: OtlpLogSink) -> Self {
+        self.otlp = Some(sink);
+        self
+    }
 }
 
 pub trait Convert {
@@ -85,6 +110,9 @@ where C: Convert + Default + Send + Sync
                 }
                 Err(e) => eprintln!("failed to convert log to bytes {:?}", e),
             }
+            if let Some(otlp) = self.otlp.as_ref() {
+                otlp.export(record);
+            }
         }
     }
 
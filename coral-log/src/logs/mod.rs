@@ -1,7 +1,13 @@
+use std::str::FromStr;
+
 use crate::error::CoralRes;
+use crate::error::Error;
 
 mod format;
 pub mod logger;
+pub mod otlp;
+pub use format::Json;
+pub use format::Logfmt;
 pub use logs_proto::Record;
 
 #[cfg(debug_assertions)]
@@ -11,23 +17,87 @@ pub(super) mod logs_proto {
     include!(concat!(".", "/logs_proto.rs"));
 }
 
+/// output format of log records
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// length-prefixed protobuf `Record`
+    Proto,
+    /// newline-delimited json
+    Json,
+    /// `key=value` pairs per line
+    Logfmt,
+    /// debug print for terminal
+    Human,
+}
+
+impl FromStr for LogFormat {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "proto" => Ok(Self::Proto),
+            "json" => Ok(Self::Json),
+            "logfmt" => Ok(Self::Logfmt),
+            "human" => Ok(Self::Human),
+            _ => Err(Error::InvalidLogFormat(s.to_owned())),
+        }
+    }
+}
+
 pub fn set_logger<C>(coral: logger::Logger<C>) -> CoralRes<()>
 where C: logger::Convert + Default + Send + Sync + 'static {
     log::set_boxed_logger(Box::new(coral))?;
     Ok(())
 }
 
-pub fn set_proto_logger(f: std::fs::File, level: log::Level) -> CoralRes<()> {
-    let global_log = logger::Logger::<logs_proto::Record>::new(level, None, f)?;
-    log::set_boxed_logger(Box::new(global_log))?;
+fn set_sink_logger<C, W, F>(writer: W, otlp: Option<otlp::OtlpLogSink>, on_start: F) -> CoralRes<()>
+where
+    C: logger::Convert + Default + Send + Sync + 'static,
+    W: std::io::Write + Send + 'static,
+    F: FnOnce() + Send + 'static,
+{
+    // filtered by `log::max_level`, so that the level can be changed later
+    let mut coral = logger::Logger::<C>::new_on(log::Level::Trace, None, writer, on_start)?;
+    if let Some(sink) = otlp {
+        coral = coral.with_otlp(sink);
+    }
+    set_logger(coral)
+}
+
+/// set global logger writing records in `format` to `writer`, and optionally
+/// exporting them to opentelemetry collector, `on_start` runs first on the
+/// writer thread
+pub fn set_format_logger<W, F>(
+    writer: W,
+    level: log::Level,
+    format: LogFormat,
+    otlp: Option<otlp::OtlpLogSink>,
+    on_start: F,
+) -> CoralRes<()>
+where
+    W: std::io::Write + Send + 'static,
+    F: FnOnce() + Send + 'static,
+{
+    match format {
+        LogFormat::Proto => set_sink_logger::<logs_proto::Record, W, F>(writer, otlp, on_start)?,
+        LogFormat::Json => set_sink_logger::<Json, W, F>(writer, otlp, on_start)?,
+        LogFormat::Logfmt => set_sink_logger::<Logfmt, W, F>(writer, otlp, on_start)?,
+        LogFormat::Human => set_sink_logger::<logger::Stdout, W, F>(writer, otlp, on_start)?,
+    }
     log::set_max_level(level.to_level_filter());
     Ok(())
 }
 
+pub fn set_proto_logger(f: std::fs::File, level: log::Level) -> CoralRes<()> {
+    set_format_logger(f, level, LogFormat::Proto, None, || {})
+}
+
 pub fn set_stdout_logger() -> CoralRes<()> {
-    let global_log =
-        logger::Logger::<logger::Stdout>::new(log::Level::Debug, None, std::io::stdout())?;
-    log::set_boxed_logger(Box::new(global_log))?;
-    log::set_max_level(log::LevelFilter::Debug);
-    Ok(())
+    set_format_logger(
+        std::io::stdout(),
+        log::Level::Debug,
+        LogFormat::Human,
+        None,
+        || {},
+    )
 }
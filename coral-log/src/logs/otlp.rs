@@ -0,0 +1,297 @@
+//! export log records to opentelemetry collector
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use fastrace::collector::SpanContext;
+use opentelemetry_proto::tonic::collector::logs::v1::logs_service_client::LogsServiceClient;
+use opentelemetry_proto::tonic::collector::logs::v1::ExportLogsServiceRequest;
+use opentelemetry_proto::tonic::common::v1::any_value;
+use opentelemetry_proto::tonic::common::v1::AnyValue;
+use opentelemetry_proto::tonic::common::v1::InstrumentationScope;
+use opentelemetry_proto::tonic::common::v1::KeyValue;
+use opentelemetry_proto::tonic::logs::v1::LogRecord;
+use opentelemetry_proto::tonic::logs::v1::ResourceLogs;
+use opentelemetry_proto::tonic::logs::v1::ScopeLogs;
+use opentelemetry_proto::tonic::logs::v1::SeverityNumber;
+use opentelemetry_proto::tonic::resource::v1::Resource;
+use tokio::sync::mpsc;
+
+use crate::error::CoralRes;
+use crate::error::Error;
+
+/// default max records in one export request
+pub const DEFAULT_BATCH_SIZE: usize = 512;
+
+/// default interval of exporting
+pub const DEFAULT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// max records in one export request, the queue holds 8 batches
+pub const MAX_BATCH_SIZE: usize = 65536;
+
+/// max interval of exporting
+pub const MAX_INTERVAL: Duration = Duration::from_secs(3600);
+
+/// `batch_size` in `[1, MAX_BATCH_SIZE]`
+pub fn check_batch_size(batch_size: &usize) -> CoralRes<()> {
+    match (1..=MAX_BATCH_SIZE).contains(batch_size) {
+        true => Ok(()),
+        false => Err(Error::InvalidBatchSize(*batch_size)),
+    }
+}
+
+/// `interval` in `(0, MAX_INTERVAL]`
+pub fn check_interval(interval: &Duration) -> CoralRes<()> {
+    match !interval.is_zero() && *interval <= MAX_INTERVAL {
+        true => Ok(()),
+        false => Err(Error::InvalidInterval(*interval)),
+    }
+}
+
+fn any_value(value: any_value::Value) -> Option<AnyValue> {
+    Some(AnyValue { value: Some(value) })
+}
+
+#[derive(Default)]
+struct OtlpValue(Option<any_value::Value>);
+
+impl<'v> log::kv::VisitValue<'v> for OtlpValue {
+    fn visit_any(&mut self, value: log::kv::Value) -> Result<(), log::kv::Error> {
+        self.0 = Some(any_value::Value::StringValue(value.to_string()));
+        Ok(())
+    }
+
+    fn visit_u64(&mut self, value: u64) -> Result<(), log::kv::Error> {
+        self.0 = match i64::try_from(value) {
+            Ok(v) => Some(any_value::Value::IntValue(v)),
+            Err(_) => Some(any_value::Value::StringValue(value.to_string())),
+        };
+        Ok(())
+    }
+
+    fn visit_i64(&mut self, value: i64) -> Result<(), log::kv::Error> {
+        self.0 = Some(any_value::Value::IntValue(value));
+        Ok(())
+    }
+
+    fn visit_f64(&mut self, value: f64) -> Result<(), log::kv::Error> {
+        self.0 = Some(any_value::Value::DoubleValue(value));
+        Ok(())
+    }
+
+    fn visit_bool(&mut self, value: bool) -> Result<(), log::kv::Error> {
+        self.0 = Some(any_value::Value::BoolValue(value));
+        Ok(())
+    }
+
+    fn visit_str(&mut self, value: &str) -> Result<(), log::kv::Error> {
+        self.0 = Some(any_value::Value::StringValue(value.to_owned()));
+        Ok(())
+    }
+}
+
+struct OtlpAttributes<'a> {
+    attributes: &'a mut Vec<KeyValue>,
+    trace_id: bool,
+}
+
+impl<'kvs> log::kv::VisitSource<'kvs> for OtlpAttributes<'_> {
+    fn visit_pair(
+        &mut self,
+        key: log::kv::Key<'kvs>,
+        value: log::kv::Value<'kvs>,
+    ) -> Result<(), log::kv::Error> {
+        // trace_id is carried by the LogRecord itself
+        if self.trace_id && key.as_str() == "trace_id" {
+            return Ok(());
+        }
+        let mut val = OtlpValue::default();
+        value.visit(&mut val)?;
+        self.attributes.push(KeyValue {
+            key: key.to_string(),
+            value: val.0.and_then(any_value),
+        });
+        Ok(())
+    }
+}
+
+fn severity(level: log::Level) -> SeverityNumber {
+    match level {
+        log::Level::Error => SeverityNumber::Error,
+        log::Level::Warn => SeverityNumber::Warn,
+        log::Level::Info => SeverityNumber::Info,
+        log::Level::Debug => SeverityNumber::Debug,
+        log::Level::Trace => SeverityNumber::Trace,
+    }
+}
+
+fn str_attr(key: &str, val: String) -> KeyValue {
+    KeyValue {
+        key: key.to_owned(),
+        value: any_value(any_value::Value::StringValue(val)),
+    }
+}
+
+/// otlp resource from key value pairs
+pub(crate) fn resource(kvs: Vec<(String, String)>) -> Resource {
+    Resource {
+        attributes: kvs.into_iter().map(|(k, v)| str_attr(&k, v)).collect(),
+        dropped_attributes_count: 0,
+    }
+}
+
+/// convert `log::Record` to otlp `LogRecord` with current span context
+pub fn log_record(record: &log::Record) -> CoralRes<LogRecord> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    let mut attributes = vec![str_attr("log.target", record.target().to_owned())];
+    if let Some(name) = std::thread::current().name() {
+        attributes.push(str_attr("thread.name", name.to_owned()));
+    }
+    if let Some(file) = record.file() {
+        attributes.push(str_attr("code.filepath", file.to_owned()));
+    }
+    if let Some(line) = record.line() {
+        attributes.push(KeyValue {
+            key: "code.lineno".to_owned(),
+            value: any_value(any_value::Value::IntValue(line as i64)),
+        });
+    }
+    let context = SpanContext::current_local_parent();
+    record.key_values().visit(&mut OtlpAttributes {
+        attributes: &mut attributes,
+        trace_id: context.is_some(),
+    })?;
+    let mut otlp_record = LogRecord {
+        time_unix_nano: now,
+        observed_time_unix_nano: now,
+        severity_number: severity(record.level()).into(),
+        severity_text: record.level().as_str().to_owned(),
+        body: any_value(any_value::Value::StringValue(record.args().to_string())),
+        attributes,
+        ..Default::default()
+    };
+    if let Some(span) = context {
+        otlp_record.trace_id = span.trace_id.0.to_be_bytes().to_vec();
+        otlp_record.span_id = span.span_id.0.to_be_bytes().to_vec();
+    }
+    Ok(otlp_record)
+}
+
+/// Batch log records and export them to collector over grpc
+///
+/// Records are sent to a dedicated thread running its own runtime, so the sink
+/// can be created before the application runtime. Records dropped on a full
+/// queue are counted and reported by the export loop once per interval.
+#[derive(Clone)]
+pub struct OtlpLogSink {
+    tx: mpsc::Sender<LogRecord>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl OtlpLogSink {
+    pub fn new(
+        endpoint: String,
+        resource: Vec<(String, String)>,
+        batch_size: Option<usize>,
+        interval: Option<Duration>,
+    ) -> CoralRes<Self> {
+        let batch_size = batch_size.unwrap_or(DEFAULT_BATCH_SIZE);
+        let interval = interval.unwrap_or(DEFAULT_INTERVAL);
+        check_batch_size(&batch_size)?;
+        check_interval(&interval)?;
+        let channel =
+            tonic::transport::Endpoint::from_shared(endpoint)?.timeout(Duration::from_secs(10));
+        let resource = self::resource(resource);
+        let (tx, rx) = mpsc::channel(batch_size * 8);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let counter = dropped.clone();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()?;
+        std::thread::Builder::new()
+            .name(String::from("coral-otlp-log"))
+            .spawn(move || {
+                rt.block_on(async move {
+                    let client = LogsServiceClient::new(channel.connect_lazy());
+                    export_loop(client, resource, rx, counter, batch_size, interval).await;
+                })
+            })?;
+        Ok(Self { tx, dropped })
+    }
+
+    /// queue a record, dropped if the queue is full
+    pub fn export(&self, record: &log::Record) {
+        match log_record(record) {
+            Ok(r) => {
+                if self.tx.try_send(r).is_err() {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            Err(e) => eprintln!("failed to convert log to otlp record {:?}", e),
+        }
+    }
+}
+
+async fn export_loop(
+    mut client: LogsServiceClient<tonic::transport::Channel>,
+    resource: Resource,
+    mut rx: mpsc::Receiver<LogRecord>,
+    dropped: Arc<AtomicU64>,
+    batch_size: usize,
+    interval: Duration,
+) {
+    let scope = InstrumentationScope {
+        name: env!("CARGO_PKG_NAME").to_owned(),
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        ..Default::default()
+    };
+    let mut batch = Vec::with_capacity(batch_size);
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    loop {
+        let (closed, tick) = tokio::select! {
+            biased;
+            record = rx.recv() => match record {
+                Some(r) => {
+                    batch.push(r);
+                    if batch.len() < batch_size {
+                        continue;
+                    }
+                    (false, false)
+                }
+                None => (true, true),
+            },
+            _ = ticker.tick() => (false, true),
+        };
+        if !batch.is_empty() {
+            let req = ExportLogsServiceRequest {
+                resource_logs: vec![ResourceLogs {
+                    resource: Some(resource.clone()),
+                    scope_logs: vec![ScopeLogs {
+                        scope: Some(scope.clone()),
+                        log_records: std::mem::take(&mut batch),
+                        schema_url: String::new(),
+                    }],
+                    schema_url: String::new(),
+                }],
+            };
+            if let Err(e) = client.export(req).await {
+                eprintln!("failed to export otlp logs {:?}", e);
+            }
+        }
+        if tick {
+            let count = dropped.swap(0, Ordering::Relaxed);
+            if count > 0 {
+                eprintln!("dropped {} otlp log records on full queue", count);
+            }
+        }
+        if closed {
+            break;
+        }
+    }
+}
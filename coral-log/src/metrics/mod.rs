@@ -1 +1,415 @@
 //! Metrics
+//!
+//! Counters, gauges and histograms with labels, kept in a [`Registry`]. The
+//! global registry is exported in prometheus text format by [`encode`] and can
+//! be pushed to opentelemetry collector by [`otel_metrics`].
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::OnceLock;
+use std::sync::RwLock;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use crate::error::CoralRes;
+use crate::error::Error;
+
+mod otlp;
+mod prometheus;
+
+pub use otlp::otel_metrics;
+
+/// label pairs sorted by key
+pub type Labels = Vec<(String, String)>;
+
+/// default histogram buckets, in seconds for latency
+pub static DEFAULT_BUCKETS: [f64; 11] = [
+    0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0,
+];
+
+fn sort_labels(pairs: &[(&str, &str)]) -> Labels {
+    let mut labels: Labels = pairs
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    labels.sort_by(|a, b| a.0.cmp(&b.0));
+    labels
+}
+
+fn add_f64(cell: &AtomicU64, val: f64) {
+    let mut cur = cell.load(Ordering::Relaxed);
+    loop {
+        let new = (f64::from_bits(cur) + val).to_bits();
+        match cell.compare_exchange_weak(cur, new, Ordering::AcqRel, Ordering::Relaxed) {
+            Ok(_) => break,
+            Err(v) => cur = v,
+        }
+    }
+}
+
+/// monotonic counter of one label set
+#[derive(Default)]
+pub struct CounterCell(AtomicU64);
+
+impl CounterCell {
+    pub fn inc(&self) {
+        self.inc_by(1);
+    }
+
+    pub fn inc_by(&self, val: u64) {
+        self.add(val as f64);
+    }
+
+    /// add `val`, ignored if negative
+    pub fn add(&self, val: f64) {
+        if val > 0.0 {
+            add_f64(&self.0, val);
+        }
+    }
+
+    /// set to a total counted elsewhere, ignored if less than the current
+    pub fn set_total(&self, val: f64) {
+        let mut cur = self.0.load(Ordering::Relaxed);
+        while f64::from_bits(cur) < val {
+            match self.0.compare_exchange_weak(
+                cur,
+                val.to_bits(),
+                Ordering::AcqRel,
+                Ordering::Relaxed,
+            ) {
+                Ok(_) => break,
+                Err(v) => cur = v,
+            }
+        }
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+/// gauge of one label set
+#[derive(Default)]
+pub struct GaugeCell(AtomicU64);
+
+impl GaugeCell {
+    pub fn set(&self, val: f64) {
+        self.0.store(val.to_bits(), Ordering::Relaxed);
+    }
+
+    pub fn add(&self, val: f64) {
+        add_f64(&self.0, val);
+    }
+
+    pub fn inc(&self) {
+        self.add(1.0);
+    }
+
+    pub fn dec(&self) {
+        self.add(-1.0);
+    }
+
+    pub fn get(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+}
+
+/// histogram of one label set
+pub struct HistogramCell {
+    bounds: Arc<[f64]>,
+    buckets: Vec<AtomicU64>,
+    count: AtomicU64,
+    sum: AtomicU64,
+}
+
+impl HistogramCell {
+    fn new(bounds: Arc<[f64]>) -> Self {
+        Self {
+            buckets: (0..bounds.len() + 1).map(|_| AtomicU64::new(0)).collect(),
+            bounds,
+            count: AtomicU64::new(0),
+            sum: AtomicU64::new(0f64.to_bits()),
+        }
+    }
+
+    pub fn record(&self, val: f64) {
+        let ix = self
+            .bounds
+            .iter()
+            .position(|b| val <= *b)
+            .unwrap_or(self.bounds.len());
+        self.buckets[ix].fetch_add(1, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+        add_f64(&self.sum, val);
+    }
+
+    pub fn bounds(&self) -> &[f64] {
+        &self.bounds
+    }
+
+    /// count of each bucket, the last one is `+Inf`
+    pub fn bucket_counts(&self) -> Vec<u64> {
+        self.buckets
+            .iter()
+            .map(|v| v.load(Ordering::Relaxed))
+            .collect()
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count.load(Ordering::Relaxed)
+    }
+
+    pub fn sum(&self) -> f64 {
+        f64::from_bits(self.sum.load(Ordering::Relaxed))
+    }
+}
+
+/// metric with the same name and different labels
+pub struct Family<M> {
+    name: String,
+    help: String,
+    series: RwLock<HashMap<Labels, Arc<M>>>,
+    make: Box<dyn Fn() -> M + Send + Sync>,
+}
+
+impl<M> Family<M> {
+    fn new(name: &str, help: &str, make: Box<dyn Fn() -> M + Send + Sync>) -> Self {
+        Self {
+            name: name.to_owned(),
+            help: help.to_owned(),
+            series: RwLock::new(HashMap::new()),
+            make,
+        }
+    }
+
+    /// get or create the metric of `labels`, keep it to skip the lookup
+    pub fn with(&self, labels: &[(&str, &str)]) -> Arc<M> {
+        let key = sort_labels(labels);
+        if let Some(m) = self.series.read().unwrap().get(&key) {
+            return m.clone();
+        }
+        self.series
+            .write()
+            .unwrap()
+            .entry(key)
+            .or_insert_with(|| Arc::new((self.make)()))
+            .clone()
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn help(&self) -> &str {
+        &self.help
+    }
+
+    /// snapshot of all label sets
+    pub fn series(&self) -> Vec<(Labels, Arc<M>)> {
+        let mut series: Vec<(Labels, Arc<M>)> = self
+            .series
+            .read()
+            .unwrap()
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        series.sort_by(|a, b| a.0.cmp(&b.0));
+        series
+    }
+}
+
+#[derive(Clone)]
+pub struct Counter(Arc<Family<CounterCell>>);
+
+impl std::ops::Deref for Counter {
+    type Target = Family<CounterCell>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Counter {
+    pub fn inc(&self, labels: &[(&str, &str)]) {
+        self.with(labels).inc();
+    }
+
+    pub fn inc_by(&self, val: u64, labels: &[(&str, &str)]) {
+        self.with(labels).inc_by(val);
+    }
+
+    pub fn set_total(&self, val: f64, labels: &[(&str, &str)]) {
+        self.with(labels).set_total(val);
+    }
+}
+
+#[derive(Clone)]
+pub struct Gauge(Arc<Family<GaugeCell>>);
+
+impl std::ops::Deref for Gauge {
+    type Target = Family<GaugeCell>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Gauge {
+    pub fn set(&self, val: f64, labels: &[(&str, &str)]) {
+        self.with(labels).set(val);
+    }
+
+    pub fn add(&self, val: f64, labels: &[(&str, &str)]) {
+        self.with(labels).add(val);
+    }
+}
+
+#[derive(Clone)]
+pub struct Histogram(Arc<Family<HistogramCell>>);
+
+impl std::ops::Deref for Histogram {
+    type Target = Family<HistogramCell>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl Histogram {
+    pub fn record(&self, val: f64, labels: &[(&str, &str)]) {
+        self.with(labels).record(val);
+    }
+}
+
+#[derive(Clone)]
+pub enum Metric {
+    Counter(Counter),
+    Gauge(Gauge),
+    Histogram(Histogram),
+}
+
+/// Registry of metrics, keyed by name
+pub struct Registry {
+    metrics: RwLock<BTreeMap<String, Metric>>,
+    start_time: u64,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        Self {
+            metrics: RwLock::new(BTreeMap::new()),
+            start_time: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos() as u64,
+        }
+    }
+}
+
+impl Registry {
+    fn get_or_insert<F>(&self, name: &str, make: F) -> Metric
+    where F: FnOnce() -> Metric {
+        if let Some(m) = self.metrics.read().unwrap().get(name) {
+            return m.clone();
+        }
+        self.metrics
+            .write()
+            .unwrap()
+            .entry(name.to_owned())
+            .or_insert_with(make)
+            .clone()
+    }
+
+    /// get or register a counter
+    pub fn counter(&self, name: &str, help: &str) -> CoralRes<Counter> {
+        let metric = self.get_or_insert(name, || {
+            Metric::Counter(Counter(Arc::new(Family::new(
+                name,
+                help,
+                Box::new(CounterCell::default),
+            ))))
+        });
+        match metric {
+            Metric::Counter(c) => Ok(c),
+            _ => Err(Error::MetricConflict(name.to_owned())),
+        }
+    }
+
+    /// get or register a gauge
+    pub fn gauge(&self, name: &str, help: &str) -> CoralRes<Gauge> {
+        let metric = self.get_or_insert(name, || {
+            Metric::Gauge(Gauge(Arc::new(Family::new(
+                name,
+                help,
+                Box::new(GaugeCell::default),
+            ))))
+        });
+        match metric {
+            Metric::Gauge(g) => Ok(g),
+            _ => Err(Error::MetricConflict(name.to_owned())),
+        }
+    }
+
+    /// get or register a histogram with upper bounds of buckets, use
+    /// [`DEFAULT_BUCKETS`] if `buckets` is none
+    pub fn histogram(
+        &self,
+        name: &str,
+        help: &str,
+        buckets: Option<&[f64]>,
+    ) -> CoralRes<Histogram> {
+        let mut bounds = buckets.unwrap_or(&DEFAULT_BUCKETS).to_vec();
+        bounds.sort_by(|a, b| a.total_cmp(b));
+        bounds.dedup();
+        let bounds: Arc<[f64]> = bounds.into();
+        let metric = self.get_or_insert(name, || {
+            Metric::Histogram(Histogram(Arc::new(Family::new(
+                name,
+                help,
+                Box::new(move || HistogramCell::new(bounds.clone())),
+            ))))
+        });
+        match metric {
+            Metric::Histogram(h) => Ok(h),
+            _ => Err(Error::MetricConflict(name.to_owned())),
+        }
+    }
+
+    /// snapshot of all metrics sorted by name
+    pub fn metrics(&self) -> Vec<Metric> {
+        self.metrics.read().unwrap().values().cloned().collect()
+    }
+
+    /// render all metrics in prometheus text format
+    pub fn encode(&self) -> String {
+        prometheus::encode(&self.metrics())
+    }
+}
+
+/// global registry
+pub fn registry() -> &'static Registry {
+    static REGISTRY: OnceLock<Registry> = OnceLock::new();
+    REGISTRY.get_or_init(Registry::default)
+}
+
+pub fn counter(name: &str, help: &str) -> CoralRes<Counter> {
+    registry().counter(name, help)
+}
+
+pub fn gauge(name: &str, help: &str) -> CoralRes<Gauge> {
+    registry().gauge(name, help)
+}
+
+pub fn histogram(name: &str, help: &str, buckets: Option<&[f64]>) -> CoralRes<Histogram> {
+    registry().histogram(name, help, buckets)
+}
+
+/// render global registry in prometheus text format
+pub fn encode() -> String {
+    registry().encode()
+}
+
+/// content type of [`encode`]
+pub static PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
@@ -0,0 +1,161 @@
+//! push metrics to opentelemetry collector
+use std::time::Duration;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use opentelemetry::KeyValue;
+use opentelemetry_proto::tonic::collector::metrics::v1::metrics_service_client::MetricsServiceClient;
+use opentelemetry_proto::tonic::collector::metrics::v1::ExportMetricsServiceRequest;
+use opentelemetry_proto::tonic::common::v1::any_value;
+use opentelemetry_proto::tonic::common::v1::AnyValue;
+use opentelemetry_proto::tonic::common::v1::InstrumentationScope;
+use opentelemetry_proto::tonic::metrics::v1 as proto;
+use opentelemetry_proto::tonic::metrics::v1::number_data_point;
+use opentelemetry_proto::tonic::resource::v1::Resource;
+
+use super::Labels;
+use super::Metric;
+use super::Registry;
+
+fn attributes(labels: Labels) -> Vec<opentelemetry_proto::tonic::common::v1::KeyValue> {
+    labels
+        .into_iter()
+        .map(|(k, v)| opentelemetry_proto::tonic::common::v1::KeyValue {
+            key: k,
+            value: Some(AnyValue {
+                value: Some(any_value::Value::StringValue(v)),
+            }),
+        })
+        .collect()
+}
+
+fn to_proto(registry: &Registry) -> Vec<proto::Metric> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_nanos() as u64;
+    let start = registry.start_time;
+    let number_point = |labels: Labels, value: number_data_point::Value| proto::NumberDataPoint {
+        attributes: attributes(labels),
+        start_time_unix_nano: start,
+        time_unix_nano: now,
+        value: Some(value),
+        ..Default::default()
+    };
+    let mut metrics = Vec::new();
+    for metric in registry.metrics() {
+        let (name, help, data) = match metric {
+            Metric::Counter(c) => {
+                let data_points = c
+                    .series()
+                    .into_iter()
+                    .map(|(labels, cell)| {
+                        number_point(labels, number_data_point::Value::AsDouble(cell.get()))
+                    })
+                    .collect();
+                let data = proto::metric::Data::Sum(proto::Sum {
+                    data_points,
+                    aggregation_temporality: proto::AggregationTemporality::Cumulative.into(),
+                    is_monotonic: true,
+                });
+                (c.name().to_owned(), c.help().to_owned(), data)
+            }
+            Metric::Gauge(g) => {
+                let data_points = g
+                    .series()
+                    .into_iter()
+                    .map(|(labels, cell)| {
+                        number_point(labels, number_data_point::Value::AsDouble(cell.get()))
+                    })
+                    .collect();
+                let data = proto::metric::Data::Gauge(proto::Gauge { data_points });
+                (g.name().to_owned(), g.help().to_owned(), data)
+            }
+            Metric::Histogram(h) => {
+                let data_points = h
+                    .series()
+                    .into_iter()
+                    .map(|(labels, cell)| proto::HistogramDataPoint {
+                        attributes: attributes(labels),
+                        start_time_unix_nano: start,
+                        time_unix_nano: now,
+                        count: cell.count(),
+                        sum: Some(cell.sum()),
+                        bucket_counts: cell.bucket_counts(),
+                        explicit_bounds: cell.bounds().to_vec(),
+                        ..Default::default()
+                    })
+                    .collect();
+                let data = proto::metric::Data::Histogram(proto::Histogram {
+                    data_points,
+                    aggregation_temporality: proto::AggregationTemporality::Cumulative.into(),
+                });
+                (h.name().to_owned(), h.help().to_owned(), data)
+            }
+        };
+        metrics.push(proto::Metric {
+            name,
+            description: help,
+            data: Some(data),
+            ..Default::default()
+        });
+    }
+    metrics
+}
+
+async fn push_loop(
+    mut client: MetricsServiceClient<tonic::transport::Channel>,
+    resource: Resource,
+    interval: Duration,
+) {
+    let scope = InstrumentationScope {
+        name: env!("CARGO_PKG_NAME").to_owned(),
+        version: env!("CARGO_PKG_VERSION").to_owned(),
+        ..Default::default()
+    };
+    let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+    loop {
+        ticker.tick().await;
+        let metrics = to_proto(super::registry());
+        if metrics.is_empty() {
+            continue;
+        }
+        let req = ExportMetricsServiceRequest {
+            resource_metrics: vec![proto::ResourceMetrics {
+                resource: Some(resource.clone()),
+                scope_metrics: vec![proto::ScopeMetrics {
+                    scope: Some(scope.clone()),
+                    metrics,
+                    schema_url: String::new(),
+                }],
+                schema_url: String::new(),
+            }],
+        };
+        if let Err(err) = client.export(req).await {
+            log::error!(e = format!("{:?}", err); "failed to push metrics to collector");
+        }
+    }
+}
+
+/// Push global registry to collector every `interval`, must be called in tokio
+/// runtime
+pub fn otel_metrics<T: IntoIterator<Item = KeyValue>>(
+    endpoint: String,
+    kvs: T,
+    interval: Duration,
+) {
+    let endpoint = match tonic::transport::Endpoint::from_shared(endpoint) {
+        Ok(v) => v.timeout(Duration::from_secs(10)),
+        Err(err) => {
+            log::error!(e = format!("{:?}", err); "invalid otlp metrics endpoint");
+            return;
+        }
+    };
+    let resource = crate::logs::otlp::resource(
+        kvs.into_iter()
+            .map(|kv| (kv.key.to_string(), kv.value.to_string()))
+            .collect(),
+    );
+    let client = MetricsServiceClient::new(endpoint.connect_lazy());
+    tokio::spawn(push_loop(client, resource, interval));
+}
@@ -0,0 +1,123 @@
+//! prometheus text exposition format
+use std::fmt::Write;
+
+use super::Labels;
+use super::Metric;
+
+fn escape_help(help: &str) -> String {
+    help.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn escape_label(val: &str) -> String {
+    val.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn write_labels(buf: &mut String, labels: &Labels, le: Option<&str>) {
+    if labels.is_empty() && le.is_none() {
+        return;
+    }
+    buf.push('{');
+    let mut first = true;
+    for (k, v) in labels.iter() {
+        if !first {
+            buf.push(',');
+        }
+        first = false;
+        let _ = write!(buf, "{}=\"{}\"", k, escape_label(v));
+    }
+    if let Some(le) = le {
+        if !first {
+            buf.push(',');
+        }
+        let _ = write!(buf, "le=\"{}\"", le);
+    }
+    buf.push('}');
+}
+
+fn write_header(buf: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(buf, "# HELP {} {}", name, escape_help(help));
+    let _ = writeln!(buf, "# TYPE {} {}", name, kind);
+}
+
+pub(super) fn encode(metrics: &[Metric]) -> String {
+    let mut buf = String::with_capacity(4096);
+    for metric in metrics.iter() {
+        match metric {
+            Metric::Counter(c) => {
+                write_header(&mut buf, c.name(), c.help(), "counter");
+                for (labels, cell) in c.series() {
+                    buf.push_str(c.name());
+                    write_labels(&mut buf, &labels, None);
+                    let _ = writeln!(buf, " {}", cell.get());
+                }
+            }
+            Metric::Gauge(g) => {
+                write_header(&mut buf, g.name(), g.help(), "gauge");
+                for (labels, cell) in g.series() {
+                    buf.push_str(g.name());
+                    write_labels(&mut buf, &labels, None);
+                    let _ = writeln!(buf, " {}", cell.get());
+                }
+            }
+            Metric::Histogram(h) => {
+                write_header(&mut buf, h.name(), h.help(), "histogram");
+                for (labels, cell) in h.series() {
+                    let mut cumulative = 0;
+                    let counts = cell.bucket_counts();
+                    for (ix, count) in counts.iter().enumerate() {
+                        cumulative += count;
+                        let le = match cell.bounds().get(ix) {
+                            Some(b) => b.to_string(),
+                            None => String::from("+Inf"),
+                        };
+                        let _ = write!(buf, "{}_bucket", h.name());
+                        write_labels(&mut buf, &labels, Some(&le));
+                        let _ = writeln!(buf, " {}", cumulative);
+                    }
+                    let _ = write!(buf, "{}_sum", h.name());
+                    write_labels(&mut buf, &labels, None);
+                    let _ = writeln!(buf, " {}", cell.sum());
+                    let _ = write!(buf, "{}_count", h.name());
+                    write_labels(&mut buf, &labels, None);
+                    let _ = writeln!(buf, " {}", cell.count());
+                }
+            }
+        }
+    }
+    buf
+}
+
+#[cfg(test)]
+mod test {
+    use crate::metrics::Registry;
+
+    #[test]
+    fn test_encode() {
+        let registry = Registry::default();
+        let counter = registry
+            .counter("requests_total", "total requests")
+            .unwrap();
+        counter.inc(&[("method", "GET"), ("code", "200")]);
+        counter.inc_by(2, &[("code", "200"), ("method", "GET")]);
+        let gauge = registry.gauge("in_flight", "in flight requests").unwrap();
+        gauge.set(3.0, &[]);
+        let histogram = registry
+            .histogram("latency_seconds", "latency", Some(&[0.1, 1.0]))
+            .unwrap();
+        histogram.record(0.05, &[("path", "/a\"b")]);
+        histogram.record(0.5, &[("path", "/a\"b")]);
+        assert!(registry.gauge("requests_total", "").is_err());
+
+        let text = registry.encode();
+        assert!(text.contains("# TYPE requests_total counter\n"));
+        assert!(text.contains("requests_total{code=\"200\",method=\"GET\"} 3\n"));
+        assert!(text.contains("in_flight 3\n"));
+        assert!(text.contains("latency_seconds_bucket{path=\"/a\\\"b\",le=\"0.1\"} 1\n"));
+        assert!(text.contains("latency_seconds_bucket{path=\"/a\\\"b\",le=\"1\"} 2\n"));
+        assert!(text.contains("latency_seconds_bucket{path=\"/a\\\"b\",le=\"+Inf\"} 2\n"));
+        assert!(text.contains("latency_seconds_sum{path=\"/a\\\"b\"} 0.55\n"));
+        assert!(text.contains("latency_seconds_count{path=\"/a\\\"b\"} 2\n"));
+    }
+}
@@ -1,2 +1,8 @@
 pub use otel::otel_trace;
+pub use sampler::sampler;
+pub use sampler::set_sampler;
+pub use sampler::Decision;
+pub use sampler::Sampler;
+pub use sampler::DROP_PROPERTY;
 mod otel;
+mod sampler;
@@ -3,7 +3,17 @@ use fastrace_opentelemetry;
 use opentelemetry::KeyValue;
 use opentelemetry_otlp::WithExportConfig;
 
-pub fn otel_trace<T: IntoIterator<Item = KeyValue>>(endpoint: String, kvs: T) {
+use super::sampler::SamplingReporter;
+use super::Sampler;
+
+/// Report spans to collector, root spans are sampled by `sampler` and reported
+/// every `report_interval` if set
+pub fn otel_trace<T: IntoIterator<Item = KeyValue>>(
+    endpoint: String,
+    kvs: T,
+    sampler: Sampler,
+    report_interval: Option<std::time::Duration>,
+) {
     let reporter = fastrace_opentelemetry::OpenTelemetryReporter::new(
         opentelemetry_otlp::new_exporter()
             .tonic()
@@ -20,5 +30,10 @@ pub fn otel_trace<T: IntoIterator<Item = KeyValue>>(endpoint: String, kvs: T) {
             .with_version(env!("CARGO_PKG_VERSION"))
             .build(),
     );
-    fastrace::set_reporter(reporter, Config::default());
+    super::set_sampler(sampler);
+    let mut config = Config::default();
+    if let Some(interval) = report_interval {
+        config = config.report_interval(interval);
+    }
+    fastrace::set_reporter(SamplingReporter::new(reporter), config);
 }
@@ -0,0 +1,127 @@
+//! head based sampling of root spans
+use std::sync::OnceLock;
+
+use fastrace::collector::Reporter;
+use fastrace::collector::SpanRecord;
+
+/// property marking a recorded trace to be dropped by the reporter
+pub static DROP_PROPERTY: &str = "coral.sampling.drop";
+
+static SAMPLER: OnceLock<Sampler> = OnceLock::new();
+
+/// Sampling decision of a root span
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Decision {
+    /// record and report
+    Sample,
+    /// do not record
+    Drop,
+    /// record, report only if the request fails
+    RecordOnError,
+}
+
+#[derive(Debug, Clone)]
+pub struct Sampler {
+    ratio: f64,
+    parent_based: bool,
+    always_on_error: bool,
+}
+
+impl Default for Sampler {
+    fn default() -> Self {
+        Self {
+            ratio: 1.0,
+            parent_based: true,
+            always_on_error: false,
+        }
+    }
+}
+
+impl Sampler {
+    /// `ratio` is clamped to `[0, 1]`
+    pub fn new(ratio: f64, parent_based: bool, always_on_error: bool) -> Self {
+        Self {
+            ratio: ratio.clamp(0.0, 1.0),
+            parent_based,
+            always_on_error,
+        }
+    }
+
+    /// Decide by the decision of upstream `parent` if parent based, otherwise by
+    /// the lower 64 bits of `trace_id`, so every service agrees on the same trace
+    pub fn sample(&self, trace_id: u128, parent: Option<bool>) -> Decision {
+        let sampled = match parent {
+            Some(v) if self.parent_based => v,
+            _ if self.ratio >= 1.0 => true,
+            _ if self.ratio <= 0.0 => false,
+            _ => (trace_id as u64) < (self.ratio * u64::MAX as f64) as u64,
+        };
+        match (sampled, self.always_on_error) {
+            (true, _) => Decision::Sample,
+            (false, true) => Decision::RecordOnError,
+            (false, false) => Decision::Drop,
+        }
+    }
+}
+
+/// set global sampler, ignored if already set
+pub fn set_sampler(sampler: Sampler) {
+    let _ = SAMPLER.set(sampler);
+}
+
+/// global sampler, sample everything if unset
+pub fn sampler() -> &'static Sampler {
+    SAMPLER.get_or_init(Sampler::default)
+}
+
+/// Reporter dropping traces whose span carries [`DROP_PROPERTY`]
+pub(crate) struct SamplingReporter<R> {
+    inner: R,
+}
+
+impl<R> SamplingReporter<R> {
+    pub(crate) fn new(inner: R) -> Self {
+        Self { inner }
+    }
+}
+
+impl<R: Reporter> Reporter for SamplingReporter<R> {
+    fn report(&mut self, spans: Vec<SpanRecord>) {
+        let dropped: Vec<_> = spans
+            .iter()
+            .filter(|s| s.properties.iter().any(|(k, _)| k == DROP_PROPERTY))
+            .map(|s| s.trace_id)
+            .collect();
+        let spans: Vec<SpanRecord> = if dropped.is_empty() {
+            spans
+        } else {
+            spans
+                .into_iter()
+                .filter(|s| !dropped.contains(&s.trace_id))
+                .collect()
+        };
+        if !spans.is_empty() {
+            self.inner.report(spans);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Decision;
+    use super::Sampler;
+
+    #[test]
+    fn test_sample() {
+        let sampler = Sampler::new(0.5, true, false);
+        assert_eq!(sampler.sample(1, None), Decision::Sample);
+        assert_eq!(sampler.sample(u64::MAX as u128, None), Decision::Drop);
+        assert_eq!(sampler.sample(u64::MAX as u128, Some(true)), Decision::Sample);
+        assert_eq!(sampler.sample(1, Some(false)), Decision::Drop);
+
+        let sampler = Sampler::new(0.0, false, true);
+        assert_eq!(sampler.sample(1, Some(true)), Decision::RecordOnError);
+        let sampler = Sampler::new(2.0, false, false);
+        assert_eq!(sampler.sample(u128::MAX, None), Decision::Sample);
+    }
+}
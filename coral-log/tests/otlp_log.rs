@@ -0,0 +1,110 @@
+use std::time::Duration;
+
+use coral_log::logs::otlp::OtlpLogSink;
+use fastrace::collector::SpanContext;
+use fastrace::Span;
+use opentelemetry_proto::tonic::collector::logs::v1::logs_service_server::LogsService;
+use opentelemetry_proto::tonic::collector::logs::v1::logs_service_server::LogsServiceServer;
+use opentelemetry_proto::tonic::collector::logs::v1::ExportLogsServiceRequest;
+use opentelemetry_proto::tonic::collector::logs::v1::ExportLogsServiceResponse;
+use tokio::sync::mpsc;
+
+/// stand-in collector forwarding every export request to channel
+struct Collector {
+    tx: mpsc::UnboundedSender<ExportLogsServiceRequest>,
+}
+
+#[tonic::async_trait]
+impl LogsService for Collector {
+    async fn export(
+        &self,
+        request: tonic::Request<ExportLogsServiceRequest>,
+    ) -> Result<tonic::Response<ExportLogsServiceResponse>, tonic::Status> {
+        self.tx.send(request.into_inner()).unwrap();
+        Ok(tonic::Response::new(ExportLogsServiceResponse {
+            partial_success: None,
+        }))
+    }
+}
+
+#[test]
+fn export_to_collector() {
+    let rt = tokio::runtime::Builder::new_multi_thread()
+        .worker_threads(1)
+        .enable_all()
+        .build()
+        .unwrap();
+    let (tx, mut rx) = mpsc::unbounded_channel();
+    let listener = rt
+        .block_on(tokio::net::TcpListener::bind("127.0.0.1:0"))
+        .unwrap();
+    let addr = listener.local_addr().unwrap();
+    rt.spawn(
+        tonic::transport::Server::builder()
+            .add_service(LogsServiceServer::new(Collector { tx }))
+            .serve_with_incoming(tokio_stream::wrappers::TcpListenerStream::new(listener)),
+    );
+
+    let sink = OtlpLogSink::new(
+        format!("http://{}", addr),
+        vec![(String::from("service.name"), String::from("coral"))],
+        Some(2),
+        Some(Duration::from_millis(50)),
+    )
+    .unwrap();
+    let root = Span::root("root", SpanContext::random());
+    let ctx = SpanContext::from_span(&root).unwrap();
+    {
+        let _guard = root.set_local_parent();
+        let kvs: [(&str, log::kv::Value); 1] = [("age", 11u64.into())];
+        sink.export(
+            &log::Record::builder()
+                .args(format_args!("hello otlp"))
+                .level(log::Level::Warn)
+                .target("coral")
+                .key_values(&kvs)
+                .build(),
+        );
+    }
+    sink.export(
+        &log::Record::builder()
+            .args(format_args!("no span"))
+            .level(log::Level::Info)
+            .target("coral")
+            .build(),
+    );
+
+    let req = rt
+        .block_on(async { tokio::time::timeout(Duration::from_secs(10), rx.recv()).await })
+        .unwrap()
+        .unwrap();
+    let resource_logs = &req.resource_logs[0];
+    let resource = resource_logs.resource.as_ref().unwrap();
+    assert_eq!(resource.attributes[0].key, "service.name");
+    let records = &resource_logs.scope_logs[0].log_records;
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0].severity_text, "WARN");
+    assert_eq!(records[0].trace_id, ctx.trace_id.0.to_be_bytes().to_vec());
+    assert_eq!(records[0].span_id, ctx.span_id.0.to_be_bytes().to_vec());
+    assert!(records[0].attributes.iter().any(|kv| kv.key == "age"));
+    assert!(records[1].trace_id.is_empty());
+}
+
+#[test]
+fn otel_logs_conf() {
+    let conf = |otel_logs: &str| -> coral_log::LogConf {
+        toml::from_str(&format!(
+            "otel_endpoint = \"http://127.0.0.1:4317\"\n[otel_logs]\n{}",
+            otel_logs
+        ))
+        .unwrap()
+    };
+    assert!(conf("batch_size = 512\ninterval = \"1s\"").check().is_ok());
+    assert!(conf("batch_size = 0").check().is_err());
+    assert!(conf("batch_size = 1000000").check().is_err());
+    assert!(conf("interval = \"0s\"").check().is_err());
+    assert!(conf("interval = \"2h\"").check().is_err());
+
+    let sink = OtlpLogSink::new(String::from("http://127.0.0.1:4317"), vec![], Some(0), None);
+    assert!(sink.is_err());
+}
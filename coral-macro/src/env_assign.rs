@@ -1,7 +1,10 @@
 use proc_macro::TokenStream;
 use proc_macro2::TokenStream as TokenStream2;
+use quote::format_ident;
 use quote::quote;
-use syn::{parse_macro_input, Data, DeriveInput, Type, TypePath};
+use syn::{
+    parse_macro_input, Attribute, Data, DeriveInput, Fields, Ident, Index, LitStr, Type, TypePath,
+};
 
 pub fn assign_basic(input: TokenStream) -> TokenStream {
     if let Type::Path(TypePath { path, .. }) = parse_macro_input!(input as Type) {
@@ -24,55 +27,203 @@ pub fn assign_basic(input: TokenStream) -> TokenStream {
     TokenStream::from(quote! {})
 }
 
+/// field attributes of `#[env_assign(...)]`
+#[derive(Default)]
+pub(crate) struct FieldAttr {
+    rename: Option<String>,
+    skip: bool,
+    pub(crate) secret: bool,
+    split: Option<String>,
+}
+
+impl FieldAttr {
+    pub(crate) fn parse(attrs: &[Attribute]) -> syn::Result<Self> {
+        let mut this = Self::default();
+        for attr in attrs.iter().filter(|a| a.path().is_ident("env_assign")) {
+            attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("rename") {
+                    this.rename = Some(meta.value()?.parse::<LitStr>()?.value());
+                } else if meta.path.is_ident("skip") {
+                    this.skip = true;
+                } else if meta.path.is_ident("secret") {
+                    this.secret = true;
+                } else if meta.path.is_ident("split") {
+                    this.split = Some(meta.value()?.parse::<LitStr>()?.value());
+                } else {
+                    return Err(meta.error("expected `rename`, `skip`, `secret` or `split`"));
+                }
+                Ok(())
+            })?;
+        }
+        Ok(this)
+    }
+}
+
+/// assign `fields` bound to `binds` with env name `{prefix}{FIELD}`, tuple
+/// fields are named by index
+fn assign_fields(
+    fields: &Fields,
+    binds: &[TokenStream2],
+    prefix: TokenStream2,
+) -> syn::Result<TokenStream2> {
+    let mut stmts = TokenStream2::new();
+    for (ix, (field, bind)) in fields.iter().zip(binds).enumerate() {
+        let attr = FieldAttr::parse(&field.attrs)?;
+        if attr.skip {
+            continue;
+        }
+        let this = match (attr.rename, field.ident.as_ref()) {
+            (Some(rename), _) => rename,
+            (None, Some(ident)) => ident.to_string().to_uppercase(),
+            (None, None) => ix.to_string(),
+        };
+        let split = match attr.split.as_ref() {
+            Some(sep) => quote! { Some(#sep) },
+            None => quote! { None },
+        };
+        let mut stmt = quote! {
+            #bind.assign(Some(key.as_str()))?;
+        };
+        if attr.split.is_some() {
+            stmt = quote! {
+                match std::env::var(&key) {
+                    Ok(v) => #bind = coral_conf::parse_str(&v, #split)?,
+                    Err(_) => { #stmt }
+                }
+            };
+        }
+        if attr.secret {
+            stmt = quote! {
+                match coral_conf::secret(&key)? {
+                    Some(v) => #bind = coral_conf::parse_str(&v, #split)?,
+                    None => { #stmt }
+                }
+            };
+        }
+        stmts.extend(quote! {
+            {
+                let key = format!("{}{}", #prefix, #this);
+                #stmt
+            }
+        });
+    }
+    Ok(stmts)
+}
+
+/// variant fields are bound by `&mut`
+fn deref(binds: &[Ident]) -> Vec<TokenStream2> {
+    binds.iter().map(|bind| quote! { (*#bind) }).collect()
+}
+
 pub fn assign_struct(input: TokenStream) -> TokenStream {
     let derive_input = parse_macro_input!(input as DeriveInput);
+    expand(derive_input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(derive_input: DeriveInput) -> syn::Result<TokenStream2> {
     let mut fields = TokenStream2::new();
     match derive_input.data {
-        Data::Struct(s) => {
-            fields.extend(quote! {
-                let prefix = match prefix {
-                    Some(v) => format!("{}_", v),
-                    None => "".into(),
-                };
-            });
-            for field in s.fields.iter() {
-                if let Some(field_ident) = field.ident.as_ref() {
-                    let this = field_ident.to_string().to_uppercase();
-                    fields.extend(quote! {
-                        self.#field_ident.assign(Some(format!("{}{}", &prefix, #this).as_str()))?;
-                    });
-                }
+        Data::Struct(s) => match s.fields {
+            // newtype is transparent
+            Fields::Unnamed(ref unnamed) if unnamed.unnamed.len() == 1 => {
+                fields.extend(quote! {
+                    self.0.assign(prefix)
+                });
             }
-            fields.extend(quote! {
-                Ok(())
-            });
-        }
+            _ => {
+                fields.extend(quote! {
+                    let prefix = match prefix {
+                        Some(v) => format!("{}_", v),
+                        None => "".into(),
+                    };
+                });
+                let binds: Vec<_> = s
+                    .fields
+                    .iter()
+                    .enumerate()
+                    .map(|(ix, field)| match field.ident.as_ref() {
+                        Some(ident) => quote! { self.#ident },
+                        None => {
+                            let ix = Index::from(ix);
+                            quote! { self.#ix }
+                        }
+                    })
+                    .collect();
+                fields.extend(assign_fields(&s.fields, &binds, quote! { &prefix })?);
+                fields.extend(quote! {
+                    Ok(())
+                });
+            }
+        },
         Data::Enum(e) => {
             let mut unit_var = Vec::new();
             let mut item_var = Vec::new();
             for var in e.variants.iter() {
                 let vident = &var.ident;
-                if let syn::Fields::Unit = var.fields {
-                    // unit_var.push(var.ident);
-                    let vident_str = &vident.to_string();
-                    unit_var.push(quote! {
-                        if v == #vident_str {
-                            *self = Self::#vident;
-                            return Ok(());
-                        }
-                    });
-                } else {
-                    let this = vident.to_string().to_uppercase();
-                    item_var.push(quote! {
-                        Self::#vident(v) => v.assign(Some(format!("{}{}", &prefix2, #this).as_str()))
-                    });
+                let this = vident.to_string().to_uppercase();
+                match var.fields {
+                    Fields::Unit => {
+                        let vident_str = &vident.to_string();
+                        unit_var.push(quote! {
+                            if v == #vident_str {
+                                *self = Self::#vident;
+                                return Ok(());
+                            }
+                        });
+                    }
+                    Fields::Unnamed(ref unnamed) if unnamed.unnamed.len() == 1 => {
+                        item_var.push(quote! {
+                            Self::#vident(v) => v.assign(Some(format!("{}{}", &prefix2, #this).as_str()))
+                        });
+                    }
+                    Fields::Unnamed(ref unnamed) => {
+                        let binds: Vec<_> = (0..unnamed.unnamed.len())
+                            .map(|ix| format_ident!("f{}", ix))
+                            .collect();
+                        let stmts = assign_fields(
+                            &var.fields,
+                            &deref(&binds),
+                            quote! { format!("{}{}_", &prefix2, #this) },
+                        )?;
+                        item_var.push(quote! {
+                            Self::#vident(#(#binds),*) => {
+                                #stmts
+                                Ok(())
+                            }
+                        });
+                    }
+                    Fields::Named(ref named) => {
+                        let idents: Vec<_> = named
+                            .named
+                            .iter()
+                            .filter_map(|f| f.ident.as_ref())
+                            .collect();
+                        let binds: Vec<_> = idents
+                            .iter()
+                            .map(|ident| format_ident!("f_{}", ident))
+                            .collect();
+                        let stmts = assign_fields(
+                            &var.fields,
+                            &deref(&binds),
+                            quote! { format!("{}{}_", &prefix2, #this) },
+                        )?;
+                        item_var.push(quote! {
+                            Self::#vident { #(#idents: #binds),* } => {
+                                #stmts
+                                Ok(())
+                            }
+                        });
+                    }
                 }
             }
             if unit_var.len() != 0 {
                 fields.extend(quote! {
-                    let prefix1 = prefix.unwrap();
-                    if let Ok(v) = std::env::var(prefix1) {
-                        #(#unit_var)*
+                    if let Some(prefix1) = prefix {
+                        if let Ok(v) = std::env::var(prefix1) {
+                            #(#unit_var)*
+                        }
                     }
                 });
             }
@@ -95,13 +246,15 @@ pub fn assign_struct(input: TokenStream) -> TokenStream {
                 });
             }
         }
-        Data::Union(_) => {
-            // TODO:
-            todo!()
+        Data::Union(u) => {
+            return Err(syn::Error::new_spanned(
+                u.union_token,
+                "EnvAssign cannot be derived for unions",
+            ));
         }
     };
     let ident = &derive_input.ident;
-    TokenStream::from(quote! {
+    Ok(quote! {
         const _: () = {
             impl EnvAssignToml for  #ident {
                 fn assign(&mut self, prefix: Option<&str>) -> std::result::Result<(), serde_json::Error> {
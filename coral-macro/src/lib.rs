@@ -1,7 +1,9 @@
 use proc_macro::TokenStream;
 
 mod env_assign;
+mod schema;
 mod trace_log;
+mod validate;
 mod wasm;
 
 #[proc_macro_derive(WasmAttr)]
@@ -67,11 +69,49 @@ pub fn env_assign_basic(input: TokenStream) -> TokenStream {
 ///     assert_eq!(conf.sub1.val, 2);
 /// }
 /// ```
-#[proc_macro_derive(EnvAssign)]
+///
+/// # Field attributes
+///
+/// - `#[env_assign(rename = "NAME")]` env name of field instead of uppercased
+///   field name
+/// - `#[env_assign(skip)]` never assign from env
+/// - `#[env_assign(secret)]` read value from file in `{NAME}_FILE` if `{NAME}`
+///   is unset, as docker secrets
+/// - `#[env_assign(split = ",")]` list separated by `,` instead of json
+#[proc_macro_derive(EnvAssign, attributes(env_assign))]
 pub fn derive(input: TokenStream) -> TokenStream {
     env_assign::assign_struct(input)
 }
 
+/// Derive `coral_conf::Validate`, reporting every invalid field
+///
+/// # Field attributes
+///
+/// - `#[validate(range(min = 0, max = 10))]` either bound may be omitted
+/// - `#[validate(file)]` path of an existing file
+/// - `#[validate(dir)]` path of an existing directory
+/// - `#[validate(one_of("a", "b"))]`
+/// - `#[validate(port)]` non zero port
+/// - `#[validate(nested)]` validate field by its own `Validate`
+/// - `#[validate(custom = "path::to::check")]` `fn(&T) -> Result<(), E: Display>`
+///
+/// Rules of `Option` fields apply only if it is some. `custom` is also allowed
+/// on struct, called with `&self`.
+#[proc_macro_derive(Validate, attributes(validate))]
+pub fn derive_validate(input: TokenStream) -> TokenStream {
+    validate::derive(input)
+}
+
+/// Derive `coral_conf::Schema`, json schema of the config as serde reads it
+///
+/// Doc comments become `description`, `#[env_assign(secret)]` fields are
+/// `writeOnly` so that they are redacted on dump, and `#[validate(...)]` rules
+/// map to `minimum`, `maximum` and `enum` where they can.
+#[proc_macro_derive(Schema, attributes(env_assign, validate))]
+pub fn derive_schema(input: TokenStream) -> TokenStream {
+    schema::derive(input)
+}
+
 #[proc_macro]
 pub fn trace_error(input: TokenStream) -> TokenStream {
     trace_log::parse_log(input, trace_log::Level::Error)
@@ -0,0 +1,201 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Expr, ExprLit, Fields, Lit, Meta};
+
+use crate::env_assign::FieldAttr;
+use crate::validate::parse_rules;
+use crate::validate::Rule;
+
+/// doc comments joined as description
+fn description(attrs: &[Attribute]) -> Option<String> {
+    let lines: Vec<String> = attrs
+        .iter()
+        .filter_map(|attr| match &attr.meta {
+            Meta::NameValue(nv) if nv.path.is_ident("doc") => match &nv.value {
+                Expr::Lit(ExprLit {
+                    lit: Lit::Str(s), ..
+                }) => Some(s.value().trim().to_owned()),
+                _ => None,
+            },
+            _ => None,
+        })
+        .collect();
+    match lines.is_empty() {
+        true => None,
+        false => Some(lines.join(" ").trim().to_owned()),
+    }
+}
+
+/// keywords of `#[validate(...)]`, `#[env_assign(secret)]` and doc comments
+fn keywords(attrs: &[Attribute]) -> syn::Result<TokenStream2> {
+    let mut keywords = TokenStream2::new();
+    if let Some(desc) = description(attrs) {
+        keywords.extend(quote! { map.insert("description".into(), serde_json::json!(#desc)); });
+    }
+    if FieldAttr::parse(attrs)?.secret {
+        keywords.extend(quote! { map.insert("writeOnly".into(), serde_json::json!(true)); });
+    }
+    for rule in parse_rules(attrs)? {
+        keywords.extend(match rule {
+            Rule::Range(min, max) => {
+                let min =
+                    min.map(|v| quote! { map.insert("minimum".into(), serde_json::json!(#v)); });
+                let max =
+                    max.map(|v| quote! { map.insert("maximum".into(), serde_json::json!(#v)); });
+                quote! { #min #max }
+            }
+            Rule::OneOf(options) => {
+                quote! { map.insert("enum".into(), serde_json::json!([#(#options),*])); }
+            }
+            Rule::Port => quote! {
+                map.insert("minimum".into(), serde_json::json!(1));
+                map.insert("maximum".into(), serde_json::json!(65535));
+            },
+            Rule::File | Rule::Dir => quote! {
+                map.insert("format".into(), serde_json::json!("path"));
+            },
+            Rule::Nested | Rule::Custom(_) => quote! {},
+        });
+    }
+    Ok(match keywords.is_empty() {
+        true => keywords,
+        false => quote! {
+            let mut map = serde_json::Map::new();
+            #keywords
+            schema = coral_conf::schema::extend(schema, serde_json::Value::Object(map));
+        },
+    })
+}
+
+/// schema of `fields`, object for named fields and array for tuple fields
+fn fields_schema(fields: &Fields) -> syn::Result<TokenStream2> {
+    let mut stmts = TokenStream2::new();
+    for field in fields.iter() {
+        let ty = &field.ty;
+        let keywords = keywords(&field.attrs)?;
+        let push = match field.ident.as_ref() {
+            Some(ident) => {
+                let name = ident.to_string();
+                quote! {
+                    if !<#ty as coral_conf::Schema>::optional() {
+                        required.push(serde_json::json!(#name));
+                    }
+                    properties.insert(#name.into(), schema);
+                }
+            }
+            None => quote! { items.push(schema); },
+        };
+        stmts.extend(quote! {
+            {
+                let mut schema = <#ty as coral_conf::Schema>::schema();
+                #keywords
+                #push
+            }
+        });
+    }
+    Ok(match fields {
+        Fields::Named(_) => quote! {
+            {
+                let mut properties = serde_json::Map::new();
+                let mut required = Vec::new();
+                #stmts
+                serde_json::json!({
+                    "type": "object",
+                    "properties": properties,
+                    "required": required,
+                })
+            }
+        },
+        Fields::Unnamed(f) if f.unnamed.len() == 1 => {
+            let ty = &f.unnamed[0].ty;
+            quote! { <#ty as coral_conf::Schema>::schema() }
+        }
+        Fields::Unnamed(f) => {
+            let len = f.unnamed.len();
+            quote! {
+                {
+                    let mut items = Vec::new();
+                    #stmts
+                    serde_json::json!({
+                        "type": "array",
+                        "prefixItems": items,
+                        "minItems": #len,
+                        "maxItems": #len,
+                    })
+                }
+            }
+        }
+        Fields::Unit => quote! { serde_json::json!({ "type": "null" }) },
+    })
+}
+
+pub fn derive(input: TokenStream) -> TokenStream {
+    let derive_input = parse_macro_input!(input as DeriveInput);
+    expand(derive_input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(derive_input: DeriveInput) -> syn::Result<TokenStream2> {
+    let body = match &derive_input.data {
+        Data::Struct(s) => fields_schema(&s.fields)?,
+        Data::Enum(e) => {
+            // externally tagged as serde does, unit variants are plain strings
+            let mut variants = Vec::new();
+            for variant in e.variants.iter() {
+                let name = variant.ident.to_string();
+                let desc = description(&variant.attrs)
+                    .map(|desc| quote! { "description": #desc, })
+                    .unwrap_or_default();
+                variants.push(match &variant.fields {
+                    Fields::Unit => quote! {
+                        serde_json::json!({ #desc "const": #name })
+                    },
+                    fields => {
+                        let schema = fields_schema(fields)?;
+                        quote! {
+                            {
+                                let schema = #schema;
+                                serde_json::json!({
+                                    #desc
+                                    "type": "object",
+                                    "properties": { #name: schema },
+                                    "required": [#name],
+                                    "additionalProperties": false,
+                                })
+                            }
+                        }
+                    }
+                });
+            }
+            quote! { serde_json::json!({ "oneOf": [#((#variants)),*] }) }
+        }
+        Data::Union(_) => {
+            return Err(syn::Error::new_spanned(
+                &derive_input.ident,
+                "Schema cannot be derived for unions",
+            ));
+        }
+    };
+    let mut keywords = TokenStream2::new();
+    if let Some(desc) = description(&derive_input.attrs) {
+        keywords.extend(quote! {
+            let mut map = serde_json::Map::new();
+            map.insert("description".into(), serde_json::json!(#desc));
+            schema = coral_conf::schema::extend(schema, serde_json::Value::Object(map));
+        });
+    }
+    let ident = &derive_input.ident;
+    let (impl_generics, ty_generics, where_clause) = derive_input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics coral_conf::Schema for #ident #ty_generics #where_clause {
+            #[allow(unused_mut)]
+            fn schema() -> serde_json::Value {
+                let mut schema = #body;
+                #keywords
+                schema
+            }
+        }
+    })
+}
@@ -0,0 +1,175 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::punctuated::Punctuated;
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Expr, ExprPath, LitStr, Token, Type};
+
+/// rules of `#[validate(...)]`
+pub(crate) enum Rule {
+    Range(Option<Box<Expr>>, Option<Box<Expr>>),
+    File,
+    Dir,
+    OneOf(Vec<LitStr>),
+    Port,
+    Nested,
+    Custom(ExprPath),
+}
+
+pub(crate) fn parse_rules(attrs: &[Attribute]) -> syn::Result<Vec<Rule>> {
+    let mut rules = Vec::new();
+    for attr in attrs.iter().filter(|a| a.path().is_ident("validate")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("range") {
+                let (mut min, mut max) = (None, None);
+                meta.parse_nested_meta(|meta| {
+                    if meta.path.is_ident("min") {
+                        min = Some(Box::new(meta.value()?.parse::<Expr>()?));
+                    } else if meta.path.is_ident("max") {
+                        max = Some(Box::new(meta.value()?.parse::<Expr>()?));
+                    } else {
+                        return Err(meta.error("expected `min` or `max`"));
+                    }
+                    Ok(())
+                })?;
+                rules.push(Rule::Range(min, max));
+            } else if meta.path.is_ident("file") {
+                rules.push(Rule::File);
+            } else if meta.path.is_ident("dir") {
+                rules.push(Rule::Dir);
+            } else if meta.path.is_ident("one_of") {
+                let content;
+                syn::parenthesized!(content in meta.input);
+                let options = Punctuated::<LitStr, Token![,]>::parse_terminated(&content)?;
+                rules.push(Rule::OneOf(options.into_iter().collect()));
+            } else if meta.path.is_ident("port") {
+                rules.push(Rule::Port);
+            } else if meta.path.is_ident("nested") {
+                rules.push(Rule::Nested);
+            } else if meta.path.is_ident("custom") {
+                let func = meta.value()?.parse::<LitStr>()?.parse::<ExprPath>()?;
+                rules.push(Rule::Custom(func));
+            } else {
+                return Err(meta.error(
+                    "expected `range`, `file`, `dir`, `one_of`, `port`, `nested` or `custom`",
+                ));
+            }
+            Ok(())
+        })?;
+    }
+    Ok(rules)
+}
+
+fn is_option(ty: &Type) -> bool {
+    match ty {
+        Type::Path(p) => p
+            .path
+            .segments
+            .last()
+            .map(|s| s.ident == "Option")
+            .unwrap_or(false),
+        _ => false,
+    }
+}
+
+fn rule_check(rule: &Rule) -> TokenStream2 {
+    let check = match rule {
+        Rule::Range(min, max) => {
+            let min = match min {
+                Some(v) => quote! { Some(#v) },
+                None => quote! { None },
+            };
+            let max = match max {
+                Some(v) => quote! { Some(#v) },
+                None => quote! { None },
+            };
+            quote! { coral_conf::validate::range(value, #min, #max) }
+        }
+        Rule::File => quote! { coral_conf::validate::file(value) },
+        Rule::Dir => quote! { coral_conf::validate::dir(value) },
+        Rule::OneOf(options) => quote! { coral_conf::validate::one_of(value, &[#(#options),*]) },
+        Rule::Port => quote! { coral_conf::validate::port(value) },
+        Rule::Nested => {
+            return quote! {
+                coral_conf::Validate::validate(value, &path, report);
+            }
+        }
+        Rule::Custom(func) => quote! { #func(value) },
+    };
+    quote! {
+        report.check(&path, #check);
+    }
+}
+
+pub fn derive(input: TokenStream) -> TokenStream {
+    let derive_input = parse_macro_input!(input as DeriveInput);
+    expand(derive_input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+fn expand(derive_input: DeriveInput) -> syn::Result<TokenStream2> {
+    let s = match derive_input.data {
+        Data::Struct(s) => s,
+        _ => {
+            return Err(syn::Error::new_spanned(
+                &derive_input.ident,
+                "Validate can only be derived for structs",
+            ));
+        }
+    };
+    let mut stmts = TokenStream2::new();
+    for (ix, field) in s.fields.iter().enumerate() {
+        let rules = parse_rules(&field.attrs)?;
+        if rules.is_empty() {
+            continue;
+        }
+        let (member, name) = match field.ident.as_ref() {
+            Some(ident) => (quote! { #ident }, ident.to_string()),
+            None => {
+                let index = syn::Index::from(ix);
+                (quote! { #index }, ix.to_string())
+            }
+        };
+        let checks: Vec<_> = rules.iter().map(rule_check).collect();
+        let checks = match is_option(&field.ty) {
+            true => quote! {
+                if let Some(value) = self.#member.as_ref() {
+                    #(#checks)*
+                }
+            },
+            false => quote! {
+                let value = &self.#member;
+                #(#checks)*
+            },
+        };
+        stmts.extend(quote! {
+            {
+                let path = coral_conf::validate::join(path, #name);
+                #checks
+            }
+        });
+    }
+    for rule in parse_rules(&derive_input.attrs)? {
+        match rule {
+            Rule::Custom(func) => stmts.extend(quote! {
+                report.check(path, #func(self));
+            }),
+            _ => {
+                return Err(syn::Error::new_spanned(
+                    &derive_input.ident,
+                    "only `custom` is allowed on struct",
+                ));
+            }
+        }
+    }
+    let ident = &derive_input.ident;
+    let (impl_generics, ty_generics, where_clause) = derive_input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics coral_conf::Validate for #ident #ty_generics #where_clause {
+            #[allow(unused_variables)]
+            fn validate(&self, path: &str, report: &mut coral_conf::Report) {
+                #stmts
+            }
+        }
+    })
+}
@@ -0,0 +1,56 @@
+//! admin listener
+//!
+//! Metrics and runtime stats are served by a listener of their own, apart
+//! from the public h2 and h3 listeners, so that they are not exposed to
+//! clients and do not shadow routes of upstreams.
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+
+use axum::routing::get;
+use coral_conf::EnvAssignToml;
+use coral_macro::EnvAssign;
+use coral_macro::Schema;
+use coral_macro::Validate;
+use coral_runtime::tokio::net::TcpListener;
+use serde::Deserialize;
+use serde::Serialize;
+
+use crate::error::CoralRes;
+use crate::hand::metrics_hand;
+use crate::hand::runtime_hand;
+use crate::hand::METRICS_URI;
+use crate::hand::RUNTIME_URI;
+
+/// default port of [`AdminConf::addr`]
+pub const DEFAULT_PORT: u16 = 9090;
+
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate, Debug, Clone, Default)]
+pub struct AdminConf {
+    /// plain http listen address, default `127.0.0.1:9090`
+    pub addr: Option<SocketAddr>,
+}
+
+impl AdminConf {
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+            .unwrap_or(SocketAddr::from((Ipv4Addr::LOCALHOST, DEFAULT_PORT)))
+    }
+
+    /// routes of the admin listener
+    pub fn router(&self) -> axum::Router {
+        axum::Router::new()
+            .route(METRICS_URI, get(metrics_hand))
+            .route(RUNTIME_URI, get(runtime_hand))
+    }
+
+    /// Serve [`AdminConf::router`] on [`AdminConf::addr`] until
+    /// [`coral_runtime::supervise::root`] is cancelled
+    pub async fn serve(self) -> CoralRes<()> {
+        let listener = TcpListener::bind(self.addr()).await?;
+        let token = coral_runtime::supervise::root().token().clone();
+        axum::serve(listener, self.router())
+            .with_graceful_shutdown(async move { token.cancelled().await })
+            .await?;
+        Ok(())
+    }
+}
@@ -1,3 +1,4 @@
+use std::future::Future;
 use std::marker::PhantomData;
 use std::str::FromStr;
 use std::sync::atomic::AtomicU32;
@@ -5,8 +6,11 @@ use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 use coral_runtime::{spawn, tokio};
+use fastrace::collector::SpanContext;
+use fastrace::Span;
 
 use crate::error::CoralRes;
+use crate::propagation::TraceParent;
 
 /// client is normal
 pub static NORMAL: u8 = 0;
@@ -28,6 +32,54 @@ pub trait Request<R, H> {
     async fn send(&mut self, req: hyper::Request<R>) -> CoralRes<hyper::Response<H>>;
 }
 
+/// Send `req` in a client span and inject trace headers of the span, headers
+/// are untouched if there is no local parent span
+pub(crate) async fn traced_send<B, T, F>(
+    mut req: hyper::Request<B>,
+    send: impl FnOnce(hyper::Request<B>) -> F,
+) -> CoralRes<hyper::Response<T>>
+where
+    F: Future<Output = CoralRes<hyper::Response<T>>>,
+{
+    let mut properties = vec![
+        ("http.request.method", req.method().to_string()),
+        ("url.full", req.uri().to_string()),
+    ];
+    if let Some(host) = req.uri().host() {
+        properties.push(("server.address", host.to_owned()));
+    }
+    let mut span =
+        Span::enter_with_local_parent(req.method().to_string()).with_properties(|| properties);
+    if let Some(ctx) = SpanContext::from_span(&span) {
+        let upstream = crate::propagation::extract(req.headers());
+        let parent = TraceParent {
+            trace_id: ctx.trace_id,
+            span_id: ctx.span_id,
+            sampled: Some(upstream.as_ref().and_then(|v| v.sampled).unwrap_or(true)),
+            trace_state: upstream.and_then(|v| v.trace_state),
+        };
+        crate::propagation::inject(req.headers_mut(), &parent);
+    }
+    let mut fut = std::pin::pin!(send(req));
+    let rsp = std::future::poll_fn(|cx| {
+        let _guard = span.set_local_parent();
+        fut.as_mut().poll(cx)
+    })
+    .await;
+    span = match rsp.as_ref() {
+        Ok(v) => {
+            let status = v.status().as_u16().to_string();
+            span.with_property(|| ("http.response.status_code", status))
+        }
+        Err(err) => {
+            let err = err.to_string();
+            span.with_property(|| ("error.type", err))
+        }
+    };
+    drop(span);
+    rsp
+}
+
 pub struct StatisticsGuard(pub(crate) Arc<AtomicU32>);
 
 impl Drop for StatisticsGuard {
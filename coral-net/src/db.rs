@@ -2,37 +2,58 @@ use std::{io::Read, str::FromStr};
 
 use coral_conf::EnvAssignToml;
 use coral_macro::EnvAssign;
+use coral_macro::Schema;
+use coral_macro::Validate;
 use redis::{
     aio::{ConnectionLike, ConnectionManager, MultiplexedConnection},
     cluster_async::ClusterConnection,
 };
 use serde::Deserialize;
+use serde::Serialize;
 use sqlx::ConnectOptions;
 
 use crate::error::CoralRes;
 pub use sqlx::Error as SqlxErr;
 
-#[derive(Deserialize, EnvAssign, Debug, Clone)]
+/// level filter of sqlx, e.g. `off` or `warn`
+fn check_level(level: &str) -> Result<(), log::ParseLevelError> {
+    log::LevelFilter::from_str(level).map(|_| ())
+}
+
+fn check_ssl_mode(mode: &str) -> Result<(), sqlx::Error> {
+    sqlx::postgres::PgSslMode::from_str(mode).map(|_| ())
+}
+
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate, Debug, Clone)]
 pub(crate) struct LogSettings {
+    #[validate(custom = "check_level")]
     pub(crate) statements_level: String,
+    #[validate(custom = "check_level")]
     pub(crate) slow_statements_level: String,
     pub(crate) slow_statements_duration: u64,
 }
 
-#[derive(Deserialize, EnvAssign, Debug, Clone)]
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate, Debug, Clone)]
 pub(crate) struct PgConnectOptions {
     pub(crate) host: String,
+    #[validate(port)]
     pub(crate) port: u16,
     pub(crate) socket: Option<String>,
     pub(crate) username: String,
+    #[env_assign(secret)]
     pub(crate) password: Option<String>,
     pub(crate) database: Option<String>,
+    #[validate(custom = "check_ssl_mode")]
     pub(crate) ssl_mode: String,
+    #[validate(file)]
     pub(crate) ssl_root_cert: Option<String>,
+    #[validate(file)]
     pub(crate) ssl_client_cert: Option<String>,
+    #[validate(file)]
     pub(crate) ssl_client_key: Option<String>,
     pub(crate) statement_cache_capacity: Option<usize>,
     pub(crate) application_name: Option<String>,
+    #[validate(nested)]
     pub(crate) log_settings: Option<LogSettings>,
 }
 
@@ -81,11 +102,14 @@ impl TryFrom<&PgConnectOptions> for sqlx::postgres::PgConnectOptions {
     }
 }
 
-#[derive(Deserialize, EnvAssign, Debug, Clone)]
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate, Debug, Clone)]
 pub(crate) struct PoolOptions {
     pub(crate) test_before_acquire: Option<bool>,
+    #[validate(range(min = 1))]
     pub(crate) max_connections: Option<u32>,
+    #[validate(custom = "check_level")]
     pub(crate) acquire_time_level: Option<String>,
+    #[validate(custom = "check_level")]
     pub(crate) acquire_slow_level: Option<String>,
     pub(crate) acquire_slow_threshold: Option<u64>,
     pub(crate) acquire_timeout: Option<u64>,
@@ -135,9 +159,11 @@ impl<DB: sqlx::Database> TryFrom<&PoolOptions> for sqlx::pool::PoolOptions<DB> {
     }
 }
 
-#[derive(Deserialize, EnvAssign, Debug, Clone)]
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate, Debug, Clone)]
 pub struct DbConf {
+    #[validate(nested)]
     pool: Option<PoolOptions>,
+    #[validate(nested)]
     postgres: Option<PgConnectOptions>,
 }
 
@@ -160,10 +186,13 @@ impl DbConf {
     }
 }
 
-#[derive(Deserialize, EnvAssign, Debug, Clone)]
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate, Debug, Clone)]
 struct RedisTls {
+    #[validate(file)]
     root_cert_store: Option<String>,
+    #[validate(file)]
     client_cert: Option<String>,
+    #[validate(file)]
     client_key: Option<String>,
 }
 
@@ -197,25 +226,30 @@ impl TryFrom<&RedisTls> for redis::TlsCertificates {
     }
 }
 
-#[derive(Deserialize, EnvAssign, Debug, Clone)]
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate, Debug, Clone)]
 pub struct RedisSingle {
     host: String,
+    #[validate(port)]
     port: u16,
     insecure: bool,
+    #[validate(nested)]
     tls_params: Option<RedisTls>,
     db: Option<i64>,
     username: Option<String>,
+    #[env_assign(secret)]
     password: Option<String>,
+    /// 0 for RESP2, 1 for RESP3
+    #[validate(range(max = 1))]
     protocol: Option<u16>,
     config: RedisSingleConf,
 }
 
-#[derive(Deserialize, EnvAssign, Debug, Clone)]
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Debug, Clone)]
 enum RedisSingleConf {
     Manager(RedisConnManagerConf), // TODO: Multi
 }
 
-#[derive(Deserialize, EnvAssign, Debug, Clone)]
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Debug, Clone)]
 struct RedisConnManagerConf {
     exponent_base: Option<u64>,
     /// A multiplicative factor that will be applied to the retry delay.
@@ -280,7 +314,7 @@ impl From<&RedisSingle> for redis::RedisConnectionInfo {
     }
 }
 
-#[derive(Deserialize, EnvAssign, Debug, Clone)]
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Debug, Clone)]
 struct RedisRetryParams {
     number_of_retries: u32,
     max_wait_time: u64,
@@ -289,25 +323,44 @@ struct RedisRetryParams {
     factor: u64,
 }
 
-#[derive(Deserialize, EnvAssign, Debug, Clone)]
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate, Debug, Clone)]
 pub struct RedisCluster {
+    #[env_assign(secret)]
     password: Option<String>,
     username: Option<String>,
     read_from_replicas: Option<bool>,
     insecure: bool,
     retry_params: Option<RedisRetryParams>,
+    #[validate(nested)]
     tls_params: Option<RedisTls>,
     connection_timeout: Option<u64>,
     response_timeout: Option<u64>,
     protocol: Option<u16>,
 }
 
-#[derive(Deserialize, EnvAssign, Debug, Clone)]
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Debug, Clone)]
 pub enum RedisConf {
     Single(RedisSingle),
     Cluster(RedisCluster),
 }
 
+impl coral_conf::Validate for RedisConf {
+    fn validate(&self, path: &str, report: &mut coral_conf::Report) {
+        match self {
+            RedisConf::Single(single) => coral_conf::Validate::validate(
+                single,
+                &coral_conf::validate::join(path, "Single"),
+                report,
+            ),
+            RedisConf::Cluster(cluster) => coral_conf::Validate::validate(
+                cluster,
+                &coral_conf::validate::join(path, "Cluster"),
+                report,
+            ),
+        }
+    }
+}
+
 pub type RedisAsyncPushSender = coral_runtime::tokio::sync::mpsc::UnboundedSender<redis::PushInfo>;
 
 impl RedisConf {
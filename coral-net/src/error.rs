@@ -1,6 +1,9 @@
 use axum::response::IntoResponse;
+use fastrace::collector::SpanContext;
 use hyper::header::InvalidHeaderValue;
+use hyper::header::CONTENT_TYPE;
 use hyper::StatusCode;
+use log::log;
 use quinn::crypto::rustls::NoInitialCipherSuite;
 use rustls::pki_types::InvalidDnsNameError;
 use rustls::server::VerifierBuilderError;
@@ -10,6 +13,8 @@ pub(crate) type CoralRes<T> = Result<T, Error>;
 
 #[derive(Error, Debug)]
 pub enum Error {
+    /// not returned any more, see [`crate::tls::TlsConf::check`]
+    #[deprecated(note = "an invalid ca is reported by `coral_conf::Validate`")]
     #[error("invalid ca directory")]
     InvalidCa,
 
@@ -95,6 +100,12 @@ pub enum Error {
     #[error("log::ParseLevelError")]
     LogParseLevelError(#[from] log::ParseLevelError),
 
+    #[error("coral log error")]
+    CoralLogErr(#[from] coral_log::error::Error),
+
+    #[error("{0}")]
+    ConfErr(#[from] coral_conf::Error),
+
     // ---------- db ----------
     #[error("sqlx::error")]
     SqlxErr(#[from] sqlx::Error),
@@ -105,10 +116,71 @@ pub enum Error {
 
     #[error("invalid redis conn type")]
     InvalidConnType,
+
+    // ---------- midware ----------
+    #[error("invalid access log field {0}")]
+    InvalidAccessLogField(String),
+
+    #[error("invalid compression encoding {0}")]
+    InvalidEncoding(String),
+}
+
+impl Error {
+    /// status of the response of the error
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Error::MissingHeader(_) | Error::HttpUri(_) | Error::UriAuthErr => {
+                StatusCode::BAD_REQUEST
+            }
+            Error::DiscoverConnErr
+            | Error::HeartBeatFailed
+            | Error::ConnectError(_)
+            | Error::ConnectError1(_)
+            | Error::EmptyAddr => StatusCode::BAD_GATEWAY,
+            Error::SqlxErr(sqlx::Error::PoolTimedOut) => StatusCode::SERVICE_UNAVAILABLE,
+            Error::RedisErr(err)
+                if err.is_timeout()
+                    || err.is_connection_refusal()
+                    || err.is_connection_dropped() =>
+            {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// RFC 7807 problem details of `err` with only `status` and the trace id, the
+/// details of `err` are logged
+pub fn problem<E: std::fmt::Debug>(status: StatusCode, err: &E) -> axum::response::Response {
+    let title = status.canonical_reason().unwrap_or("Unknown Error");
+    let mut body = serde_json::json!({
+        "type": "about:blank",
+        "title": title,
+        "status": status.as_u16(),
+    });
+    let level = match status.is_server_error() {
+        true => log::Level::Error,
+        false => log::Level::Warn,
+    };
+    let (code, e) = (status.as_u16(), format!("{:?}", err));
+    match SpanContext::current_local_parent() {
+        Some(span) => {
+            body["trace_id"] = format!("{:032x}", span.trace_id.0).into();
+            log!(level, status = code, e = e, trace_id = span.trace_id.0; "{}", title);
+        }
+        None => log!(level, status = code, e = e; "{}", title),
+    }
+    (
+        status,
+        [(CONTENT_TYPE, "application/problem+json")],
+        body.to_string(),
+    )
+        .into_response()
 }
 
 impl IntoResponse for Error {
     fn into_response(self) -> axum::response::Response {
-        (StatusCode::INTERNAL_SERVER_ERROR, format!("{:?}", self)).into_response()
+        problem(self.status(), &self)
     }
 }
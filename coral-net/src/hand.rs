@@ -1,4 +1,10 @@
-use coral_runtime::spawn;
+use std::convert::Infallible;
+use std::task::Context;
+use std::task::Poll;
+
+use axum::routing::future::RouteFuture;
+use coral_runtime::supervise::TaskGroup;
+use coral_runtime::tokio::sync::watch;
 use futures::{SinkExt, StreamExt};
 use hyper_util::rt::TokioIo;
 use log::error;
@@ -26,6 +32,11 @@ use crate::{
 
 pub static HTTP_RESET_URI: &'static str = "/reset";
 pub static WS_RESET_URI: &'static str = "/reset_ws";
+pub static METRICS_URI: &'static str = "/metrics";
+pub static RUNTIME_URI: &'static str = "/runtime";
+
+/// tasks of websocket connections
+static WEBSOCKETS: std::sync::OnceLock<TaskGroup> = std::sync::OnceLock::new();
 
 /// Redirect h2 request
 pub fn redirect_h2(
@@ -50,7 +61,9 @@ pub fn redirect_h2(
         *reqc.version_mut() = req.version();
         *reqc.headers_mut() = req.headers().clone();
         *(reqc.uri_mut()) = Uri::from_static(WS_RESET_URI);
-        spawn(websocket_conn_hand(req));
+        WEBSOCKETS
+            .get_or_init(|| coral_runtime::supervise::root().child("websocket"))
+            .spawn(websocket_conn_hand(req));
         router.call(reqc)
     } else {
         router.call(req)
@@ -66,6 +79,30 @@ pub fn redirect_router(
     router.call(req)
 }
 
+/// Router passing every request to the latest router of `rx`, so that routes
+/// and layers can be replaced at runtime
+pub fn reloadable(rx: watch::Receiver<axum::Router>) -> axum::Router {
+    axum::Router::new().fallback_service(Reloadable(rx))
+}
+
+#[derive(Clone)]
+struct Reloadable(watch::Receiver<axum::Router>);
+
+impl Service<Request<Body>> for Reloadable {
+    type Response = Response<Body>;
+    type Error = Infallible;
+    type Future = RouteFuture<Infallible>;
+
+    fn poll_ready(&mut self, _: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let mut router = self.0.borrow().clone();
+        router.call(req)
+    }
+}
+
 /// Redirect requests to `path`
 pub fn redirect_req<T>(req: &mut hyper::Request<T>, path: &str) {
     let path_and_query = req
@@ -123,3 +160,75 @@ pub async fn websocket_upgrade_hand(req: Request<axum::body::Body>) -> CoralRes<
     res.headers_mut().append(SEC_WEBSOCKET_ACCEPT, derived_hv);
     Ok(res)
 }
+
+/// Render global metrics registry in prometheus text format, with stats of
+/// runtimes taken at scrape
+pub async fn metrics_hand() -> Response<Body> {
+    if let Err(err) = runtime_metrics(&coral_runtime::stats::report()) {
+        error!(e = format!("{:?}", err); "failed to record runtime metrics");
+    }
+    let mut res = Response::new(Body::from(coral_log::metrics::encode()));
+    res.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        HeaderValue::from_static(coral_log::metrics::PROMETHEUS_CONTENT_TYPE),
+    );
+    res
+}
+
+/// Report runtimes and named tasks of coral-runtime in json
+pub async fn runtime_hand() -> Response<Body> {
+    let report = coral_runtime::stats::report();
+    let mut res = Response::new(Body::from(serde_json::to_vec(&report).unwrap_or_default()));
+    res.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        HeaderValue::from_static("application/json"),
+    );
+    res
+}
+
+fn runtime_metrics(report: &coral_runtime::stats::Report) -> Result<(), coral_log::error::Error> {
+    use coral_log::metrics::counter;
+    use coral_log::metrics::gauge;
+    let workers = gauge("coral_runtime_workers", "worker threads of runtime")?;
+    let alive = gauge("coral_runtime_alive_tasks", "alive tasks of runtime")?;
+    let global_queue = gauge(
+        "coral_runtime_global_queue_depth",
+        "tasks in global queue of runtime",
+    )?;
+    let blocking = gauge(
+        "coral_runtime_blocking_threads",
+        "threads of blocking pool of runtime",
+    )?;
+    let busy = counter(
+        "coral_runtime_worker_busy_seconds",
+        "total time of worker busy polling tasks",
+    )?;
+    let polls = counter("coral_runtime_worker_polls", "total polls of worker")?;
+    let local_queue = gauge(
+        "coral_runtime_worker_local_queue_depth",
+        "tasks in local queue of worker",
+    )?;
+    let tasks = gauge("coral_runtime_tasks", "alive tasks by name")?;
+    for rt in report.runtimes.iter() {
+        let labels = [("runtime", rt.name.as_str())];
+        workers.set(rt.workers as f64, &labels);
+        alive.set(rt.alive_tasks as f64, &labels);
+        if let Some(v) = rt.global_queue_depth {
+            global_queue.set(v as f64, &labels);
+        }
+        if let Some(v) = rt.blocking_threads {
+            blocking.set(v as f64, &labels);
+        }
+        for (ix, worker) in rt.worker_stats.iter().enumerate() {
+            let ix = ix.to_string();
+            let labels = [("runtime", rt.name.as_str()), ("worker", ix.as_str())];
+            busy.set_total(worker.busy_secs, &labels);
+            polls.set_total(worker.polls as f64, &labels);
+            local_queue.set(worker.local_queue_depth as f64, &labels);
+        }
+    }
+    for (name, count) in report.tasks.iter() {
+        tasks.set(*count as f64, &[("task", name)]);
+    }
+    Ok(())
+}
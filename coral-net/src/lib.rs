@@ -1,8 +1,11 @@
+pub mod admin;
 pub mod client;
 pub mod db;
 pub mod error;
 pub mod hand;
+pub mod limit;
 pub mod midware;
+pub mod propagation;
 pub mod server;
 pub mod tcp;
 pub mod tls;
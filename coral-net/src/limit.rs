@@ -0,0 +1,238 @@
+//! limits of server connections
+//!
+//! Bounds of [`crate::server::ServerConf`] shared by h1, h2 and h3, so that
+//! slow or greedy clients can not hold connections or memory forever.
+use std::collections::HashMap;
+use std::hash::BuildHasher;
+use std::hash::RandomState;
+use std::net::IpAddr;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::MutexGuard;
+use std::sync::PoisonError;
+use std::time::Duration;
+
+use coral_runtime::tokio::sync::Notify;
+use coral_runtime::tokio::time::sleep_until;
+use coral_runtime::tokio::time::Instant;
+
+use crate::server::ServerConf;
+
+/// Limits of connections taken from [`ServerConf`], with defaults
+#[derive(Clone, Debug)]
+pub struct Limits {
+    pub header_read_timeout: Option<Duration>,
+    pub max_header_size: Option<usize>,
+    pub max_body_size: Option<usize>,
+    pub keep_alive_timeout: Duration,
+    pub max_concurrent_streams: Option<u32>,
+    pub tls_handshake_timeout: Duration,
+    pub per_ip: Option<ConnLimiter>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            header_read_timeout: None,
+            max_header_size: None,
+            max_body_size: None,
+            keep_alive_timeout: Duration::from_secs(3600),
+            max_concurrent_streams: None,
+            tls_handshake_timeout: Duration::from_secs(10),
+            per_ip: None,
+        }
+    }
+}
+
+impl From<&ServerConf> for Limits {
+    fn from(conf: &ServerConf) -> Self {
+        let default = Self::default();
+        Self {
+            header_read_timeout: conf.header_read_timeout,
+            max_header_size: conf.max_header_size,
+            max_body_size: conf.max_body_size,
+            keep_alive_timeout: conf
+                .keep_alive_timeout
+                .unwrap_or(default.keep_alive_timeout),
+            max_concurrent_streams: conf.max_concurrent_streams,
+            tls_handshake_timeout: conf
+                .tls_handshake_timeout
+                .unwrap_or(default.tls_handshake_timeout),
+            per_ip: conf.max_connections_per_ip.map(ConnLimiter::new),
+        }
+    }
+}
+
+impl Limits {
+    /// transport of h3 bounded by keep alive and concurrent streams
+    pub fn transport_config(&self) -> quinn_proto::TransportConfig {
+        let mut config = quinn_proto::TransportConfig::default();
+        if let Ok(timeout) = quinn_proto::IdleTimeout::try_from(self.keep_alive_timeout) {
+            config.max_idle_timeout(Some(timeout));
+        }
+        if let Some(max) = self.max_concurrent_streams {
+            config.max_concurrent_bidi_streams(quinn_proto::VarInt::from_u32(max));
+        }
+        config
+    }
+}
+
+/// shards of [`ConnLimiter`], so that accepts on cores seldom contend
+const SHARDS: usize = 64;
+
+type Shards = Arc<[Mutex<HashMap<IpAddr, usize>>]>;
+
+/// Count of connections by client ip, shared by clones. Build one per
+/// [`ServerConf`] and clone it into the listeners and cores counting together.
+#[derive(Clone, Debug)]
+pub struct ConnLimiter {
+    max: usize,
+    hasher: RandomState,
+    shards: Shards,
+}
+
+impl ConnLimiter {
+    pub fn new(max: usize) -> Self {
+        Self {
+            max,
+            hasher: RandomState::new(),
+            shards: (0..SHARDS).map(|_| Mutex::default()).collect(),
+        }
+    }
+
+    fn shard(&self, ip: IpAddr) -> usize {
+        self.hasher.hash_one(ip) as usize % self.shards.len()
+    }
+
+    /// count a connection from `ip` till the guard dropped, none if `ip`
+    /// already has max connections
+    pub fn acquire(&self, ip: IpAddr) -> Option<ConnGuard> {
+        let shard = self.shard(ip);
+        let mut conns = lock(&self.shards[shard]);
+        let count = conns.entry(ip).or_default();
+        if *count >= self.max {
+            return None;
+        }
+        *count += 1;
+        Some(ConnGuard {
+            ip,
+            shard,
+            shards: self.shards.clone(),
+        })
+    }
+
+    /// connections of `ip`
+    pub fn count(&self, ip: IpAddr) -> usize {
+        let conns = lock(&self.shards[self.shard(ip)]);
+        conns.get(&ip).cloned().unwrap_or(0)
+    }
+}
+
+/// Lock ignoring poison, counts change by one under the lock and stay
+/// consistent whatever panicked
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+pub struct ConnGuard {
+    ip: IpAddr,
+    shard: usize,
+    shards: Shards,
+}
+
+impl Drop for ConnGuard {
+    fn drop(&mut self) {
+        let mut conns = lock(&self.shards[self.shard]);
+        if let Some(count) = conns.get_mut(&self.ip) {
+            *count -= 1;
+            if *count == 0 {
+                conns.remove(&self.ip);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct IdleState {
+    in_flight: AtomicUsize,
+    last: Mutex<Option<Instant>>,
+    /// notified once a request finished
+    finished: Notify,
+}
+
+/// Requests in flight and last activity of a connection
+#[derive(Clone, Debug)]
+pub struct Idle {
+    state: Arc<IdleState>,
+    start: Instant,
+}
+
+impl Default for Idle {
+    fn default() -> Self {
+        Self {
+            state: Arc::default(),
+            start: Instant::now(),
+        }
+    }
+}
+
+impl Idle {
+    /// count a request in flight till the guard dropped
+    pub fn request(&self) -> RequestGuard {
+        self.state.in_flight.fetch_add(1, Ordering::SeqCst);
+        RequestGuard(self.clone())
+    }
+
+    /// Wait until no request in flight for `keep_alive`, or for
+    /// `first_request` if no request arrived yet
+    pub async fn expired(&self, first_request: Option<Duration>, keep_alive: Duration) {
+        let sleep = sleep_until(self.start + first_request.unwrap_or(keep_alive));
+        let mut sleep = std::pin::pin!(sleep);
+        loop {
+            let finished = self.state.finished.notified();
+            let mut finished = std::pin::pin!(finished);
+            finished.as_mut().enable();
+            if self.state.in_flight.load(Ordering::SeqCst) > 0 {
+                finished.await;
+                continue;
+            }
+            if let Some(last) = *lock(&self.state.last) {
+                sleep.as_mut().reset(last + keep_alive);
+            }
+            coral_runtime::tokio::select! {
+                _ = sleep.as_mut() => {
+                    // a request may have started meanwhile
+                    if self.state.in_flight.load(Ordering::SeqCst) == 0 {
+                        return;
+                    }
+                }
+                _ = finished => {}
+            }
+        }
+    }
+
+    /// wait until no request in flight
+    pub async fn drained(&self) {
+        loop {
+            let finished = self.state.finished.notified();
+            let mut finished = std::pin::pin!(finished);
+            finished.as_mut().enable();
+            if self.state.in_flight.load(Ordering::SeqCst) == 0 {
+                return;
+            }
+            finished.await;
+        }
+    }
+}
+
+pub struct RequestGuard(Idle);
+
+impl Drop for RequestGuard {
+    fn drop(&mut self) {
+        *lock(&self.0.state.last) = Some(Instant::now());
+        self.0.state.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.0.state.finished.notify_waiters();
+    }
+}
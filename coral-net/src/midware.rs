@@ -1,27 +1,100 @@
 //! tower middleware
+pub mod ratelimit;
+
 use std::future::Future;
 use std::pin::Pin;
 
 use axum::extract::Request;
-use axum::http::HeaderMap;
-use axum::http::HeaderValue;
-use fastrace::future::FutureExt;
+use coral_conf::EnvAssignToml;
+use coral_log::traces::Decision;
+use coral_log::traces::DROP_PROPERTY;
+use coral_macro::EnvAssign;
+use coral_macro::Schema;
+use coral_macro::Validate;
+use coral_runtime::supervise::CancellationToken;
 use fastrace::prelude::*;
+use serde::Deserialize;
+use serde::Serialize;
 use tower::Layer;
 use tower::Service;
+use tower_http::compression::predicate::Predicate;
+use tower_http::compression::predicate::SizeAbove;
+use tower_http::compression::Compression;
+use tower_http::decompression::RequestDecompression;
+use tower_http::decompression::RequestDecompressionLayer;
+use tower_http::limit::RequestBodyLimit;
+use tower_http::limit::RequestBodyLimitLayer;
+
+use crate::error::CoralRes;
+use crate::error::Error;
+use crate::propagation;
+use crate::propagation::TraceParent;
+use crate::server::ServerName;
 
-use super::HTTP_HEADER_TRACE_ID;
-use crate::HTTP_HEADER_SPAN_ID;
+fn protocol_version(version: axum::http::Version) -> &'static str {
+    match version {
+        axum::http::Version::HTTP_09 => "0.9",
+        axum::http::Version::HTTP_10 => "1.0",
+        axum::http::Version::HTTP_2 => "2",
+        axum::http::Version::HTTP_3 => "3",
+        _ => "1.1",
+    }
+}
+
+/// root span named by method and matched route, with http semantic convention
+/// attributes of the request
+fn root_span(req: &Request, ctx: SpanContext) -> Span {
+    let method = req.method().to_string();
+    let route = req
+        .extensions()
+        .get::<axum::extract::MatchedPath>()
+        .map(|v| v.as_str().to_owned());
+    let name = match route.as_ref() {
+        Some(route) => format!("{} {}", method, route),
+        None => method.clone(),
+    };
+    let mut properties = vec![
+        ("http.request.method", method),
+        ("url.path", req.uri().path().to_owned()),
+        ("network.protocol.name", String::from("http")),
+        (
+            "network.protocol.version",
+            protocol_version(req.version()).to_owned(),
+        ),
+    ];
+    if let Some(route) = route {
+        properties.push(("http.route", route));
+    }
+    if let Some(addr) = req
+        .extensions()
+        .get::<axum::extract::ConnectInfo<std::net::SocketAddr>>()
+    {
+        properties.push(("client.address", addr.0.ip().to_string()));
+        properties.push(("client.port", addr.0.port().to_string()));
+    }
+    if let Some(agent) = req
+        .headers()
+        .get(axum::http::header::USER_AGENT)
+        .and_then(|v| v.to_str().ok())
+    {
+        properties.push(("user_agent.original", agent.to_owned()));
+    }
+    Span::root(name, ctx).with_properties(|| properties)
+}
 
 /// midware for add trace id
+///
+/// Root spans are sampled by [`coral_log::traces::sampler`], unsampled requests
+/// are not recorded unless `always_on_error` is set, in which case they are
+/// reported only if the response is a server error.
 #[derive(Clone)]
 pub struct TraceMidware<S> {
     inner: S,
 }
 
-impl<S> Service<Request> for TraceMidware<S>
+impl<S, B> Service<Request> for TraceMidware<S>
 where
-    S: Service<Request> + Send + 'static,
+    S: Service<Request, Response = axum::http::Response<B>> + Send + 'static,
     S::Future: Send + 'static,
 {
     type Response = S::Response;
@@ -39,90 +112,765 @@ where
     }
 
     fn call(&mut self, mut req: Request) -> Self::Future {
-        let header = req.headers_mut();
-        let res = match header.get(HTTP_HEADER_TRACE_ID) {
-            Some(val) => val
-                .to_str()
-                .map(|v| TraceId::from(CoralTraceId::from(v)))
-                .map_err(|err| {
-                    log::error!(e = err.to_string(); "failed to convert trace header value to str");
-                }),
-            None => {
-                let trace_id = uuid::Uuid::new_v4().to_string();
-                HeaderValue::from_str(trace_id.as_str()).map(|v| {
-                    header.insert(HTTP_HEADER_TRACE_ID, v);
-                    TraceId::from(CoralTraceId::from(trace_id.as_str()))
-                }).map_err(|err| {
-                        log::error!(e = err.to_string(); "failed to convert uuid bytes to header value");
-                    })
-            }
+        let upstream = propagation::extract(req.headers());
+        let trace_id = upstream
+            .as_ref()
+            .map(|v| v.trace_id)
+            .unwrap_or_else(|| SpanContext::random().trace_id);
+        let parent_id = upstream.as_ref().map(|v| v.span_id).unwrap_or(SpanId(0));
+        let decision = coral_log::traces::sampler()
+            .sample(trace_id.0, upstream.as_ref().and_then(|v| v.sampled));
+        // propagated to downstream if there is no local span
+        let span_id = match parent_id.0 {
+            0 => SpanContext::random().span_id,
+            _ => parent_id,
         };
-        let span_id = match header.get(HTTP_HEADER_SPAN_ID) {
-            Some(val) => match val.to_str() {
-                Ok(v) => match v.parse::<u64>() {
-                    Ok(id) => Some(SpanId(id)),
-                    Err(err) => {
-                        log::error!(e = err.to_string(); "failed to convert span header str to u64");
-                        None
+        req.extensions_mut().insert(TraceParent {
+            trace_id,
+            span_id,
+            sampled: Some(decision == Decision::Sample),
+            trace_state: upstream.and_then(|v| v.trace_state),
+        });
+        if decision == Decision::Drop {
+            return Box::pin(self.inner.call(req));
+        }
+        let mut root_ctx = SpanContext::random();
+        root_ctx.trace_id = trace_id;
+        root_ctx.span_id = parent_id;
+        let mut root_span = root_span(&req, root_ctx);
+        let fut = {
+            let _guard = root_span.set_local_parent();
+            self.inner.call(req)
+        };
+        Box::pin(async move {
+            let mut fut = std::pin::pin!(fut);
+            let rsp = std::future::poll_fn(|cx| {
+                let _guard = root_span.set_local_parent();
+                fut.as_mut().poll(cx)
+            })
+            .await;
+            let status = rsp.as_ref().ok().map(|v| v.status());
+            if let Some(status) = status {
+                root_span = root_span
+                    .with_property(|| ("http.response.status_code", status.as_u16().to_string()));
+            }
+            match status {
+                Some(v) if !v.is_server_error() => {
+                    if decision == Decision::RecordOnError {
+                        root_span = root_span.with_property(|| (DROP_PROPERTY, "true"));
                     }
-                },
+                }
+                _ => {
+                    let error_type = status
+                        .map(|v| v.as_u16().to_string())
+                        .unwrap_or_else(|| String::from("_OTHER"));
+                    root_span = root_span.with_property(|| ("error.type", error_type));
+                }
+            }
+            drop(root_span);
+            rsp
+        })
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct TraceLayer;
+
+impl<S> Layer<S> for TraceLayer {
+    type Service = TraceMidware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Self::Service { inner }
+    }
+}
+
+/// size buckets of request and response body in bytes
+static SIZE_BUCKETS: [f64; 8] = [
+    64.0, 256.0, 1024.0, 4096.0, 16384.0, 65536.0, 262144.0, 1048576.0,
+];
+
+struct HttpMetrics {
+    requests: coral_log::metrics::Counter,
+    duration: coral_log::metrics::Histogram,
+    in_flight: coral_log::metrics::Gauge,
+    request_size: coral_log::metrics::Histogram,
+    response_size: coral_log::metrics::Histogram,
+}
+
+impl HttpMetrics {
+    fn new() -> Result<Self, coral_log::error::Error> {
+        Ok(Self {
+            requests: coral_log::metrics::counter(
+                "http_server_requests_total",
+                "total http requests",
+            )?,
+            duration: coral_log::metrics::histogram(
+                "http_server_request_duration_seconds",
+                "http request latency in seconds",
+                None,
+            )?,
+            in_flight: coral_log::metrics::gauge(
+                "http_server_requests_in_flight",
+                "http requests being served",
+            )?,
+            request_size: coral_log::metrics::histogram(
+                "http_server_request_body_bytes",
+                "http request body size in bytes",
+                Some(&SIZE_BUCKETS),
+            )?,
+            response_size: coral_log::metrics::histogram(
+                "http_server_response_body_bytes",
+                "http response body size in bytes",
+                Some(&SIZE_BUCKETS),
+            )?,
+        })
+    }
+
+    fn get() -> Option<&'static Self> {
+        static METRICS: std::sync::OnceLock<Option<HttpMetrics>> = std::sync::OnceLock::new();
+        METRICS
+            .get_or_init(|| match Self::new() {
+                Ok(m) => Some(m),
                 Err(err) => {
-                    log::error!(e = err.to_string(); "failed to convert span header value to str");
+                    log::error!(e = format!("{:?}", err); "failed to register http metrics");
                     None
                 }
-            },
-            None => None,
-        };
-        if let Ok(trace_id) = res {
-            let mut root_ctx = SpanContext::random();
-            root_ctx.trace_id = trace_id;
-            if let Some(id) = span_id {
-                root_ctx.span_id = id;
+            })
+            .as_ref()
+    }
+}
+
+fn version_label(version: axum::http::Version) -> &'static str {
+    match version {
+        axum::http::Version::HTTP_2 => "h2",
+        axum::http::Version::HTTP_3 => "h3",
+        _ => "h1",
+    }
+}
+
+fn status_class(status: axum::http::StatusCode) -> &'static str {
+    match status.as_u16() / 100 {
+        1 => "1xx",
+        2 => "2xx",
+        3 => "3xx",
+        4 => "4xx",
+        _ => "5xx",
+    }
+}
+
+pin_project_lite::pin_project! {
+    /// body counting bytes of data frames, `done` is called with the size on drop
+    struct SizeBody<B> {
+        #[pin]
+        inner: B,
+        size: u64,
+        done: Option<Box<dyn FnOnce(u64) + Send>>,
+    }
+
+    impl<B> PinnedDrop for SizeBody<B> {
+        fn drop(this: Pin<&mut Self>) {
+            let this = this.project();
+            if let Some(f) = this.done.take() {
+                f(*this.size);
             }
-            let root_span = Span::root("trace_id", root_ctx);
+        }
+    }
+}
 
-            let fut = self.inner.call(req).in_span(root_span);
-            Box::pin(async move { fut.await })
-        } else {
-            let fut = self.inner.call(req);
-            Box::pin(async move { fut.await })
+impl<B> SizeBody<B> {
+    fn new(inner: B, done: Box<dyn FnOnce(u64) + Send>) -> Self {
+        Self {
+            inner,
+            size: 0,
+            done: Some(done),
+        }
+    }
+}
+
+impl<B> http_body::Body for SizeBody<B>
+where
+    B: http_body::Body<Data = bytes::Bytes>,
+{
+    type Data = bytes::Bytes;
+
+    type Error = B::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Option<Result<http_body::Frame<Self::Data>, Self::Error>>> {
+        let this = self.project();
+        let frame = futures::ready!(this.inner.poll_frame(cx));
+        if let Some(data) = frame.as_ref().and_then(|v| v.as_ref().ok()?.data_ref()) {
+            *this.size += data.len() as u64;
         }
+        std::task::Poll::Ready(frame)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.inner.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.inner.size_hint()
+    }
+}
+
+/// decrease the in-flight gauge on drop, also if the request is cancelled
+struct InFlight(std::sync::Arc<coral_log::metrics::GaugeCell>);
+
+impl InFlight {
+    fn new(cell: std::sync::Arc<coral_log::metrics::GaugeCell>) -> Self {
+        cell.inc();
+        Self(cell)
+    }
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.dec();
+    }
+}
+
+/// midware for recording http server metrics
+///
+/// Requests are labeled by protocol version (h1, h2, h3), matched route and
+/// method, the route is `unmatched` if no route matched. Latency, status class
+/// and response size are recorded when the response body is dropped, so
+/// streaming responses are measured till the end.
+#[derive(Clone)]
+pub struct MetricsMidware<S> {
+    inner: S,
+}
+
+impl<S, B> Service<Request> for MetricsMidware<S>
+where
+    S: Service<Request, Response = axum::http::Response<B>> + Send + 'static,
+    S::Future: Send + 'static,
+    B: http_body::Body<Data = bytes::Bytes> + Send + 'static,
+    B::Error: Into<axum::BoxError>,
+{
+    type Response = axum::response::Response;
+
+    type Error = S::Error;
+
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let metrics = match HttpMetrics::get() {
+            Some(m) => m,
+            None => {
+                let fut = self.inner.call(req);
+                return Box::pin(
+                    async move { fut.await.map(|rsp| rsp.map(axum::body::Body::new)) },
+                );
+            }
+        };
+        let start = std::time::Instant::now();
+        let version = version_label(req.version());
+        let method = req.method().to_string();
+        let route = req
+            .extensions()
+            .get::<axum::extract::MatchedPath>()
+            .map(|v| v.as_str().to_owned())
+            .unwrap_or_else(|| String::from("unmatched"));
+        let labels = [
+            ("version", version),
+            ("route", route.as_str()),
+            ("method", method.as_str()),
+        ];
+        let in_flight = InFlight::new(metrics.in_flight.with(&labels));
+        let request_size = metrics.request_size.with(&labels);
+        let req = req.map(|body| {
+            axum::body::Body::new(SizeBody::new(
+                body,
+                Box::new(move |size| request_size.record(size as f64)),
+            ))
+        });
+        let fut = self.inner.call(req);
+        Box::pin(async move {
+            let rsp = fut.await?;
+            let status = status_class(rsp.status());
+            let done = move |size: u64| {
+                let labels = [
+                    ("version", version),
+                    ("route", route.as_str()),
+                    ("method", method.as_str()),
+                    ("status", status),
+                ];
+                metrics.requests.inc(&labels);
+                metrics
+                    .duration
+                    .record(start.elapsed().as_secs_f64(), &labels);
+                metrics.response_size.record(size as f64, &labels);
+                drop(in_flight);
+            };
+            Ok(rsp.map(|body| axum::body::Body::new(SizeBody::new(body, Box::new(done)))))
+        })
     }
 }
 
 #[derive(Clone, Default)]
-pub struct TraceLayer;
+pub struct MetricsLayer;
 
-impl<S> Layer<S> for TraceLayer {
-    type Service = TraceMidware<S>;
+impl<S> Layer<S> for MetricsLayer {
+    type Service = MetricsMidware<S>;
 
     fn layer(&self, inner: S) -> Self::Service {
         Self::Service { inner }
     }
 }
 
-struct CoralTraceId(u128);
+/// upstream chosen by proxy, inserted into response extensions for access log
+#[derive(Clone, Debug)]
+pub struct Upstream(pub String);
+
+/// fields of access log record
+pub static ACCESS_LOG_FIELDS: [&str; 11] = [
+    "method",
+    "path",
+    "protocol",
+    "status",
+    "latency_ms",
+    "bytes_in",
+    "bytes_out",
+    "peer_addr",
+    "sni",
+    "trace_id",
+    "upstream",
+];
+
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate, Debug, Clone)]
+pub struct AccessLogConf {
+    /// ratio of logged requests in `[0, 1]`, default 1
+    #[validate(range(min = 0.0, max = 1.0))]
+    sample_ratio: Option<f64>,
+    /// log every response with status 4xx or 5xx, default true
+    always_on_error: Option<bool>,
+    /// fields of each record, default all of [`ACCESS_LOG_FIELDS`]
+    #[validate(custom = "Self::check_fields")]
+    fields: Option<Vec<String>>,
+}
+
+impl AccessLogConf {
+    fn check_fields(fields: &[String]) -> CoralRes<()> {
+        Self::mask(fields).map(|_| ())
+    }
+
+    fn mask(fields: &[String]) -> CoralRes<u32> {
+        fields.iter().try_fold(0, |mask, field| {
+            match ACCESS_LOG_FIELDS.iter().position(|v| v == field) {
+                Some(ix) => Ok(mask | 1 << ix),
+                None => Err(Error::InvalidAccessLogField(field.to_owned())),
+            }
+        })
+    }
+
+    fn field_mask(&self) -> CoralRes<u32> {
+        match self.fields.as_ref() {
+            Some(fields) => Self::mask(fields),
+            None => Ok(u32::MAX),
+        }
+    }
+
+    pub fn layer(&self) -> CoralRes<AccessLogLayer> {
+        Ok(AccessLogLayer {
+            threshold: match self.sample_ratio.unwrap_or(1.0) {
+                v if v >= 1.0 => u64::MAX,
+                v => (v.max(0.0) * u64::MAX as f64) as u64,
+            },
+            always_on_error: self.always_on_error.unwrap_or(true),
+            fields: self.field_mask()?,
+        })
+    }
+}
+
+struct AccessEntry {
+    fields: u32,
+    method: String,
+    path: String,
+    protocol: String,
+    peer_addr: Option<String>,
+    sni: Option<ServerName>,
+    trace_id: Option<String>,
+    bytes_in: std::sync::Arc<std::sync::atomic::AtomicU64>,
+    start: std::time::Instant,
+}
+
+impl AccessEntry {
+    fn emit(self, status: u16, bytes_out: u64, upstream: Option<String>) {
+        let latency = self.start.elapsed().as_secs_f64() * 1000.0;
+        let bytes_in = self.bytes_in.load(std::sync::atomic::Ordering::Relaxed);
+        let values: [Option<log::kv::Value>; 11] = [
+            Some(self.method.as_str().into()),
+            Some(self.path.as_str().into()),
+            Some(self.protocol.as_str().into()),
+            Some(status.into()),
+            Some(latency.into()),
+            Some(bytes_in.into()),
+            Some(bytes_out.into()),
+            self.peer_addr.as_deref().map(Into::into),
+            self.sni.as_ref().map(|v| v.0.as_ref().into()),
+            self.trace_id.as_deref().map(Into::into),
+            upstream.as_deref().map(Into::into),
+        ];
+        let kvs: Vec<(&str, log::kv::Value)> = ACCESS_LOG_FIELDS
+            .iter()
+            .zip(values)
+            .enumerate()
+            .filter(|(ix, _)| self.fields & 1 << ix != 0)
+            .filter_map(|(_, (k, v))| v.map(|v| (*k, v)))
+            .collect();
+        log::logger().log(
+            &log::Record::builder()
+                .args(format_args!("{} {} {}", self.method, self.path, status))
+                .level(log::Level::Info)
+                .target("coral::access")
+                .key_values(&kvs)
+                .build(),
+        );
+    }
+}
+
+/// midware writing one access log record per request
+///
+/// The record is written to target `coral::access` when the response body is
+/// dropped, so latency and bytes out cover the whole body.
+#[derive(Clone)]
+pub struct AccessLogMidware<S> {
+    inner: S,
+    layer: AccessLogLayer,
+}
+
+impl<S, B> Service<Request> for AccessLogMidware<S>
+where
+    S: Service<Request, Response = axum::http::Response<B>> + Send + 'static,
+    S::Future: Send + 'static,
+    B: http_body::Body<Data = bytes::Bytes> + Send + 'static,
+    B::Error: Into<axum::BoxError>,
+{
+    type Response = axum::response::Response;
+
+    type Error = S::Error;
+
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let trace_id = req.extensions().get::<TraceParent>().map(|v| v.trace_id.0);
+        // sample by trace id, so access logs agree with traces
+        let sampled = self.layer.threshold == u64::MAX
+            || (trace_id.unwrap_or_else(|| SpanContext::random().trace_id.0) as u64)
+                < self.layer.threshold;
+        let always_on_error = self.layer.always_on_error;
+        if !sampled && !always_on_error {
+            let fut = self.inner.call(req);
+            return Box::pin(async move { fut.await.map(|rsp| rsp.map(axum::body::Body::new)) });
+        }
+        let path = match req.extensions().get::<axum::http::uri::PathAndQuery>() {
+            Some(v) => v.path().to_owned(),
+            None => req.uri().path().to_owned(),
+        };
+        let entry = AccessEntry {
+            fields: self.layer.fields,
+            method: req.method().to_string(),
+            path,
+            protocol: format!("HTTP/{}", protocol_version(req.version())),
+            peer_addr: req
+                .extensions()
+                .get::<axum::extract::ConnectInfo<std::net::SocketAddr>>()
+                .map(|v| v.0.to_string()),
+            sni: req.extensions().get::<ServerName>().cloned(),
+            trace_id: trace_id.map(|v| format!("{:032x}", v)),
+            bytes_in: Default::default(),
+            start: std::time::Instant::now(),
+        };
+        let bytes_in = entry.bytes_in.clone();
+        let req = req.map(|body| {
+            axum::body::Body::new(SizeBody::new(
+                body,
+                Box::new(move |size| bytes_in.store(size, std::sync::atomic::Ordering::Relaxed)),
+            ))
+        });
+        let fut = self.inner.call(req);
+        Box::pin(async move {
+            let rsp = fut.await?;
+            let status = rsp.status().as_u16();
+            if !sampled && status < 400 {
+                return Ok(rsp.map(axum::body::Body::new));
+            }
+            let upstream = rsp.extensions().get::<Upstream>().map(|v| v.0.clone());
+            let done = move |size: u64| entry.emit(status, size, upstream);
+            Ok(rsp.map(|body| axum::body::Body::new(SizeBody::new(body, Box::new(done)))))
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct AccessLogLayer {
+    threshold: u64,
+    always_on_error: bool,
+    fields: u32,
+}
+
+impl<S> Layer<S> for AccessLogLayer {
+    type Service = AccessLogMidware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Self::Service {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+/// encodings of [`CompressionConf`]
+pub static COMPRESSION_ENCODINGS: [&str; 3] = ["gzip", "br", "zstd"];
+
+/// prefixes of content types compressed by default
+pub static COMPRESSION_CONTENT_TYPES: [&str; 7] = [
+    "text/",
+    "application/json",
+    "application/problem+json",
+    "application/javascript",
+    "application/xml",
+    "application/wasm",
+    "image/svg+xml",
+];
+
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate, Debug, Clone)]
+pub struct CompressionConf {
+    /// encodings of responses chosen by `accept-encoding` and of requests
+    /// decompressed by `content-encoding`, default all of
+    /// [`COMPRESSION_ENCODINGS`]
+    #[validate(custom = "Self::check_encodings")]
+    encodings: Option<Vec<String>>,
+    /// responses smaller than it in bytes are not compressed, default 1024
+    min_size: Option<u16>,
+    /// prefixes of compressed content types, default
+    /// [`COMPRESSION_CONTENT_TYPES`]
+    content_types: Option<Vec<String>>,
+    /// decompress request bodies, default true
+    decompress_requests: Option<bool>,
+}
+
+impl CompressionConf {
+    fn check_encodings(encodings: &[String]) -> CoralRes<()> {
+        match encodings
+            .iter()
+            .find(|v| !COMPRESSION_ENCODINGS.contains(&v.as_str()))
+        {
+            Some(v) => Err(Error::InvalidEncoding(v.to_owned())),
+            None => Ok(()),
+        }
+    }
+
+    fn encoding(&self, encoding: &str) -> bool {
+        self.encodings
+            .as_ref()
+            .map_or(true, |v| v.iter().any(|v| v == encoding))
+    }
+
+    /// Layer of `self`, request bodies are bounded by `max_body_size` once
+    /// decompressed, a small compressed body may inflate far beyond the limit
+    /// of the listener.
+    pub fn layer(&self, max_body_size: Option<usize>) -> CoralRes<CompressionLayer> {
+        if let Some(encodings) = self.encodings.as_ref() {
+            Self::check_encodings(encodings)?;
+        }
+        let content_types = match self.content_types.as_ref() {
+            Some(v) => v.clone(),
+            None => COMPRESSION_CONTENT_TYPES.map(String::from).to_vec(),
+        };
+        let predicate = CompressPredicate {
+            size: SizeAbove::new(self.min_size.unwrap_or(1024)),
+            content_types: content_types.into(),
+        };
+        let (gzip, br, zstd) = (
+            self.encoding("gzip"),
+            self.encoding("br"),
+            self.encoding("zstd"),
+        );
+        let decompress = self.decompress_requests.unwrap_or(true);
+        Ok(CompressionLayer {
+            compression: tower_http::compression::CompressionLayer::new()
+                .gzip(gzip)
+                .br(br)
+                .zstd(zstd)
+                .no_deflate()
+                .compress_when(predicate),
+            decompression: RequestDecompressionLayer::new()
+                .gzip(decompress && gzip)
+                .br(decompress && br)
+                .zstd(decompress && zstd)
+                .no_deflate()
+                // bodies are passed as is if not decompressed
+                .pass_through_unaccepted(!decompress),
+            limit: RequestBodyLimitLayer::new(max_body_size.unwrap_or(usize::MAX)),
+        })
+    }
+}
+
+/// compress responses of allowed content types and at least min size
+#[derive(Clone)]
+pub struct CompressPredicate {
+    size: SizeAbove,
+    content_types: std::sync::Arc<[String]>,
+}
+
+impl Predicate for CompressPredicate {
+    fn should_compress<B>(&self, response: &axum::http::Response<B>) -> bool
+    where
+        B: http_body::Body,
+    {
+        let content_type = response
+            .headers()
+            .get(axum::http::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .unwrap_or_default();
+        // events are flushed one by one, not worth buffering by encoders
+        !content_type.starts_with("text/event-stream")
+            && self
+                .content_types
+                .iter()
+                .any(|v| content_type.starts_with(v.as_str()))
+            && self.size.should_compress(response)
+    }
+}
+
+/// compression of responses and decompression of requests by
+/// [`CompressionConf`], for h2 and h3 alike
+#[derive(Clone)]
+pub struct CompressionLayer {
+    compression: tower_http::compression::CompressionLayer<CompressPredicate>,
+    decompression: RequestDecompressionLayer,
+    limit: RequestBodyLimitLayer,
+}
 
-impl std::convert::From<CoralTraceId> for TraceId {
-    fn from(value: CoralTraceId) -> Self {
-        Self(value.0)
+impl<S> Layer<S> for CompressionLayer {
+    type Service = Compression<RequestDecompression<RequestBodyLimit<S>>, CompressPredicate>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        self.compression
+            .layer(self.decompression.layer(self.limit.layer(inner)))
     }
 }
 
-impl<'a> std::convert::From<&'a str> for CoralTraceId {
-    fn from(value: &'a str) -> Self {
-        let mut u128byte = [0u8; 16];
-        let byte = value.as_bytes();
-        u128byte
-            .iter_mut()
-            .zip(byte.iter())
-            .for_each(|(v0, v1)| *v0 = *v1);
-        Self(u128::from_be_bytes(u128byte))
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate, Debug, Clone, Default)]
+pub struct AltSvcConf {
+    /// advertise h3 by `Alt-Svc`, default true
+    enabled: Option<bool>,
+    /// seconds clients remember the advertisement, default 86400
+    max_age: Option<u64>,
+}
+
+impl AltSvcConf {
+    /// Advertise h3 protocols of `alpn` on `port`, or `h3` if `alpn` has
+    /// none of them. `Alt-Svc: clear` is sent once `drain` is cancelled.
+    pub fn layer(
+        &self,
+        port: u16,
+        alpn: &[String],
+        drain: CancellationToken,
+    ) -> CoralRes<Option<AltSvcLayer>> {
+        if !self.enabled.unwrap_or(true) {
+            return Ok(None);
+        }
+        let max_age = self.max_age.unwrap_or(86400);
+        let mut protocols: Vec<&str> = alpn
+            .iter()
+            .map(String::as_str)
+            .filter(|v| v.starts_with("h3"))
+            .collect();
+        if protocols.is_empty() {
+            protocols.push("h3");
+        }
+        let value = protocols
+            .iter()
+            .map(|v| format!("{}=\":{}\"; ma={}", v, port, max_age))
+            .collect::<Vec<_>>()
+            .join(", ");
+        Ok(Some(AltSvcLayer {
+            value: axum::http::HeaderValue::from_str(&value)?,
+            drain,
+        }))
     }
 }
 
-pub fn add_header_span_id(header: &mut HeaderMap) {
-    if let Some(span) = SpanContext::current_local_parent() {
-        header.insert(HTTP_HEADER_SPAN_ID, HeaderValue::from(span.span_id.0));
+/// midware adding `Alt-Svc` to responses without one
+#[derive(Clone)]
+pub struct AltSvcMidware<S> {
+    inner: S,
+    layer: AltSvcLayer,
+}
+
+impl<S, B> Service<Request> for AltSvcMidware<S>
+where
+    S: Service<Request, Response = axum::http::Response<B>> + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+
+    type Error = S::Error;
+
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let fut = self.inner.call(req);
+        let layer = self.layer.clone();
+        Box::pin(async move {
+            let mut rsp = fut.await?;
+            // clients stop switching to h3 of a draining server
+            let value = match layer.drain.is_cancelled() {
+                true => axum::http::HeaderValue::from_static("clear"),
+                false => layer.value,
+            };
+            if let axum::http::header::Entry::Vacant(entry) =
+                rsp.headers_mut().entry(axum::http::header::ALT_SVC)
+            {
+                entry.insert(value);
+            }
+            Ok(rsp)
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct AltSvcLayer {
+    value: axum::http::HeaderValue,
+    drain: CancellationToken,
+}
+
+impl<S> Layer<S> for AltSvcLayer {
+    type Service = AltSvcMidware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Self::Service {
+            inner,
+            layer: self.clone(),
+        }
     }
 }
@@ -0,0 +1,583 @@
+//! rate limit midware
+//!
+//! Requests are counted by the rules of [`RateLimitConf`], either in memory
+//! of the process or in redis to share the counters between instances.
+//! Requests over a limit are answered with 429 and `Retry-After`.
+use std::collections::HashMap;
+use std::future::Future;
+use std::net::IpAddr;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::PoisonError;
+use std::time::Duration;
+use std::time::Instant;
+
+use axum::extract::Request;
+use axum::response::IntoResponse;
+use coral_conf::EnvAssignToml;
+use coral_macro::EnvAssign;
+use coral_macro::Schema;
+use coral_macro::Validate;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::Layer;
+use tower::Service;
+
+use crate::db::RedisClient;
+use crate::error::CoralRes;
+use crate::server::PeerIdentity;
+
+/// Count a request by every counter of `KEYS`, with the algorithm and its two
+/// arguments of each key in `ARGV`. Counters are changed only if all allow
+/// the request, otherwise the most milliseconds to wait is returned.
+const RATE_LIMIT_SCRIPT: &str = r#"
+local time = redis.call('TIME')
+local now = tonumber(time[1]) * 1000 + math.floor(tonumber(time[2]) / 1000)
+local retry = 0
+local counted = {}
+for i, key in ipairs(KEYS) do
+    local algorithm = ARGV[i * 3 - 2]
+    local a = tonumber(ARGV[i * 3 - 1])
+    local b = tonumber(ARGV[i * 3])
+    if algorithm == 'bucket' then
+        local rate, capacity = a, b
+        local state = redis.call('HMGET', key, 'tokens', 'at')
+        local tokens = tonumber(state[1]) or capacity
+        local at = tonumber(state[2]) or now
+        tokens = math.min(capacity, tokens + math.max(0, now - at) * rate)
+        if tokens >= 1 then
+            counted[i] = tokens - 1
+        else
+            retry = math.max(retry, math.ceil((1 - tokens) / rate))
+        end
+    else
+        local limit, period = a, b
+        local id = math.floor(now / period)
+        local elapsed = now - id * period
+        local prev = tonumber(redis.call('GET', key .. ':' .. (id - 1))) or 0
+        local curr = tonumber(redis.call('GET', key .. ':' .. id)) or 0
+        if prev * (1 - elapsed / period) + curr + 1 <= limit then
+            counted[i] = id
+        elseif curr >= limit then
+            retry = math.max(retry, period - elapsed + math.ceil(period * (1 - (limit - 1) / curr)), 1)
+        else
+            retry = math.max(retry, math.ceil(period * (1 - (limit - 1 - curr) / prev)) - elapsed, 1)
+        end
+    end
+end
+if retry > 0 then
+    return retry
+end
+for i, key in ipairs(KEYS) do
+    local a = tonumber(ARGV[i * 3 - 1])
+    local b = tonumber(ARGV[i * 3])
+    if ARGV[i * 3 - 2] == 'bucket' then
+        redis.call('HSET', key, 'tokens', tostring(counted[i]), 'at', now)
+        redis.call('PEXPIRE', key, math.ceil(b / a) + 1000)
+    else
+        local window = key .. ':' .. counted[i]
+        redis.call('INCR', window)
+        redis.call('PEXPIRE', window, b * 2)
+    end
+end
+return 0
+"#;
+
+/// algorithm of [`RateRule`]
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateAlgorithm {
+    /// `limit` tokens refilled per `period`, at most `burst` at once
+    TokenBucket,
+    /// at most `limit` requests in any `period`, weighted by the last period
+    SlidingWindow,
+}
+
+/// key of counters of [`RateRule`], requests without the key are not limited
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Debug, Clone, PartialEq, Eq)]
+pub enum RateKey {
+    /// client ip, forwarded by [`RateLimitConf::trusted_proxies`] if any
+    Ip,
+    /// fingerprint of the client certificate of mtls
+    Identity,
+    /// value of the request header
+    Header(String),
+    /// matched route, shared by all clients, one counter for all unmatched
+    Route,
+}
+
+/// store of counters of [`RateLimitConf`]
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RateStore {
+    /// counters of this process
+    Memory,
+    /// Counters shared by instances, with the [`RedisClient`] in request
+    /// extensions. Requests are allowed if redis fails. Counters of a
+    /// request are checked by one script, so all keys share the slot of
+    /// [`RateLimitConf`] prefix in a cluster.
+    Redis,
+}
+
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate, Debug, Clone)]
+pub struct RateRule {
+    /// path prefixes limited by the rule, default all
+    routes: Option<Vec<String>>,
+    key: RateKey,
+    /// default `TokenBucket`
+    algorithm: Option<RateAlgorithm>,
+    /// requests per period
+    #[validate(range(min = 1))]
+    limit: u64,
+    /// e.g. `1s` or `1m`, counted in milliseconds
+    #[serde(with = "coral_conf::duration")]
+    #[validate(custom = "Self::check_period")]
+    period: Duration,
+    /// tokens of a full bucket, default `limit`
+    #[validate(range(min = 1))]
+    burst: Option<u64>,
+}
+
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate, Debug, Clone)]
+pub struct RateLimitConf {
+    /// default `Memory`
+    store: Option<RateStore>,
+    /// prefix of redis keys, default `coral:ratelimit`
+    prefix: Option<String>,
+    /// Peers whose `Forwarded` or `X-Forwarded-For` header gives the client
+    /// ip of key `Ip`, default none, so that the headers are ignored
+    trusted_proxies: Option<Vec<IpAddr>>,
+    #[validate(nested)]
+    rules: Vec<RateRule>,
+}
+
+impl RateLimitConf {
+    pub fn store(&self) -> RateStore {
+        self.store.unwrap_or(RateStore::Memory)
+    }
+
+    pub fn layer(&self) -> CoralRes<RateLimitLayer> {
+        let rules = self
+            .rules
+            .iter()
+            .map(|rule| Rule {
+                conf: rule.clone(),
+                memory: Mutex::default(),
+            })
+            .collect();
+        Ok(RateLimitLayer(Arc::new(Limiter {
+            rules,
+            store: self.store(),
+            trusted_proxies: self.trusted_proxies.clone().unwrap_or_default(),
+            prefix: self
+                .prefix
+                .clone()
+                .unwrap_or_else(|| String::from("coral:ratelimit")),
+            start: Instant::now(),
+            script: redis::Script::new(RATE_LIMIT_SCRIPT),
+        })))
+    }
+}
+
+impl RateRule {
+    fn check_period(period: &Duration) -> Result<(), &'static str> {
+        match period.as_millis() {
+            0 => Err("period is less than 1ms"),
+            _ => Ok(()),
+        }
+    }
+
+    /// period in milliseconds
+    fn period(&self) -> u64 {
+        self.period.as_millis() as u64
+    }
+
+    fn algorithm(&self) -> RateAlgorithm {
+        self.algorithm.unwrap_or(RateAlgorithm::TokenBucket)
+    }
+
+    /// tokens refilled per millisecond
+    fn rate(&self) -> f64 {
+        self.limit as f64 / self.period() as f64
+    }
+
+    fn capacity(&self) -> f64 {
+        self.burst.unwrap_or(self.limit) as f64
+    }
+
+    /// `path` is under a prefix by segments, `/api` matches `/api/v1` but
+    /// not `/apix`
+    fn matches(&self, path: &str) -> bool {
+        self.routes.as_ref().map_or(true, |routes| {
+            routes
+                .iter()
+                .any(|prefix| match path.strip_prefix(prefix.as_str()) {
+                    Some(rest) => rest.is_empty() || prefix.ends_with('/') || rest.starts_with('/'),
+                    None => false,
+                })
+        })
+    }
+
+    fn key(&self, req: &Request, trusted_proxies: &[IpAddr]) -> Option<String> {
+        match &self.key {
+            RateKey::Ip => req
+                .extensions()
+                .get::<axum::extract::ConnectInfo<std::net::SocketAddr>>()
+                .map(|v| client_ip(req, v.0.ip(), trusted_proxies).to_string()),
+            RateKey::Identity => req
+                .extensions()
+                .get::<PeerIdentity>()
+                .map(|v| v.0.to_string()),
+            RateKey::Header(name) => req
+                .headers()
+                .get(name)
+                .and_then(|v| v.to_str().ok())
+                .map(ToOwned::to_owned),
+            // raw paths of unmatched requests would be counters made up by clients
+            RateKey::Route => Some(match req.extensions().get::<axum::extract::MatchedPath>() {
+                Some(v) => v.as_str().to_owned(),
+                None => String::from("unmatched"),
+            }),
+        }
+    }
+}
+
+/// Hops of `Forwarded`, or of `X-Forwarded-For` if none, from the client to
+/// the last proxy, none if any hop is not an ip
+fn forwarded_hops(req: &Request) -> Option<Vec<IpAddr>> {
+    let headers = req.headers();
+    let forwarded: Vec<&str> = headers
+        .get_all(axum::http::header::FORWARDED)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (k, v) = pair.split_once('=')?;
+                k.trim().eq_ignore_ascii_case("for").then_some(v.trim())
+            })
+        })
+        .collect();
+    let hops: Vec<&str> = match forwarded.is_empty() {
+        false => forwarded,
+        true => headers
+            .get_all("x-forwarded-for")
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(str::trim)
+            .collect(),
+    };
+    hops.into_iter().map(parse_hop).collect()
+}
+
+/// ip of `1.2.3.4`, `"[2001:db8::1]:4711"` or `1.2.3.4:80`
+fn parse_hop(hop: &str) -> Option<IpAddr> {
+    let hop = hop.trim_matches('"');
+    if let Ok(ip) = hop.parse() {
+        return Some(ip);
+    }
+    match hop.strip_prefix('[') {
+        Some(v) => v.split_once(']')?.0.parse().ok(),
+        None => hop.rsplit_once(':')?.0.parse().ok(),
+    }
+}
+
+/// The first hop from the right not of a trusted proxy, `peer` itself if
+/// not trusted or the headers are missing or malformed
+fn client_ip(req: &Request, peer: IpAddr, trusted_proxies: &[IpAddr]) -> IpAddr {
+    if !trusted_proxies.contains(&peer) {
+        return peer;
+    }
+    let Some(hops) = forwarded_hops(req) else {
+        return peer;
+    };
+    let mut client = peer;
+    for hop in hops.into_iter().rev() {
+        client = hop;
+        if !trusted_proxies.contains(&hop) {
+            break;
+        }
+    }
+    client
+}
+
+/// state of a token bucket, in milliseconds since the limiter created
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Bucket {
+    tokens: f64,
+    at: u64,
+}
+
+/// take a token from `bucket`, the milliseconds to wait if it is empty
+fn take_token(bucket: &mut Option<Bucket>, now: u64, rate: f64, capacity: f64) -> u64 {
+    let state = bucket.get_or_insert(Bucket {
+        tokens: capacity,
+        at: now,
+    });
+    state.tokens = capacity.min(state.tokens + now.saturating_sub(state.at) as f64 * rate);
+    state.at = now;
+    if state.tokens >= 1.0 {
+        state.tokens -= 1.0;
+        0
+    } else {
+        ((1.0 - state.tokens) / rate).ceil() as u64
+    }
+}
+
+/// counts of the current and the last window, windows are aligned to
+/// multiples of the period
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Window {
+    id: u64,
+    prev: u64,
+    curr: u64,
+}
+
+/// count a request in `window`, the milliseconds to wait if over `limit`
+fn count_window(window: &mut Option<Window>, now: u64, limit: u64, period: u64) -> u64 {
+    let id = now / period;
+    let state = window.get_or_insert(Window {
+        id,
+        prev: 0,
+        curr: 0,
+    });
+    if state.id != id {
+        state.prev = if state.id + 1 == id { state.curr } else { 0 };
+        state.curr = 0;
+        state.id = id;
+    }
+    let elapsed = (now - id * period) as f64;
+    let period = period as f64;
+    let (limit, prev, curr) = (limit as f64, state.prev as f64, state.curr as f64);
+    if prev * (1.0 - elapsed / period) + curr + 1.0 <= limit {
+        state.curr += 1;
+        return 0;
+    }
+    let retry = if curr >= limit {
+        // wait for the next window, where the current one is weighted
+        period - elapsed + (period * (1.0 - (limit - 1.0) / curr)).ceil()
+    } else {
+        (period * (1.0 - (limit - 1.0 - curr) / prev)).ceil() - elapsed
+    };
+    retry.max(1.0) as u64
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Counter {
+    Bucket(Option<Bucket>),
+    Window(Option<Window>),
+}
+
+#[derive(Default)]
+struct Memory {
+    counters: HashMap<String, Counter>,
+    swept: u64,
+}
+
+struct Rule {
+    conf: RateRule,
+    memory: Mutex<Memory>,
+}
+
+impl Rule {
+    /// counter of `key` having counted a request at `now`, with the
+    /// milliseconds to wait if over limit
+    fn count_memory(&self, memory: &mut Memory, key: &str, now: u64) -> (Counter, u64) {
+        let conf = &self.conf;
+        // drop counters idle for a while, they are as good as new
+        let idle = match conf.algorithm() {
+            RateAlgorithm::TokenBucket => (conf.capacity() / conf.rate()).ceil() as u64,
+            RateAlgorithm::SlidingWindow => conf.period() * 2,
+        };
+        if now.saturating_sub(memory.swept) >= idle {
+            memory.counters.retain(|_, counter| match counter {
+                Counter::Bucket(Some(v)) => now.saturating_sub(v.at) < idle,
+                Counter::Window(Some(v)) => now / conf.period() <= v.id + 1,
+                _ => false,
+            });
+            memory.swept = now;
+        }
+        let mut counter = match memory.counters.get(key) {
+            Some(v) => *v,
+            None => match conf.algorithm() {
+                RateAlgorithm::TokenBucket => Counter::Bucket(None),
+                RateAlgorithm::SlidingWindow => Counter::Window(None),
+            },
+        };
+        let retry = match &mut counter {
+            Counter::Bucket(v) => take_token(v, now, conf.rate(), conf.capacity()),
+            Counter::Window(v) => count_window(v, now, conf.limit, conf.period()),
+        };
+        (counter, retry)
+    }
+}
+
+struct Limiter {
+    rules: Vec<Rule>,
+    store: RateStore,
+    trusted_proxies: Vec<IpAddr>,
+    prefix: String,
+    start: Instant,
+    script: redis::Script,
+}
+
+impl Limiter {
+    /// Milliseconds to wait till all rules of `keys` allow the request, 0 if
+    /// allowed. Counters are only changed by allowed requests, so that a
+    /// request over one limit does not use up the others.
+    async fn check(&self, keys: Vec<(usize, String)>, client: Option<RedisClient>) -> u64 {
+        match (self.store, client) {
+            (RateStore::Redis, Some(client)) => match self.check_redis(keys, client).await {
+                Ok(retry) => retry,
+                Err(err) => {
+                    log::error!(e = format!("{:?}", err); "failed to check rate limit in redis");
+                    0
+                }
+            },
+            (RateStore::Redis, None) => {
+                log::warn!("rate limit store is redis, but no redis client of request");
+                0
+            }
+            (RateStore::Memory, _) => {
+                self.check_memory(keys, self.start.elapsed().as_millis() as u64)
+            }
+        }
+    }
+
+    fn check_memory(&self, keys: Vec<(usize, String)>, now: u64) -> u64 {
+        // locked in the order of rules, as `keys` are
+        let mut memories: Vec<_> = keys
+            .iter()
+            .map(|(ix, _)| {
+                self.rules[*ix]
+                    .memory
+                    .lock()
+                    .unwrap_or_else(PoisonError::into_inner)
+            })
+            .collect();
+        let counted: Vec<(Counter, u64)> = keys
+            .iter()
+            .zip(memories.iter_mut())
+            .map(|((ix, key), memory)| self.rules[*ix].count_memory(memory, key, now))
+            .collect();
+        let retry = counted.iter().map(|v| v.1).max().unwrap_or(0);
+        if retry == 0 {
+            let counted = keys.into_iter().zip(counted);
+            for (memory, ((_, key), (counter, _))) in memories.iter_mut().zip(counted) {
+                memory.counters.insert(key, counter);
+            }
+        }
+        retry
+    }
+
+    async fn check_redis(
+        &self,
+        keys: Vec<(usize, String)>,
+        mut client: RedisClient,
+    ) -> redis::RedisResult<u64> {
+        let mut invocation = self.script.prepare_invoke();
+        for (ix, key) in keys {
+            let conf = &self.rules[ix].conf;
+            // hash tag keeps keys of a request in one slot
+            invocation.key(format!("{{{}}}:{}:{}", self.prefix, ix, key));
+            match conf.algorithm() {
+                RateAlgorithm::TokenBucket => invocation
+                    .arg("bucket")
+                    .arg(conf.rate())
+                    .arg(conf.capacity()),
+                RateAlgorithm::SlidingWindow => {
+                    invocation.arg("window").arg(conf.limit).arg(conf.period())
+                }
+            };
+        }
+        invocation.invoke_async(&mut client).await
+    }
+}
+
+/// 429 with `Retry-After` in seconds
+fn too_many_requests(retry: u64) -> axum::response::Response {
+    let secs = retry.div_ceil(1000).to_string();
+    (
+        axum::http::StatusCode::TOO_MANY_REQUESTS,
+        [(axum::http::header::RETRY_AFTER, secs)],
+    )
+        .into_response()
+}
+
+/// midware limiting requests by [`RateLimitConf`]
+#[derive(Clone)]
+pub struct RateLimitMidware<S> {
+    inner: S,
+    limiter: Arc<Limiter>,
+}
+
+impl<S, B> Service<Request> for RateLimitMidware<S>
+where
+    S: Service<Request, Response = axum::http::Response<B>> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+    B: http_body::Body<Data = bytes::Bytes> + Send + 'static,
+    B::Error: Into<axum::BoxError>,
+{
+    type Response = axum::response::Response;
+
+    type Error = S::Error;
+
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(
+        &mut self,
+        cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request) -> Self::Future {
+        let path = req.uri().path();
+        let keys: Vec<(usize, String)> = self
+            .limiter
+            .rules
+            .iter()
+            .enumerate()
+            .filter(|(_, rule)| rule.conf.matches(path))
+            .filter_map(|(ix, rule)| {
+                rule.conf
+                    .key(&req, &self.limiter.trusted_proxies)
+                    .map(|key| (ix, key))
+            })
+            .collect();
+        if keys.is_empty() {
+            let fut = self.inner.call(req);
+            return Box::pin(async move { fut.await.map(|rsp| rsp.map(axum::body::Body::new)) });
+        }
+        let client = req.extensions().get::<RedisClient>().cloned();
+        let limiter = self.limiter.clone();
+        // the service driven ready by poll_ready is kept for this request
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        Box::pin(async move {
+            let retry = limiter.check(keys, client).await;
+            if retry > 0 {
+                return Ok(too_many_requests(retry));
+            }
+            inner
+                .call(req)
+                .await
+                .map(|rsp| rsp.map(axum::body::Body::new))
+        })
+    }
+}
+
+#[derive(Clone)]
+pub struct RateLimitLayer(Arc<Limiter>);
+
+impl<S> Layer<S> for RateLimitLayer {
+    type Service = RateLimitMidware<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Self::Service {
+            inner,
+            limiter: self.0.clone(),
+        }
+    }
+}
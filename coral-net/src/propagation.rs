@@ -0,0 +1,232 @@
+//! trace context propagation over http headers
+//!
+//! Extract from W3C `traceparent`/`tracestate`, B3 single `b3` and B3 multi
+//! `x-b3-*` headers, with the legacy `x-trace-id`/`x-span-id` still accepted.
+//! Inject as W3C and B3 single headers.
+use fastrace::collector::SpanContext;
+use fastrace::collector::SpanId;
+use fastrace::collector::TraceId;
+use hyper::header::HeaderMap;
+use hyper::header::HeaderValue;
+
+use crate::HTTP_HEADER_SPAN_ID;
+use crate::HTTP_HEADER_TRACE_ID;
+
+pub static HTTP_HEADER_TRACEPARENT: &str = "traceparent";
+pub static HTTP_HEADER_TRACESTATE: &str = "tracestate";
+pub static HTTP_HEADER_B3: &str = "b3";
+pub static HTTP_HEADER_B3_TRACE_ID: &str = "x-b3-traceid";
+pub static HTTP_HEADER_B3_SPAN_ID: &str = "x-b3-spanid";
+pub static HTTP_HEADER_B3_PARENT_SPAN_ID: &str = "x-b3-parentspanid";
+pub static HTTP_HEADER_B3_SAMPLED: &str = "x-b3-sampled";
+pub static HTTP_HEADER_B3_FLAGS: &str = "x-b3-flags";
+
+/// Trace context received from upstream or sent to downstream
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceParent {
+    pub trace_id: TraceId,
+    /// span id of the caller
+    pub span_id: SpanId,
+    /// sampling decision of the caller, none if deferred
+    pub sampled: Option<bool>,
+    /// vendor specific `tracestate`
+    pub trace_state: Option<String>,
+}
+
+impl TraceParent {
+    /// context of current local span, `upstream` provides trace state and
+    /// sampling decision, and is used if there is no local span
+    pub fn current(upstream: Option<&TraceParent>) -> Option<Self> {
+        let trace_state = upstream.and_then(|v| v.trace_state.clone());
+        match SpanContext::current_local_parent() {
+            Some(ctx) => Some(Self {
+                trace_id: ctx.trace_id,
+                span_id: ctx.span_id,
+                sampled: Some(upstream.and_then(|v| v.sampled).unwrap_or(true)),
+                trace_state,
+            }),
+            None => upstream.cloned(),
+        }
+    }
+
+    /// `00-{trace_id}-{span_id}-{flags}`
+    pub fn to_traceparent(&self) -> String {
+        let flags = if self.sampled.unwrap_or(false) { 1 } else { 0 };
+        format!(
+            "00-{:032x}-{:016x}-{:02x}",
+            self.trace_id.0, self.span_id.0, flags
+        )
+    }
+
+    /// `{trace_id}-{span_id}[-{sampled}]`
+    pub fn to_b3(&self) -> String {
+        let ids = format!("{:032x}-{:016x}", self.trace_id.0, self.span_id.0);
+        match self.sampled {
+            Some(true) => format!("{}-1", ids),
+            Some(false) => format!("{}-0", ids),
+            None => ids,
+        }
+    }
+}
+
+fn header_str<'a>(headers: &'a HeaderMap, key: &str) -> Option<&'a str> {
+    headers
+        .get(key)
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+}
+
+fn is_hex(s: &str) -> bool {
+    s.bytes()
+        .all(|b| b.is_ascii_digit() || (b'a'..=b'f').contains(&b))
+}
+
+/// parse lowercase hex trace id of 32 or 16 characters, zero is invalid
+fn parse_trace_id(s: &str) -> Option<TraceId> {
+    if (s.len() != 32 && s.len() != 16) || !is_hex(s) {
+        return None;
+    }
+    match u128::from_str_radix(s, 16) {
+        Ok(0) | Err(_) => None,
+        Ok(v) => Some(TraceId(v)),
+    }
+}
+
+/// parse lowercase hex span id of 16 characters, zero is invalid
+fn parse_span_id(s: &str) -> Option<SpanId> {
+    if s.len() != 16 || !is_hex(s) {
+        return None;
+    }
+    match u64::from_str_radix(s, 16) {
+        Ok(0) | Err(_) => None,
+        Ok(v) => Some(SpanId(v)),
+    }
+}
+
+fn parse_traceparent(value: &str) -> Option<TraceParent> {
+    let parts: Vec<&str> = value.split('-').collect();
+    if parts.len() < 4 || parts[0].len() != 2 || !is_hex(parts[0]) || parts[0] == "ff" {
+        return None;
+    }
+    // version 00 has exactly 4 fields, later versions may append fields
+    if parts[0] == "00" && parts.len() != 4 {
+        return None;
+    }
+    if parts[1].len() != 32 || parts[3].len() != 2 || !is_hex(parts[3]) {
+        return None;
+    }
+    let flags = u8::from_str_radix(parts[3], 16).ok()?;
+    Some(TraceParent {
+        trace_id: parse_trace_id(parts[1])?,
+        span_id: parse_span_id(parts[2])?,
+        sampled: Some(flags & 1 == 1),
+        trace_state: None,
+    })
+}
+
+fn parse_b3_sampled(value: &str) -> Option<bool> {
+    match value {
+        "1" | "d" | "true" => Some(true),
+        "0" | "false" => Some(false),
+        _ => None,
+    }
+}
+
+fn parse_b3(value: &str) -> Option<TraceParent> {
+    let mut parts = value.split('-');
+    let trace_id = parse_trace_id(parts.next()?)?;
+    let span_id = parse_span_id(parts.next()?)?;
+    let sampled = parts.next().and_then(parse_b3_sampled);
+    Some(TraceParent {
+        trace_id,
+        span_id,
+        sampled,
+        trace_state: None,
+    })
+}
+
+fn parse_b3_multi(headers: &HeaderMap) -> Option<TraceParent> {
+    let trace_id = parse_trace_id(header_str(headers, HTTP_HEADER_B3_TRACE_ID)?)?;
+    let span_id = parse_span_id(header_str(headers, HTTP_HEADER_B3_SPAN_ID)?)?;
+    let sampled = match header_str(headers, HTTP_HEADER_B3_FLAGS) {
+        Some("1") => Some(true),
+        _ => header_str(headers, HTTP_HEADER_B3_SAMPLED).and_then(parse_b3_sampled),
+    };
+    Some(TraceParent {
+        trace_id,
+        span_id,
+        sampled,
+        trace_state: None,
+    })
+}
+
+/// legacy `x-trace-id` of hex or uuid and decimal `x-span-id`
+fn parse_legacy(headers: &HeaderMap) -> Option<TraceParent> {
+    let trace_id = header_str(headers, HTTP_HEADER_TRACE_ID)?.replace('-', "");
+    let trace_id = parse_trace_id(&trace_id.to_ascii_lowercase())?;
+    let span_id = header_str(headers, HTTP_HEADER_SPAN_ID)
+        .and_then(|v| v.parse::<u64>().ok())
+        .map(SpanId)
+        .unwrap_or(SpanId(0));
+    Some(TraceParent {
+        trace_id,
+        span_id,
+        sampled: None,
+        trace_state: None,
+    })
+}
+
+/// Extract trace context, W3C first, then B3 single, B3 multi and legacy headers
+pub fn extract(headers: &HeaderMap) -> Option<TraceParent> {
+    if let Some(mut parent) =
+        header_str(headers, HTTP_HEADER_TRACEPARENT).and_then(parse_traceparent)
+    {
+        let state: Vec<&str> = headers
+            .get_all(HTTP_HEADER_TRACESTATE)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .collect();
+        if !state.is_empty() {
+            parent.trace_state = Some(state.join(","));
+        }
+        return Some(parent);
+    }
+    header_str(headers, HTTP_HEADER_B3)
+        .and_then(parse_b3)
+        .or_else(|| parse_b3_multi(headers))
+        .or_else(|| parse_legacy(headers))
+}
+
+/// Replace propagation headers with `parent`
+pub fn inject(headers: &mut HeaderMap, parent: &TraceParent) {
+    for key in [
+        HTTP_HEADER_TRACESTATE,
+        HTTP_HEADER_B3_TRACE_ID,
+        HTTP_HEADER_B3_SPAN_ID,
+        HTTP_HEADER_B3_PARENT_SPAN_ID,
+        HTTP_HEADER_B3_SAMPLED,
+        HTTP_HEADER_B3_FLAGS,
+        HTTP_HEADER_TRACE_ID,
+        HTTP_HEADER_SPAN_ID,
+    ] {
+        headers.remove(key);
+    }
+    if let Ok(v) = HeaderValue::from_str(&parent.to_traceparent()) {
+        headers.insert(HTTP_HEADER_TRACEPARENT, v);
+    }
+    if let Ok(v) = HeaderValue::from_str(&parent.to_b3()) {
+        headers.insert(HTTP_HEADER_B3, v);
+    }
+    if let Some(Ok(v)) = parent.trace_state.as_deref().map(HeaderValue::from_str) {
+        headers.insert(HTTP_HEADER_TRACESTATE, v);
+    }
+}
+
+/// Inject context of current local span, see [`TraceParent::current`]
+pub fn inject_current(headers: &mut HeaderMap, upstream: Option<&TraceParent>) {
+    if let Some(parent) = TraceParent::current(upstream) {
+        inject(headers, &parent);
+    }
+}
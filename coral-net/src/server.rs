@@ -1,11 +1,14 @@
 use std::convert::Infallible;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::routing::future::RouteFuture;
 use bytes::Bytes;
 use coral_macro::trace_error;
-use coral_runtime::spawn;
+use coral_runtime::supervise::CancellationToken;
+use coral_runtime::supervise::TaskGroup;
+use coral_runtime::tokio::net::TcpListener;
 use coral_runtime::tokio::net::TcpStream;
 use h3::quic::BidiStream;
 use h3::quic::RecvStream;
@@ -13,24 +16,81 @@ use h3::server::RequestStream;
 use http_body_util::BodyStream;
 use hyper_util::rt::TokioExecutor;
 use hyper_util::rt::TokioIo;
+use hyper_util::rt::TokioTimer;
 use log::error;
 use log::info;
+use log::warn;
 use rustls::ClientConfig;
 use rustls::ServerConfig;
+use socket2::Domain;
+use socket2::Socket;
+use socket2::Type;
 use tokio_rustls::TlsAcceptor;
 use tokio_stream::StreamExt;
 use tower::Service;
+use tower_http::limit::RequestBodyLimitLayer;
 
 use crate::error::CoralRes;
+use crate::limit::ConnGuard;
+use crate::limit::Idle;
+use crate::limit::Limits;
 
 use coral_conf::EnvAssignToml;
 use coral_macro::EnvAssign;
+use coral_macro::Schema;
+use coral_macro::Validate;
 use serde::Deserialize;
+use serde::Serialize;
 
-#[derive(Deserialize, EnvAssign, Debug, Clone)]
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate, Debug, Clone)]
 pub struct ServerConf {
+    #[validate(port)]
     pub port: u16,
     pub domain: Option<String>,
+    /// time to receive request headers, of every request of h1 and of the
+    /// first request of h2 and h3
+    #[serde(default, with = "coral_conf::duration")]
+    pub header_read_timeout: Option<Duration>,
+    /// max bytes of request headers
+    #[validate(range(min = 8192))]
+    pub max_header_size: Option<usize>,
+    /// max bytes of request body, larger requests are rejected with 413
+    pub max_body_size: Option<usize>,
+    /// idle time before closing a connection, default 1h
+    #[serde(default, with = "coral_conf::duration")]
+    pub keep_alive_timeout: Option<Duration>,
+    /// max requests in flight per connection of h2 and h3
+    #[validate(range(min = 1))]
+    pub max_concurrent_streams: Option<u32>,
+    /// max connections per client ip, counted by servers sharing a
+    /// [`crate::limit::Limits`], or else by each server
+    #[validate(range(min = 1))]
+    pub max_connections_per_ip: Option<usize>,
+    /// time to finish tls handshake, default 10s
+    #[serde(default, with = "coral_conf::duration")]
+    pub tls_handshake_timeout: Option<Duration>,
+}
+
+/// tls server name indication of the connection, inserted into request
+/// extensions
+#[derive(Clone, Debug)]
+pub struct ServerName(pub Arc<str>);
+
+/// hex sha256 fingerprint of the client certificate verified by mtls,
+/// inserted into request extensions
+#[derive(Clone, Debug)]
+pub struct PeerIdentity(pub Arc<str>);
+
+impl PeerIdentity {
+    fn of(certs: &[rustls::pki_types::CertificateDer<'_>]) -> Option<Self> {
+        let digest = ring::digest::digest(&ring::digest::SHA256, certs.first()?.as_ref());
+        let hex: String = digest
+            .as_ref()
+            .iter()
+            .map(|v| format!("{:02x}", v))
+            .collect();
+        Some(Self(hex.into()))
+    }
 }
 
 pin_project_lite::pin_project! {
@@ -90,30 +150,97 @@ async fn tcp_server<F>(
     peer_addr: SocketAddr,
     router: axum::Router,
     map_req: Option<F>,
+    limits: Limits,
+    token: CancellationToken,
+    _conn: Option<ConnGuard>,
 ) where
     F: Fn(hyper::Request<hyper::body::Incoming>, axum::Router) -> RouteFuture<Infallible> + Clone,
 {
     let peer_addr = peer_addr.clone();
-    match acceptor.accept(stream).await {
-        Ok(stream) => {
-            let service = hyper::service::service_fn(|req: hyper::Request<_>| {
-                if let Some(f) = map_req.clone().take() {
+    let handshake =
+        coral_runtime::tokio::time::timeout(limits.tls_handshake_timeout, acceptor.accept(stream));
+    match handshake.await {
+        Ok(Ok(stream)) => {
+            let sni = stream
+                .get_ref()
+                .1
+                .server_name()
+                .map(|v| ServerName(v.into()));
+            let identity = stream
+                .get_ref()
+                .1
+                .peer_certificates()
+                .and_then(PeerIdentity::of);
+            let idle = Idle::default();
+            let service = hyper::service::service_fn(|mut req: hyper::Request<_>| {
+                let request = idle.request();
+                req.extensions_mut()
+                    .insert(axum::extract::ConnectInfo(peer_addr));
+                if let Some(sni) = sni.clone() {
+                    req.extensions_mut().insert(sni);
+                }
+                if let Some(identity) = identity.clone() {
+                    req.extensions_mut().insert(identity);
+                }
+                let rsp = if let Some(f) = map_req.clone().take() {
                     // router.clone().call(f(req))
                     f(req, router.clone())
                 } else {
                     router.clone().call(req)
+                };
+                async move {
+                    let rsp = rsp.await;
+                    drop(request);
+                    rsp
                 }
             });
-            if let Err(err) = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new())
-                .serve_connection_with_upgrades(TokioIo::new(stream), service)
-                .await
-            {
-                error!("failed to serving connection from {:?}: {}", peer_addr, err);
+            let mut builder = hyper_util::server::conn::auto::Builder::new(TokioExecutor::new());
+            builder
+                .http1()
+                .timer(TokioTimer::new())
+                .header_read_timeout(limits.header_read_timeout);
+            builder
+                .http2()
+                .timer(TokioTimer::new())
+                .max_concurrent_streams(limits.max_concurrent_streams);
+            if let Some(max) = limits.max_header_size {
+                builder.http1().max_buf_size(max);
+                builder
+                    .http2()
+                    .max_header_list_size(u32::try_from(max).unwrap_or(u32::MAX));
+            }
+            let conn = builder.serve_connection_with_upgrades(TokioIo::new(stream), service);
+            let mut conn = std::pin::pin!(conn);
+            let expired = idle.expired(limits.header_read_timeout, limits.keep_alive_timeout);
+            let mut expired = std::pin::pin!(expired);
+            let mut closing = false;
+            loop {
+                coral_runtime::tokio::select! {
+                    res = conn.as_mut() => {
+                        if let Err(err) = res {
+                            error!("failed to serving connection from {:?}: {}", peer_addr, err);
+                        }
+                        break;
+                    }
+                    _ = expired.as_mut(), if !closing => {
+                        info!("close idle connection from {:?}", peer_addr);
+                        conn.as_mut().graceful_shutdown();
+                        closing = true;
+                    }
+                    _ = token.cancelled(), if !closing => {
+                        info!("drain connection from {:?} on shutdown", peer_addr);
+                        conn.as_mut().graceful_shutdown();
+                        closing = true;
+                    }
+                }
             }
         }
-        Err(err) => {
+        Ok(Err(err)) => {
             error!(e = format!("{:?}", err); "failed to accept tls stream from {:?}", peer_addr);
         }
+        Err(_) => {
+            info!("tls handshake timed out from {:?}", peer_addr);
+        }
     }
 }
 
@@ -122,6 +249,9 @@ pub struct H3Server<F> {
     endpoints: quinn::Endpoint,
     map_req_fn: F,
     router: axum::Router,
+    /// tasks of connections and requests
+    group: TaskGroup,
+    limits: Limits,
 }
 
 impl<F> H3Server<F>
@@ -136,14 +266,16 @@ where
     ) -> CoralRes<crate::udp::H3Sender> {
         let conn = self.endpoints.connect(peer_addr, domain)?.await?;
         if keep_server {
-            let (h3_conn, sender) = h3::server::builder()
+            let (h3_conn, sender) = self
+                .h3_builder()
                 .build_with_sender(h3_quinn::Connection::new(conn))
                 .await?;
-            spawn(self.quic_server(h3_conn, sender.clone()));
+            let group = self.group.clone();
+            group.spawn(self.quic_server(h3_conn, sender.clone(), peer_addr, None, None));
             Ok(sender)
         } else {
             let (mut driver, sender) = h3::client::new(h3_quinn::Connection::new(conn)).await?;
-            spawn(async move {
+            self.group.spawn(async move {
                 if let Err(err) = driver.wait_idle().await {
                     error!(e = format!("{:?}", err); "failed to run quic driver");
                 }
@@ -152,23 +284,69 @@ where
         }
     }
 
+    /// Accept connections until the endpoint is closed or the group of the
+    /// server is cancelled, connections accepted drain on their own then
     pub async fn run_server(self) -> CoralRes<()> {
-        while let Some(new_conn) = self.endpoints.accept().await {
+        loop {
+            let new_conn = coral_runtime::tokio::select! {
+                new_conn = self.endpoints.accept() => new_conn,
+                _ = self.group.token().cancelled() => {
+                    self.endpoints.set_server_config(None);
+                    return Ok(());
+                }
+            };
+            let Some(new_conn) = new_conn else {
+                break;
+            };
+            let conn_guard = match self.limits.per_ip.as_ref() {
+                Some(per_ip) => match per_ip.acquire(new_conn.remote_address().ip()) {
+                    Some(guard) => Some(guard),
+                    None => {
+                        warn!(
+                            "refuse connection beyond max connections of {:?}",
+                            new_conn.remote_address()
+                        );
+                        new_conn.refuse();
+                        continue;
+                    }
+                },
+                None => None,
+            };
             let this = self.clone();
-            spawn(async move {
-                match new_conn.await {
-                    Ok(conn) => {
-                        match h3::server::builder()
+            self.group.spawn(async move {
+                let _conn = conn_guard;
+                let handshake =
+                    coral_runtime::tokio::time::timeout(this.limits.tls_handshake_timeout, new_conn);
+                match handshake.await {
+                    Err(_) => {
+                        info!("tls handshake of quic connection timed out");
+                    }
+                    Ok(Ok(conn)) => {
+                        let peer_addr = conn.remote_address();
+                        let sni = conn
+                            .handshake_data()
+                            .and_then(|v| v.downcast::<quinn::crypto::rustls::HandshakeData>().ok())
+                            .and_then(|v| v.server_name)
+                            .map(|v| ServerName(v.into()));
+                        let identity = conn
+                            .peer_identity()
+                            .and_then(|v| v.downcast::<Vec<rustls::pki_types::CertificateDer<'static>>>().ok())
+                            .and_then(|v| PeerIdentity::of(&v));
+                        match this
+                            .h3_builder()
                             .build_with_sender(h3_quinn::Connection::new(conn))
                             .await
                         {
-                            Ok((h3_conn, sender)) => this.quic_server(h3_conn, sender).await,
+                            Ok((h3_conn, sender)) => {
+                                this.quic_server(h3_conn, sender, peer_addr, sni, identity)
+                                    .await
+                            }
                             Err(err) => {
                                 error!(e = format!("{:?}", err); "failed to establish h3 connection");
                             }
                         }
                     }
-                    Err(err) => {
+                    Ok(Err(err)) => {
                         error!(e = format!("{:?}", err); "failed to finish quinn new connection in async");
                     }
                 }
@@ -183,15 +361,58 @@ where
         self,
         mut h3_conn: h3::server::Connection<h3_quinn::Connection, Bytes>,
         sender: h3::client::SendRequest<h3_quinn::OpenStreams, Bytes>,
+        peer_addr: SocketAddr,
+        sni: Option<ServerName>,
+        identity: Option<PeerIdentity>,
     ) {
+        let idle = Idle::default();
+        let expired = idle.expired(
+            self.limits.header_read_timeout,
+            self.limits.keep_alive_timeout,
+        );
+        let mut expired = std::pin::pin!(expired);
+        let mut closing = false;
         loop {
-            match h3_conn.accept().await {
+            let accepted = coral_runtime::tokio::select! {
+                accepted = h3_conn.accept() => accepted,
+                _ = expired.as_mut(), if !closing => {
+                    info!("close idle connection from {:?}", peer_addr);
+                    if let Err(err) = h3_conn.shutdown(0).await {
+                        error!(e = format!("{:?}", err); "failed to shutdown h3 connection");
+                    }
+                    break;
+                }
+                // refuses new requests, closed once those in flight end
+                _ = self.group.token().cancelled(), if !closing => {
+                    info!("drain connection from {:?} on shutdown", peer_addr);
+                    if let Err(err) = h3_conn.shutdown(0).await {
+                        error!(e = format!("{:?}", err); "failed to shutdown h3 connection");
+                        break;
+                    }
+                    closing = true;
+                    continue;
+                }
+                _ = idle.drained(), if closing => break,
+            };
+            match accepted {
                 Ok(Some((mut req, stream))) => {
                     req.extensions_mut().insert(sender.clone());
+                    req.extensions_mut()
+                        .insert(axum::extract::ConnectInfo(peer_addr));
+                    if let Some(sni) = sni.clone() {
+                        req.extensions_mut().insert(sni);
+                    }
+                    if let Some(identity) = identity.clone() {
+                        req.extensions_mut().insert(identity);
+                    }
                     let map_req_fn = self.map_req_fn.clone();
                     let req = map_req_fn(req);
                     let router = self.router.clone();
-                    spawn(quic_handle_request(req, stream, router));
+                    let request = idle.request();
+                    self.group.spawn(async move {
+                        quic_handle_request(req, stream, router).await;
+                        drop(request);
+                    });
                 }
                 Ok(None) => {
                     info!("disconnect");
@@ -210,6 +431,15 @@ where
             }
         }
     }
+
+    /// builder of h3 connections bounded by max header size
+    fn h3_builder(&self) -> h3::server::Builder {
+        let mut builder = h3::server::builder();
+        if let Some(max) = self.limits.max_header_size {
+            builder.max_field_section_size(max as u64);
+        }
+        builder
+    }
 }
 
 async fn quic_handle_request<U>(
@@ -224,6 +454,7 @@ async fn quic_handle_request<U>(
     match hyper::http::Request::builder()
         .method(req.method())
         .uri(req.uri())
+        .version(hyper::Version::HTTP_3)
         .body(h3_recv)
     {
         Ok(mut new_req) => {
@@ -301,11 +532,25 @@ where
     Ok(())
 }
 
+#[derive(Clone)]
 pub struct ServerBuiler {
     addr: SocketAddr,
     server_tls: ServerConfig,
     router: Option<axum::Router>,
     client_tls: Option<ClientConfig>,
+    reuse_port: bool,
+    limits: Limits,
+}
+
+/// socket bound to `addr` with `SO_REUSEPORT`, so that one socket per core
+/// can be bound to the same address
+fn reuse_port_socket(addr: SocketAddr, ty: Type) -> std::io::Result<Socket> {
+    let socket = Socket::new(Domain::for_address(addr), ty, None)?;
+    socket.set_reuse_address(true)?;
+    socket.set_reuse_port(true)?;
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    Ok(socket)
 }
 
 impl ServerBuiler {
@@ -315,9 +560,39 @@ impl ServerBuiler {
             server_tls: tls,
             router: None,
             client_tls: None,
+            reuse_port: false,
+            limits: Limits::default(),
         }
     }
 
+    /// limits of connections and requests from `conf`
+    pub fn set_server_conf(mut self, conf: &ServerConf) -> Self {
+        self.limits = Limits::from(conf);
+        self
+    }
+
+    /// limits built once and cloned into servers, e.g. of every core, that
+    /// count connections per ip together
+    pub fn set_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// router bounded by max body size
+    fn limited_router(&mut self) -> CoralRes<axum::Router> {
+        let router = self.router.take().ok_or(crate::error::Error::MissRouter)?;
+        Ok(match self.limits.max_body_size {
+            Some(max) => router.layer(RequestBodyLimitLayer::new(max)),
+            None => router,
+        })
+    }
+
+    /// bind with `SO_REUSEPORT`, for servers of each [`coral_runtime::Cores`]
+    pub fn set_reuse_port(mut self, reuse_port: bool) -> Self {
+        self.reuse_port = reuse_port;
+        self
+    }
+
     pub fn set_client_tls(mut self, tls: ClientConfig) -> Self {
         self.client_tls = Some(tls);
         self
@@ -328,6 +603,8 @@ impl ServerBuiler {
         self
     }
 
+    /// h3 server, with transport of [`Limits::transport_config`] unless
+    /// `transport_config` given
     pub fn h3_server<F>(
         mut self,
         transport_config: Option<Arc<quinn_proto::TransportConfig>>,
@@ -339,24 +616,34 @@ impl ServerBuiler {
         let mut serv_cfg = quinn::ServerConfig::with_crypto(Arc::new(
             quinn_proto::crypto::rustls::QuicServerConfig::try_from(self.server_tls.clone())?,
         ));
-        if let Some(conf) = transport_config.as_ref() {
-            serv_cfg.transport_config(conf.clone());
-        }
-        let mut endpoints = quinn::Endpoint::server(serv_cfg, self.addr)?;
+        let transport_config =
+            transport_config.unwrap_or_else(|| Arc::new(self.limits.transport_config()));
+        serv_cfg.transport_config(transport_config.clone());
+        // packets of a connection hash to the same socket unless the client
+        // migrates
+        let mut endpoints = match self.reuse_port {
+            true => quinn::Endpoint::new(
+                quinn::EndpointConfig::default(),
+                Some(serv_cfg),
+                reuse_port_socket(self.addr, Type::DGRAM)?.into(),
+                Arc::new(quinn::TokioRuntime),
+            )?,
+            false => quinn::Endpoint::server(serv_cfg, self.addr)?,
+        };
         if let Some(c) = self.client_tls.take() {
             let mut client_cfg = quinn::ClientConfig::new(Arc::new(
                 quinn::crypto::rustls::QuicClientConfig::try_from(c.clone())?,
             ));
-            if let Some(conf) = transport_config.as_ref() {
-                client_cfg.transport_config(conf.clone());
-            }
+            client_cfg.transport_config(transport_config);
             endpoints.set_default_client_config(client_cfg);
         }
-        let router = self.router.take().ok_or(crate::error::Error::MissRouter)?;
+        let router = self.limited_router()?;
         Ok(H3Server {
             endpoints,
             map_req_fn,
             router,
+            group: coral_runtime::supervise::root().child("h3"),
+            limits: self.limits,
         })
     }
 
@@ -368,22 +655,50 @@ impl ServerBuiler {
             + Clone
             + 'static,
     {
-        let listener = coral_runtime::tokio::net::TcpListener::bind(&self.addr).await?;
+        let listener = match self.reuse_port {
+            true => {
+                let socket = reuse_port_socket(self.addr, Type::STREAM)?;
+                socket.listen(1024)?;
+                TcpListener::from_std(socket.into())?
+            }
+            false => TcpListener::bind(&self.addr).await?,
+        };
+        let router = self.limited_router()?;
         let tls_acceptor = TlsAcceptor::from(Arc::new(self.server_tls));
-        let router = self.router.take().ok_or(crate::error::Error::MissRouter)?;
+        let group = coral_runtime::supervise::root().child("h2");
         loop {
-            match listener.accept().await {
+            let accepted = coral_runtime::tokio::select! {
+                accepted = listener.accept() => accepted,
+                _ = group.token().cancelled() => return Ok(()),
+            };
+            match accepted {
                 Ok((stream, peer_addr)) => {
+                    let conn = match self.limits.per_ip.as_ref() {
+                        Some(per_ip) => match per_ip.acquire(peer_addr.ip()) {
+                            Some(guard) => Some(guard),
+                            None => {
+                                warn!(
+                                    "refuse connection beyond max connections of {:?}",
+                                    peer_addr
+                                );
+                                continue;
+                            }
+                        },
+                        None => None,
+                    };
                     let acceptor = tls_acceptor.clone();
 
                     let peer_addr = peer_addr.clone();
                     let map_req = map_req.clone();
-                    spawn(tcp_server(
+                    group.spawn(tcp_server(
                         acceptor,
                         stream,
                         peer_addr,
                         router.clone(),
                         map_req,
+                        self.limits.clone(),
+                        group.token().clone(),
+                        conn,
                     ));
                 }
                 Err(err) => {
@@ -54,8 +54,14 @@ where
         &mut self,
         req: hyper::Request<R>,
     ) -> CoralRes<hyper::Response<hyper::body::Incoming>> {
-        let rsp = self.inner.send_request(req).await?;
-        Ok(rsp)
+        let sender = &mut self.inner;
+        crate::client::traced_send(req, |req| async move {
+            sender
+                .send_request(req)
+                .await
+                .map_err(crate::error::Error::from)
+        })
+        .await
     }
 }
 
@@ -126,8 +132,14 @@ where
         &mut self,
         req: hyper::Request<R>,
     ) -> CoralRes<hyper::Response<hyper::body::Incoming>> {
-        let rsp = self.inner.send_request(req).await?;
-        Ok(rsp)
+        let sender = &mut self.inner;
+        crate::client::traced_send(req, |req| async move {
+            sender
+                .send_request(req)
+                .await
+                .map_err(crate::error::Error::from)
+        })
+        .await
     }
 }
 
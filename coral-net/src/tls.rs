@@ -4,43 +4,60 @@ use std::io::BufReader;
 use std::path::Path;
 use std::path::PathBuf;
 use std::sync::Arc;
+use std::sync::RwLock;
 
 use coral_conf::EnvAssignToml;
+use rustls::crypto::ring::sign::any_supported_type;
 use rustls::pki_types::CertificateDer;
 use rustls::pki_types::PrivateKeyDer;
+use rustls::server::ClientHello;
+use rustls::server::ResolvesServerCert;
+use rustls::server::WantsServerCert;
 use rustls::server::WebPkiClientVerifier;
+use rustls::sign::CertifiedKey;
 use rustls::ClientConfig;
+use rustls::ConfigBuilder;
 use rustls::RootCertStore;
 use rustls::ServerConfig;
 use rustls_pemfile::certs;
 use rustls_pemfile::private_key;
 use serde::Deserialize;
+use serde::Serialize;
 use webpki_roots::TLS_SERVER_ROOTS;
 
 use coral_macro::EnvAssign;
+use coral_macro::Schema;
+use coral_macro::Validate;
 
 use crate::error::CoralRes;
-use crate::error::Error;
 
 pub static HTTP2_ALPN: [&str; 2] = ["h2", "http/1.1"];
 pub static HTTP3_ALPN: [&str; 4] = ["h3-27", "h3-28", "h3-29", "h3"];
 
-#[derive(Deserialize, EnvAssign, Debug, Clone)]
+#[derive(Deserialize, Serialize, EnvAssign, Schema, Validate, Debug, Clone)]
 pub struct TlsConf {
+    #[validate(dir)]
     ca: Option<String>,
+    #[validate(file)]
     cert: String,
+    #[validate(file)]
     key: String,
+    #[env_assign(split = ",")]
     alpn: Option<Vec<String>>,
 }
 
 impl TlsConf {
+    /// Validate like [`coral_conf::Validate`], which loading of config runs
+    /// already. All invalid fields are reported by [`Error::ConfErr`], an
+    /// invalid `ca` is not [`Error::InvalidCa`] any more.
+    ///
+    /// [`Error::ConfErr`]: crate::error::Error::ConfErr
+    /// [`Error::InvalidCa`]: crate::error::Error::InvalidCa
+    #[deprecated(note = "use `coral_conf::Validate`")]
     pub fn check(&self) -> CoralRes<()> {
-        if let Some(dir) = self.ca.as_ref() {
-            if !std::fs::metadata(dir)?.is_dir() {
-                return Err(Error::InvalidCa);
-            }
-        }
-        Ok(())
+        let mut report = coral_conf::Report::default();
+        coral_conf::Validate::validate(self, "", &mut report);
+        Ok(report.into_result()?)
     }
 
     fn cert_key(&self) -> CoralRes<(Vec<CertificateDer<'static>>, PrivateKeyDer<'static>)> {
@@ -52,26 +69,52 @@ impl TlsConf {
         Ok((cert_chain, key_der))
     }
 
-    pub fn server_conf(&self) -> CoralRes<ServerConfig> {
-        let conf_builder = ServerConfig::builder();
+    fn certified_key(&self) -> CoralRes<CertifiedKey> {
         let (cert_chain, key_der) = self.cert_key()?;
-        let mut conf = if self.ca.is_some() {
+        let key = any_supported_type(&key_der)?;
+        Ok(CertifiedKey::new(cert_chain, key))
+    }
+
+    fn server_builder(&self) -> CoralRes<ConfigBuilder<ServerConfig, WantsServerCert>> {
+        let conf_builder = ServerConfig::builder();
+        if self.ca.is_some() {
             let root_store = root_ca(self.ca.as_ref())?;
             let client_verifier = WebPkiClientVerifier::builder(Arc::new(root_store)).build()?;
-            conf_builder
-                .with_client_cert_verifier(client_verifier)
-                .with_single_cert(cert_chain, key_der)?
+            Ok(conf_builder.with_client_cert_verifier(client_verifier))
         } else {
-            conf_builder
-                .with_no_client_auth()
-                .with_single_cert(cert_chain, key_der)?
-        };
+            Ok(conf_builder.with_no_client_auth())
+        }
+    }
+
+    fn set_alpn(&self, conf: &mut ServerConfig) {
         if let Some(alpn) = self.alpn.as_ref() {
             conf.alpn_protocols = alpn.iter().map(|v| v.as_bytes().to_vec()).collect();
         }
+    }
+
+    /// protocols negotiated by alpn
+    pub fn alpn(&self) -> &[String] {
+        self.alpn.as_deref().unwrap_or_default()
+    }
+
+    pub fn server_conf(&self) -> CoralRes<ServerConfig> {
+        let (cert_chain, key_der) = self.cert_key()?;
+        let mut conf = self
+            .server_builder()?
+            .with_single_cert(cert_chain, key_der)?;
+        self.set_alpn(&mut conf);
         Ok(conf)
     }
 
+    /// [`TlsConf::server_conf`] whose certificate can be replaced by the
+    /// returned resolver, `ca` and `alpn` are fixed
+    pub fn reloadable_server_conf(&self) -> CoralRes<(ServerConfig, Arc<CertResolver>)> {
+        let resolver = Arc::new(CertResolver(RwLock::new(Arc::new(self.certified_key()?))));
+        let mut conf = self.server_builder()?.with_cert_resolver(resolver.clone());
+        self.set_alpn(&mut conf);
+        Ok((conf, resolver))
+    }
+
     pub fn client_conf(&self) -> CoralRes<ClientConfig> {
         let root_store = root_ca(self.ca.as_ref())?;
         let (cert_chain, key_der) = self.cert_key()?;
@@ -86,6 +129,27 @@ impl TlsConf {
     }
 }
 
+/// Server certificate which can be replaced at runtime
+#[derive(Debug)]
+pub struct CertResolver(RwLock<Arc<CertifiedKey>>);
+
+impl CertResolver {
+    /// read certificate and key of `conf` again, e.g. after rotation
+    pub fn reload(&self, conf: &TlsConf) -> CoralRes<()> {
+        let key = Arc::new(conf.certified_key()?);
+        if let Ok(mut current) = self.0.write() {
+            *current = key;
+        }
+        Ok(())
+    }
+}
+
+impl ResolvesServerCert for CertResolver {
+    fn resolve(&self, _: ClientHello<'_>) -> Option<Arc<CertifiedKey>> {
+        self.0.read().ok().map(|v| v.clone())
+    }
+}
+
 // pub fn new(tls_ca: Option<String>, tls_cert: String, tls_key: String) -> Self {
 //     Self {
 //         tls_ca,
@@ -55,6 +55,10 @@ impl H3 {
             state: Arc::new(AtomicU8::default()),
         }
     }
+
+    pub fn authority(&self) -> &str {
+        &self.authority
+    }
 }
 
 async fn h3_send_body<R>(
@@ -165,14 +169,18 @@ where
             .body(())?;
         let version = req.version();
         *request.headers_mut() = req.headers().clone();
-        let (tx, mut rx) = self.inner.send_request(request).await?.split();
-        spawn(h3_send_body(BodyStream::new(req.into_body()), tx));
-        let rsp = rx.recv_response().await?;
-        let mut response = hyper::Response::builder()
-            .status(rsp.status())
-            .version(version)
-            .body(H3ClientRecv { inner: rx })?;
-        *response.headers_mut() = rsp.headers().clone();
-        Ok(response)
+        let sender = &mut self.inner;
+        crate::client::traced_send(request, |request| async move {
+            let (tx, mut rx) = sender.send_request(request).await?.split();
+            spawn(h3_send_body(BodyStream::new(req.into_body()), tx));
+            let rsp = rx.recv_response().await?;
+            let mut response = hyper::Response::builder()
+                .status(rsp.status())
+                .version(version)
+                .body(H3ClientRecv { inner: rx })?;
+            *response.headers_mut() = rsp.headers().clone();
+            Ok::<_, crate::error::Error>(response)
+        })
+        .await
     }
 }
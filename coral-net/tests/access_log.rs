@@ -0,0 +1,90 @@
+use axum::body::Body;
+use axum::routing::post;
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+
+struct AccessLogger(std::sync::Mutex<Vec<String>>);
+
+impl log::Log for AccessLogger {
+    fn enabled(&self, metadata: &log::Metadata) -> bool {
+        metadata.target() == "coral::access"
+    }
+
+    fn log(&self, record: &log::Record) {
+        struct Kvs(String);
+        impl<'kvs> log::kv::VisitSource<'kvs> for Kvs {
+            fn visit_pair(
+                &mut self,
+                key: log::kv::Key<'kvs>,
+                value: log::kv::Value<'kvs>,
+            ) -> Result<(), log::kv::Error> {
+                self.0.push_str(&format!(" {}={}", key, value));
+                Ok(())
+            }
+        }
+        if self.enabled(record.metadata()) {
+            let mut kvs = Kvs(record.args().to_string());
+            record.key_values().visit(&mut kvs).unwrap();
+            self.0.lock().unwrap().push(kvs.0);
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+async fn upstream(body: bytes::Bytes) -> axum::response::Response {
+    let mut rsp = axum::response::IntoResponse::into_response(body);
+    rsp.extensions_mut()
+        .insert(coral_net::midware::Upstream(String::from("127.0.0.1:9001")));
+    rsp
+}
+
+// apart from other midware tests, which would log to the global logger
+#[test]
+fn access_log_layer() {
+    use coral_conf::Validate;
+
+    let conf: coral_net::midware::AccessLogConf = toml::from_str(
+        r#"fields = ["method", "path", "protocol", "status", "bytes_in", "bytes_out", "upstream"]"#,
+    )
+    .unwrap();
+    let mut report = coral_conf::Report::default();
+    conf.validate("access_log", &mut report);
+    assert!(report.is_empty());
+    let invalid: coral_net::midware::AccessLogConf =
+        toml::from_str(r#"fields = ["method", "referer"]"#).unwrap();
+    invalid.validate("access_log", &mut report);
+    let invalid: coral_net::midware::AccessLogConf = toml::from_str("sample_ratio = 1.5").unwrap();
+    invalid.validate("access_log", &mut report);
+    let paths: Vec<_> = report.paths().collect();
+    assert_eq!(paths, ["access_log.fields", "access_log.sample_ratio"]);
+
+    let logger: &'static AccessLogger =
+        Box::leak(Box::new(AccessLogger(std::sync::Mutex::new(Vec::new()))));
+    log::set_logger(logger).unwrap();
+    log::set_max_level(log::LevelFilter::Info);
+    let rt = coral_runtime::tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let router = axum::Router::new()
+            .route("/upstream", post(upstream))
+            .layer(conf.layer().unwrap());
+        let req = hyper::Request::builder()
+            .method("POST")
+            .uri("/upstream?q=1")
+            .body(Body::from("hello access"))
+            .unwrap();
+        let rsp = router.oneshot(req).await.unwrap();
+        let body = rsp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body.as_ref(), b"hello access");
+    });
+    let records = logger.0.lock().unwrap();
+    assert_eq!(
+        records.as_slice(),
+        [
+            "POST /upstream 200 method=POST path=/upstream protocol=HTTP/1.1 status=200 bytes_in=12 bytes_out=12 upstream=127.0.0.1:9001"
+        ]
+    );
+}
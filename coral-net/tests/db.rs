@@ -80,3 +80,54 @@ fn test_redis_conn() {
         .unwrap();
     rt.block_on(conn_redis());
 }
+
+#[test]
+fn validate_db_conf() {
+    use coral_conf::Validate;
+
+    let mut report = coral_conf::Report::default();
+    postgres_toml().validate("db", &mut report);
+    assert!(report.is_empty());
+
+    let invalid: DbConf = toml::from_str(
+        r#"
+        [pool]
+        max_connections = 0
+        acquire_slow_level = "loud"
+
+        [postgres]
+        host = ""
+        port = 0
+        username = "postgres"
+        ssl_mode = "sometimes"
+    "#,
+    )
+    .unwrap();
+    invalid.validate("db", &mut report);
+    let paths: Vec<&str> = report.paths().collect();
+    assert_eq!(
+        paths,
+        [
+            "db.pool.max_connections",
+            "db.pool.acquire_slow_level",
+            "db.postgres.port",
+            "db.postgres.ssl_mode"
+        ]
+    );
+
+    let mut report = coral_conf::Report::default();
+    let redis: coral_net::db::RedisConf = toml::from_str(
+        r#"
+        [Single]
+        host = ""
+        port = 0
+        insecure = false
+        protocol = 3
+        [Single.config.Manager]
+    "#,
+    )
+    .unwrap();
+    redis.validate("redis", &mut report);
+    let paths: Vec<&str> = report.paths().collect();
+    assert_eq!(paths, ["redis.Single.port", "redis.Single.protocol"]);
+}
@@ -0,0 +1,34 @@
+use axum::response::IntoResponse;
+use http_body_util::BodyExt;
+
+#[test]
+fn problem_response() {
+    let rt = coral_runtime::tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let rsp = coral_net::error::Error::MissingHeader("x-api-key").into_response();
+        assert_eq!(rsp.status(), hyper::StatusCode::BAD_REQUEST);
+        assert_eq!(
+            rsp.headers()[hyper::header::CONTENT_TYPE],
+            "application/problem+json"
+        );
+        let body = rsp.into_body().collect().await.unwrap().to_bytes();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            body,
+            serde_json::json!({
+                "type": "about:blank",
+                "title": "Bad Request",
+                "status": 400,
+            })
+        );
+
+        let rsp = coral_net::error::Error::HeartBeatFailed.into_response();
+        assert_eq!(rsp.status(), hyper::StatusCode::BAD_GATEWAY);
+        let rsp = coral_net::error::Error::NoneOption("uri.authority").into_response();
+        assert_eq!(rsp.status(), hyper::StatusCode::INTERNAL_SERVER_ERROR);
+        let body = rsp.into_body().collect().await.unwrap().to_bytes();
+        assert!(!String::from_utf8_lossy(&body).contains("uri.authority"));
+    });
+}
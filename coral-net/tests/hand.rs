@@ -0,0 +1,31 @@
+use axum::body::Body;
+use axum::routing::get;
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+
+async fn get_body(router: axum::Router) -> bytes::Bytes {
+    let req = hyper::Request::builder()
+        .uri("/version")
+        .body(Body::empty())
+        .unwrap();
+    let rsp = router.oneshot(req).await.unwrap();
+    rsp.into_body().collect().await.unwrap().to_bytes()
+}
+
+#[test]
+fn reloadable_router() {
+    let rt = coral_runtime::tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let (tx, rx) = coral_runtime::tokio::sync::watch::channel(
+            axum::Router::new().route("/version", get(|| async { "v1" })),
+        );
+        let router = coral_net::hand::reloadable(rx);
+        assert_eq!(get_body(router.clone()).await.as_ref(), b"v1");
+        tx.send(axum::Router::new().route("/version", get(|| async { "v2" })))
+            .unwrap();
+        assert_eq!(get_body(router).await.as_ref(), b"v2");
+    });
+}
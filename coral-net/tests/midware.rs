@@ -0,0 +1,434 @@
+use axum::body::Body;
+use axum::routing::post;
+use http_body_util::BodyExt;
+use tower::ServiceExt;
+
+async fn echo(body: bytes::Bytes) -> bytes::Bytes {
+    body
+}
+
+#[test]
+fn metrics_layer() {
+    let rt = coral_runtime::tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let router = axum::Router::new()
+            .route("/echo/:name", post(echo))
+            .layer(coral_net::midware::MetricsLayer);
+        let req = hyper::Request::builder()
+            .method("POST")
+            .uri("/echo/coral")
+            .version(hyper::Version::HTTP_2)
+            .body(Body::from("hello metrics"))
+            .unwrap();
+        let rsp = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(rsp.status(), hyper::StatusCode::OK);
+        let body = rsp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body.as_ref(), b"hello metrics");
+
+        let req = hyper::Request::builder()
+            .uri("/missing")
+            .body(Body::empty())
+            .unwrap();
+        let rsp = router.oneshot(req).await.unwrap();
+        assert_eq!(rsp.status(), hyper::StatusCode::NOT_FOUND);
+        drop(rsp);
+    });
+    let text = coral_log::metrics::encode();
+    let labels = r#"method="POST",route="/echo/:name",status="2xx",version="h2""#;
+    assert!(text.contains(&format!("http_server_requests_total{{{}}} 1", labels)));
+    assert!(text.contains(&format!(
+        "http_server_response_body_bytes_sum{{{}}} 13",
+        labels
+    )));
+    assert!(text.contains(
+        r#"http_server_request_body_bytes_sum{method="POST",route="/echo/:name",version="h2"} 13"#
+    ));
+    assert!(text.contains(
+        r#"http_server_requests_in_flight{method="POST",route="/echo/:name",version="h2"} 0"#
+    ));
+    assert!(text.contains(
+        r#"http_server_requests_total{method="GET",route="unmatched",status="4xx",version="h1"} 1"#
+    ));
+}
+
+fn limited(uri: &str, ip: [u8; 4], key: Option<&str>) -> hyper::Request<Body> {
+    let mut req = hyper::Request::builder().uri(uri);
+    if let Some(key) = key {
+        req = req.header("x-api-key", key);
+    }
+    let mut req = req.body(Body::empty()).unwrap();
+    req.extensions_mut()
+        .insert(axum::extract::ConnectInfo(std::net::SocketAddr::from((
+            ip, 443,
+        ))));
+    req
+}
+
+fn retry_after(rsp: &axum::response::Response) -> u64 {
+    assert_eq!(rsp.status(), hyper::StatusCode::TOO_MANY_REQUESTS);
+    rsp.headers()[hyper::header::RETRY_AFTER]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap()
+}
+
+#[test]
+fn rate_limit_layer() {
+    use coral_conf::Validate;
+
+    let conf: coral_net::midware::ratelimit::RateLimitConf = toml::from_str(
+        r#"
+        [[rules]]
+        key = "Ip"
+        limit = 2
+        period = "1m"
+
+        [[rules]]
+        routes = ["/limited"]
+        key = { Header = "x-api-key" }
+        algorithm = "SlidingWindow"
+        limit = 1
+        period = "1m"
+        "#,
+    )
+    .unwrap();
+    let mut report = coral_conf::Report::default();
+    conf.validate("rate_limit", &mut report);
+    assert!(report.is_empty());
+    let sub_ms: coral_net::midware::ratelimit::RateLimitConf =
+        toml::from_str("[[rules]]\nkey = \"Ip\"\nlimit = 1\nperiod = \"500us\"").unwrap();
+    sub_ms.validate("rate_limit", &mut report);
+    assert!(!report.is_empty());
+
+    let rt = coral_runtime::tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let router = axum::Router::new()
+            .route("/echo", axum::routing::get(|| async { "echo" }))
+            .route("/limited", axum::routing::get(|| async { "limited" }))
+            .layer(conf.layer().unwrap());
+        let status = |rsp: axum::response::Response| rsp.status();
+
+        // token bucket by ip
+        for _ in 0..2 {
+            let rsp = router
+                .clone()
+                .oneshot(limited("/echo", [10, 0, 0, 1], None));
+            assert_eq!(status(rsp.await.unwrap()), hyper::StatusCode::OK);
+        }
+        let rsp = router
+            .clone()
+            .oneshot(limited("/echo", [10, 0, 0, 1], None));
+        assert_eq!(retry_after(&rsp.await.unwrap()), 30);
+        let rsp = router
+            .clone()
+            .oneshot(limited("/echo", [10, 0, 0, 2], None));
+        assert_eq!(status(rsp.await.unwrap()), hyper::StatusCode::OK);
+
+        // sliding window by header, only of the listed routes
+        let rsp = router
+            .clone()
+            .oneshot(limited("/limited", [10, 0, 0, 3], Some("coral")));
+        assert_eq!(status(rsp.await.unwrap()), hyper::StatusCode::OK);
+        let rsp = router
+            .clone()
+            .oneshot(limited("/limited", [10, 0, 0, 3], Some("coral")));
+        let retry = retry_after(&rsp.await.unwrap());
+        assert!((60..=120).contains(&retry));
+        let rsp = router
+            .clone()
+            .oneshot(limited("/limited", [10, 0, 0, 4], Some("other")));
+        assert_eq!(status(rsp.await.unwrap()), hyper::StatusCode::OK);
+        let rsp = router.oneshot(limited("/echo", [10, 0, 0, 5], Some("coral")));
+        assert_eq!(status(rsp.await.unwrap()), hyper::StatusCode::OK);
+    });
+}
+
+#[test]
+fn rate_limit_rules() {
+    let conf: coral_net::midware::ratelimit::RateLimitConf = toml::from_str(
+        r#"
+        [[rules]]
+        key = "Ip"
+        limit = 2
+        period = "1m"
+
+        [[rules]]
+        routes = ["/api"]
+        key = { Header = "x-api-key" }
+        limit = 1
+        period = "1m"
+        "#,
+    )
+    .unwrap();
+    let rt = coral_runtime::tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let router = axum::Router::new()
+            .route("/echo", axum::routing::get(|| async { "echo" }))
+            .route("/api", axum::routing::get(|| async { "api" }))
+            .route("/api/v1", axum::routing::get(|| async { "v1" }))
+            .route("/apix", axum::routing::get(|| async { "apix" }))
+            .layer(conf.layer().unwrap());
+        let ok = |rsp: axum::response::Response| assert_eq!(rsp.status(), hyper::StatusCode::OK);
+
+        // a request over the api limit takes no token of the ip
+        let req = limited("/api", [10, 0, 2, 1], Some("coral"));
+        ok(router.clone().oneshot(req).await.unwrap());
+        let req = limited("/api/v1", [10, 0, 2, 1], Some("coral"));
+        retry_after(&router.clone().oneshot(req).await.unwrap());
+        let req = limited("/echo", [10, 0, 2, 1], Some("coral"));
+        ok(router.clone().oneshot(req).await.unwrap());
+        let req = limited("/echo", [10, 0, 2, 1], Some("coral"));
+        retry_after(&router.clone().oneshot(req).await.unwrap());
+
+        // routes are prefixes by segments
+        for _ in 0..2 {
+            let req = limited("/apix", [10, 0, 2, 2], Some("coral"));
+            ok(router.clone().oneshot(req).await.unwrap());
+        }
+    });
+}
+
+fn forwarded(uri: &str, peer: [u8; 4], header: (&str, &str)) -> hyper::Request<Body> {
+    let mut req = hyper::Request::builder()
+        .uri(uri)
+        .header(header.0, header.1)
+        .body(Body::empty())
+        .unwrap();
+    req.extensions_mut()
+        .insert(axum::extract::ConnectInfo(std::net::SocketAddr::from((
+            peer, 443,
+        ))));
+    req
+}
+
+#[test]
+fn rate_limit_keys() {
+    let conf: coral_net::midware::ratelimit::RateLimitConf = toml::from_str(
+        r#"
+        trusted_proxies = ["10.0.0.9"]
+
+        [[rules]]
+        routes = ["/echo"]
+        key = "Ip"
+        limit = 1
+        period = "1m"
+
+        [[rules]]
+        routes = ["/missing"]
+        key = "Route"
+        limit = 1
+        period = "1m"
+        "#,
+    )
+    .unwrap();
+    let rt = coral_runtime::tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let router = axum::Router::new()
+            .route("/echo", axum::routing::get(|| async { "echo" }))
+            .layer(conf.layer().unwrap());
+        let ok = |rsp: axum::response::Response| assert_eq!(rsp.status(), hyper::StatusCode::OK);
+        let xff = |v| ("x-forwarded-for", v);
+
+        // client ip forwarded by the trusted proxy
+        let req = forwarded("/echo", [10, 0, 0, 9], xff("198.51.100.1, 10.0.0.9"));
+        ok(router.clone().oneshot(req).await.unwrap());
+        let req = forwarded("/echo", [10, 0, 0, 9], xff("198.51.100.1"));
+        retry_after(&router.clone().oneshot(req).await.unwrap());
+        let req = forwarded("/echo", [10, 0, 0, 9], xff("198.51.100.2"));
+        ok(router.clone().oneshot(req).await.unwrap());
+        let req = forwarded(
+            "/echo",
+            [10, 0, 0, 9],
+            ("forwarded", "for=\"198.51.100.3:4711\";proto=https"),
+        );
+        ok(router.clone().oneshot(req).await.unwrap());
+        let req = forwarded("/echo", [10, 0, 0, 9], ("forwarded", "for=198.51.100.3"));
+        retry_after(&router.clone().oneshot(req).await.unwrap());
+
+        // headers of untrusted peers are ignored
+        let req = forwarded("/echo", [10, 0, 0, 8], xff("198.51.100.1"));
+        ok(router.clone().oneshot(req).await.unwrap());
+        let req = forwarded("/echo", [10, 0, 0, 8], xff("198.51.100.4"));
+        retry_after(&router.clone().oneshot(req).await.unwrap());
+
+        // unmatched paths share one counter
+        let req = forwarded("/missing/a", [10, 0, 0, 7], xff(""));
+        let rsp = router.clone().oneshot(req).await.unwrap();
+        assert_eq!(rsp.status(), hyper::StatusCode::NOT_FOUND);
+        let req = forwarded("/missing/b", [10, 0, 0, 7], xff(""));
+        retry_after(&router.oneshot(req).await.unwrap());
+    });
+}
+
+/// script of redis, counting like the memory store
+async fn redis_rate_limit() {
+    let conf: coral_net::midware::ratelimit::RateLimitConf = toml::from_str(&format!(
+        r#"
+        store = "Redis"
+        prefix = "coral:test:{}"
+
+        [[rules]]
+        routes = ["/echo", "/limited"]
+        key = "Ip"
+        limit = 2
+        period = "1m"
+
+        [[rules]]
+        routes = ["/limited"]
+        key = {{ Header = "x-api-key" }}
+        algorithm = "SlidingWindow"
+        limit = 1
+        period = "1m"
+        "#,
+        std::process::id()
+    ))
+    .unwrap();
+    let redis: coral_net::db::RedisConf = toml::from_str(
+        r#"
+        [Single]
+        host = ""
+        port = 6379
+        db = 0
+        password = ""
+        protocol = 1
+        [Single.config.Manager]
+        connection_timeout = 2
+        "#,
+    )
+    .unwrap();
+    let client = redis.client(None).unwrap().await.unwrap();
+    let router = axum::Router::new()
+        .route("/echo", axum::routing::get(|| async { "echo" }))
+        .route("/limited", axum::routing::get(|| async { "limited" }))
+        .layer(conf.layer().unwrap());
+    let call = |uri: &str, key: Option<&str>| {
+        let mut req = limited(uri, [10, 0, 1, 1], key);
+        req.extensions_mut().insert(client.clone());
+        router.clone().oneshot(req)
+    };
+    let rsp = call("/limited", Some("coral")).await.unwrap();
+    assert_eq!(rsp.status(), hyper::StatusCode::OK);
+    let retry = retry_after(&call("/limited", Some("coral")).await.unwrap());
+    assert!((60..=120).contains(&retry));
+    // the rejected request took no token of the ip
+    let rsp = call("/echo", None).await.unwrap();
+    assert_eq!(rsp.status(), hyper::StatusCode::OK);
+    assert_eq!(retry_after(&call("/echo", None).await.unwrap()), 30);
+}
+
+#[test]
+fn rate_limit_redis() {
+    let rt = coral_runtime::tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(redis_rate_limit());
+}
+
+#[test]
+fn compression_layer() {
+    let conf: coral_net::midware::CompressionConf =
+        toml::from_str(r#"encodings = ["gzip"]"#).unwrap();
+    let invalid: coral_net::midware::CompressionConf =
+        toml::from_str(r#"encodings = ["deflate"]"#).unwrap();
+    assert!(invalid.layer(None).is_err());
+
+    let text = "coral ".repeat(1024);
+    let rt = coral_runtime::tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let large = text.clone();
+        let router = axum::Router::new()
+            .route("/echo", post(echo))
+            .route("/large", axum::routing::get(|| async move { large }))
+            .route("/small", axum::routing::get(|| async { "coral" }))
+            .layer(conf.layer(None).unwrap());
+        let get = |uri: &str| {
+            hyper::Request::builder()
+                .uri(uri)
+                .header(hyper::header::ACCEPT_ENCODING, "br, gzip")
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let rsp = router.clone().oneshot(get("/large")).await.unwrap();
+        assert_eq!(rsp.headers()[hyper::header::CONTENT_ENCODING], "gzip");
+        assert_eq!(rsp.headers()[hyper::header::VARY], "accept-encoding");
+        let gzipped = rsp.into_body().collect().await.unwrap().to_bytes();
+        assert!(gzipped.len() < text.len());
+
+        let rsp = router.clone().oneshot(get("/small")).await.unwrap();
+        assert!(rsp.headers().get(hyper::header::CONTENT_ENCODING).is_none());
+
+        // request decompressed, octet stream of echo not compressed
+        let echo_gzip = |body: bytes::Bytes| {
+            hyper::Request::builder()
+                .method("POST")
+                .uri("/echo")
+                .header(hyper::header::CONTENT_ENCODING, "gzip")
+                .header(hyper::header::ACCEPT_ENCODING, "gzip")
+                .body(Body::from(body))
+                .unwrap()
+        };
+        let rsp = router.oneshot(echo_gzip(gzipped.clone())).await.unwrap();
+        assert!(rsp.headers().get(hyper::header::CONTENT_ENCODING).is_none());
+        let body = rsp.into_body().collect().await.unwrap().to_bytes();
+        assert_eq!(body.as_ref(), text.as_bytes());
+
+        // small compressed body inflating beyond max body size
+        let bounded = axum::Router::new()
+            .route("/echo", post(echo))
+            .layer(conf.layer(Some(1024)).unwrap());
+        assert!(gzipped.len() < 1024);
+        let rsp = bounded.oneshot(echo_gzip(gzipped)).await.unwrap();
+        assert_eq!(rsp.status(), hyper::StatusCode::PAYLOAD_TOO_LARGE);
+    });
+}
+
+#[test]
+fn alt_svc_layer() {
+    let conf: coral_net::midware::AltSvcConf = toml::from_str("max_age = 3600").unwrap();
+    let alpn = ["h3".to_owned(), "h3-29".to_owned(), "h2".to_owned()];
+    let drain = coral_runtime::supervise::CancellationToken::new();
+    let layer = conf.layer(9001, &alpn, drain.clone()).unwrap().unwrap();
+    let disabled: coral_net::midware::AltSvcConf = toml::from_str("enabled = false").unwrap();
+    let disabled = disabled.layer(9001, &alpn, drain.clone()).unwrap();
+    assert!(disabled.is_none());
+
+    let rt = coral_runtime::tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let router = axum::Router::new().route("/echo", post(echo)).layer(layer);
+        let req = || {
+            hyper::Request::builder()
+                .method("POST")
+                .uri("/echo")
+                .body(Body::empty())
+                .unwrap()
+        };
+        let rsp = router.clone().oneshot(req()).await.unwrap();
+        assert_eq!(
+            rsp.headers()[hyper::header::ALT_SVC],
+            r#"h3=":9001"; ma=3600, h3-29=":9001"; ma=3600"#
+        );
+        drain.cancel();
+        let rsp = router.oneshot(req()).await.unwrap();
+        assert_eq!(rsp.headers()[hyper::header::ALT_SVC], "clear");
+    });
+}
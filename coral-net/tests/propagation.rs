@@ -0,0 +1,96 @@
+use coral_net::propagation::extract;
+use coral_net::propagation::inject;
+use coral_net::propagation::TraceParent;
+use fastrace::collector::SpanId;
+use fastrace::collector::TraceId;
+use hyper::header::HeaderMap;
+use hyper::header::HeaderValue;
+
+fn headers(pairs: &[(&'static str, &'static str)]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (k, v) in pairs {
+        headers.append(*k, HeaderValue::from_static(v));
+    }
+    headers
+}
+
+#[test]
+fn extract_w3c() {
+    let h = headers(&[
+        (
+            "traceparent",
+            "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-01",
+        ),
+        ("tracestate", "rojo=00f067aa0ba902b7"),
+        ("tracestate", "congo=t61rcWkgMzE"),
+        ("b3", "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-0"),
+    ]);
+    let parent = extract(&h).unwrap();
+    assert_eq!(parent.trace_id.0, 0x4bf92f3577b34da6a3ce929d0e0e4736);
+    assert_eq!(parent.span_id.0, 0x00f067aa0ba902b7);
+    assert_eq!(parent.sampled, Some(true));
+    assert_eq!(
+        parent.trace_state.as_deref(),
+        Some("rojo=00f067aa0ba902b7,congo=t61rcWkgMzE")
+    );
+
+    // invalid traceparent falls back to b3
+    let h = headers(&[
+        (
+            "traceparent",
+            "00-00000000000000000000000000000000-00f067aa0ba902b7-01",
+        ),
+        ("b3", "80f198ee56343ba864fe8b2a57d3eff7-e457b5a2e4d86bd1-0"),
+    ]);
+    let parent = extract(&h).unwrap();
+    assert_eq!(parent.trace_id.0, 0x80f198ee56343ba864fe8b2a57d3eff7);
+    assert_eq!(parent.sampled, Some(false));
+}
+
+#[test]
+fn extract_b3_and_legacy() {
+    let h = headers(&[
+        ("x-b3-traceid", "463ac35c9f6413ad"),
+        ("x-b3-spanid", "a2fb4a1d1a96d312"),
+        ("x-b3-flags", "1"),
+    ]);
+    let parent = extract(&h).unwrap();
+    assert_eq!(parent.trace_id.0, 0x463ac35c9f6413ad);
+    assert_eq!(parent.span_id.0, 0xa2fb4a1d1a96d312);
+    assert_eq!(parent.sampled, Some(true));
+
+    let h = headers(&[
+        ("x-trace-id", "5B8EFFF7-98A0-4A0F-9D1E-7C3C8E1A5F2B"),
+        ("x-span-id", "42"),
+    ]);
+    let parent = extract(&h).unwrap();
+    assert_eq!(parent.trace_id.0, 0x5b8efff798a04a0f9d1e7c3c8e1a5f2b);
+    assert_eq!(parent.span_id.0, 42);
+    assert_eq!(parent.sampled, None);
+
+    assert!(extract(&headers(&[("x-trace-id", "not a trace id")])).is_none());
+}
+
+#[test]
+fn inject_headers() {
+    let mut h = headers(&[("x-trace-id", "legacy"), ("x-b3-sampled", "1")]);
+    let parent = TraceParent {
+        trace_id: TraceId(0x4bf92f3577b34da6a3ce929d0e0e4736),
+        span_id: SpanId(0x00f067aa0ba902b7),
+        sampled: Some(false),
+        trace_state: Some(String::from("rojo=00f067aa0ba902b7")),
+    };
+    inject(&mut h, &parent);
+    assert_eq!(
+        h.get("traceparent").unwrap(),
+        "00-4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-00"
+    );
+    assert_eq!(
+        h.get("b3").unwrap(),
+        "4bf92f3577b34da6a3ce929d0e0e4736-00f067aa0ba902b7-0"
+    );
+    assert_eq!(h.get("tracestate").unwrap(), "rojo=00f067aa0ba902b7");
+    assert!(h.get("x-trace-id").is_none());
+    assert!(h.get("x-b3-sampled").is_none());
+    assert_eq!(extract(&h), Some(parent));
+}
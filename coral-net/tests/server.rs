@@ -1 +1,256 @@
+use std::convert::Infallible;
+use std::net::IpAddr;
+use std::net::Ipv4Addr;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
 
+use axum::routing::future::RouteFuture;
+use axum::routing::get;
+use axum::routing::post;
+use bytes::Bytes;
+use coral_net::limit::ConnLimiter;
+use coral_net::limit::Idle;
+use coral_net::limit::Limits;
+use coral_net::server::ServerBuiler;
+use coral_net::server::ServerConf;
+use coral_net::tls::TlsConf;
+use coral_runtime::tokio;
+use coral_runtime::tokio::io::AsyncReadExt;
+use coral_runtime::tokio::io::AsyncWriteExt;
+use coral_runtime::tokio::net::TcpStream;
+use http_body_util::BodyExt;
+use http_body_util::Full;
+use hyper::StatusCode;
+use hyper_util::rt::TokioIo;
+use rustls::client::danger::HandshakeSignatureValid;
+use rustls::client::danger::ServerCertVerified;
+use rustls::client::danger::ServerCertVerifier;
+use rustls::pki_types::CertificateDer;
+use rustls::pki_types::ServerName;
+use rustls::pki_types::UnixTime;
+use rustls::DigitallySignedStruct;
+use rustls::SignatureScheme;
+use tokio_rustls::client::TlsStream;
+use tokio_rustls::TlsConnector;
+
+#[test]
+fn limits_of_conf() {
+    let conf: ServerConf = toml::from_str(
+        "port = 443\nmax_body_size = 1024\nkeep_alive_timeout = \"5s\"\nmax_connections_per_ip = 2",
+    )
+    .unwrap();
+    let limits = Limits::from(&conf);
+    assert_eq!(limits.max_body_size, Some(1024));
+    assert_eq!(limits.keep_alive_timeout, Duration::from_secs(5));
+    assert_eq!(limits.tls_handshake_timeout, Duration::from_secs(10));
+    assert!(limits.header_read_timeout.is_none());
+    assert!(limits.per_ip.is_some());
+}
+
+#[test]
+fn conn_limiter() {
+    let limiter = ConnLimiter::new(2);
+    let ip = IpAddr::V4(Ipv4Addr::LOCALHOST);
+    let first = limiter.acquire(ip).unwrap();
+    let _second = limiter.clone().acquire(ip).unwrap();
+    assert!(limiter.acquire(ip).is_none());
+    assert!(limiter.acquire(IpAddr::V4(Ipv4Addr::UNSPECIFIED)).is_some());
+    drop(first);
+    assert_eq!(limiter.count(ip), 1);
+    assert!(limiter.acquire(ip).is_some());
+
+    // clones into cores count together, other limiters apart
+    let ip = IpAddr::V4(Ipv4Addr::new(192, 0, 2, 45));
+    let (core0, core1, other) = (limiter.clone(), limiter.clone(), ConnLimiter::new(1));
+    let _core0 = core0.acquire(ip).unwrap();
+    let _core1 = core1.acquire(ip).unwrap();
+    assert!(limiter.acquire(ip).is_none());
+    assert_eq!(core1.count(ip), 2);
+    let _other = other.acquire(ip).unwrap();
+    assert_eq!(other.count(ip), 1);
+}
+
+#[test]
+fn idle() {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .start_paused(true)
+        .build()
+        .unwrap();
+    rt.block_on(async {
+        let first_request = Some(Duration::from_millis(50));
+        let keep_alive = Duration::from_millis(200);
+        let idle = Idle::default();
+        let started = tokio::time::Instant::now();
+        idle.expired(first_request, keep_alive).await;
+        assert_eq!(started.elapsed(), Duration::from_millis(50));
+
+        let idle = Idle::default();
+        let request = idle.request();
+        let expired = tokio::spawn({
+            let idle = idle.clone();
+            async move { idle.expired(first_request, keep_alive).await }
+        });
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(!expired.is_finished());
+        drop(request);
+        let started = tokio::time::Instant::now();
+        expired.await.unwrap();
+        assert_eq!(started.elapsed(), keep_alive);
+
+        let request = idle.request();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(150)).await;
+            drop(request);
+        });
+        let started = tokio::time::Instant::now();
+        idle.drained().await;
+        assert!(started.elapsed() >= Duration::from_millis(150));
+    });
+}
+
+/// accepts any server certificate, the one of cicd is self signed and expired
+#[derive(Debug)]
+struct NoVerifier;
+
+impl ServerCertVerifier for NoVerifier {
+    fn verify_server_cert(
+        &self,
+        _end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        Ok(ServerCertVerified::assertion())
+    }
+
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls12_signature(
+            message,
+            cert,
+            dss,
+            &rustls::crypto::ring::default_provider().signature_verification_algorithms,
+        )
+    }
+
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        rustls::crypto::verify_tls13_signature(
+            message,
+            cert,
+            dss,
+            &rustls::crypto::ring::default_provider().signature_verification_algorithms,
+        )
+    }
+
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        rustls::crypto::ring::default_provider()
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+type MapReq = fn(hyper::Request<hyper::body::Incoming>, axum::Router) -> RouteFuture<Infallible>;
+
+/// serve `router` by h2 server of `conf` on a free local port
+fn serve(conf: &str, router: axum::Router) -> SocketAddr {
+    let addr = std::net::TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let conf: ServerConf = toml::from_str(&format!("port = {}\n{}", addr.port(), conf)).unwrap();
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../cicd/self_sign_cert");
+    let tls: TlsConf = toml::from_str(&format!(
+        "cert = \"{0}/server.crt\"\nkey = \"{0}/server.key\"\nalpn = [\"http/1.1\"]",
+        dir
+    ))
+    .unwrap();
+    let server = ServerBuiler::new(addr, tls.server_conf().unwrap())
+        .set_server_conf(&conf)
+        .set_router(router);
+    tokio::spawn(server.h2_server(None::<MapReq>));
+    addr
+}
+
+/// tls connection of http/1.1 to `addr`, once listening
+async fn connect(addr: SocketAddr) -> TlsStream<TcpStream> {
+    let stream = loop {
+        match TcpStream::connect(addr).await {
+            Ok(stream) => break stream,
+            Err(_) => tokio::time::sleep(Duration::from_millis(10)).await,
+        }
+    };
+    let mut conf = rustls::ClientConfig::builder()
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(NoVerifier))
+        .with_no_client_auth();
+    conf.alpn_protocols = vec![b"http/1.1".to_vec()];
+    let domain = ServerName::try_from("server.test.com").unwrap();
+    TlsConnector::from(Arc::new(conf))
+        .connect(domain, stream)
+        .await
+        .unwrap()
+}
+
+fn runtime() -> tokio::runtime::Runtime {
+    tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap()
+}
+
+#[test]
+fn body_too_large() {
+    runtime().block_on(async {
+        let router = axum::Router::new().route(
+            "/",
+            post(|body: Bytes| async move { body.len().to_string() }),
+        );
+        let addr = serve("max_body_size = 1024", router);
+        let stream = TokioIo::new(connect(addr).await);
+        let (mut sender, conn) = hyper::client::conn::http1::handshake(stream).await.unwrap();
+        tokio::spawn(conn);
+        let req = |len: usize| {
+            hyper::Request::post("/")
+                .header(hyper::header::HOST, "server.test.com")
+                .body(Full::new(Bytes::from(vec![0u8; len])))
+                .unwrap()
+        };
+        let res = sender.send_request(req(1024)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        res.into_body().collect().await.unwrap();
+        let res = sender.send_request(req(2048)).await.unwrap();
+        assert_eq!(res.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    });
+}
+
+#[test]
+fn header_read_timeout() {
+    runtime().block_on(async {
+        let router = axum::Router::new().route("/", get(|| async { "ok" }));
+        let addr = serve("header_read_timeout = \"100ms\"", router);
+        let mut stream = connect(addr).await;
+        stream
+            .write_all(b"GET / HTTP/1.1\r\nhost: server.test.com\r\n")
+            .await
+            .unwrap();
+        stream.flush().await.unwrap();
+        // closed by the server before the headers end, with 408 at most
+        let mut buf = Vec::new();
+        let read = tokio::time::timeout(Duration::from_secs(5), stream.read_to_end(&mut buf));
+        assert!(read.await.is_ok());
+        let res = String::from_utf8_lossy(&buf);
+        assert!(res.is_empty() || res.starts_with("HTTP/1.1 408"), "{}", res);
+    });
+}
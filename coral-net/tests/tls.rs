@@ -15,3 +15,40 @@ fn test_tls_conf() {
     let conf: TlsConf = from_str(demo_toml_conf()).unwrap();
     println!("{:?}", conf);
 }
+
+#[test]
+fn test_cert_reload() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../cicd/self_sign_cert");
+    let conf: TlsConf = from_str(&format!(
+        "cert = \"{0}/server.crt\"\nkey = \"{0}/server.key\"\nalpn = [\"h2\"]",
+        dir
+    ))
+    .unwrap();
+    let (server_conf, resolver) = conf.reloadable_server_conf().unwrap();
+    assert_eq!(server_conf.alpn_protocols, vec![b"h2".to_vec()]);
+    resolver.reload(&conf).unwrap();
+    let missing: TlsConf =
+        from_str("cert = \"/nonexistent.crt\"\nkey = \"/nonexistent.key\"").unwrap();
+    assert!(resolver.reload(&missing).is_err());
+}
+
+#[test]
+#[allow(deprecated)]
+fn test_check() {
+    let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../cicd/self_sign_cert");
+    let conf: TlsConf = from_str(&format!(
+        "cert = \"{0}/server.crt\"\nkey = \"{0}/server.key\"",
+        dir
+    ))
+    .unwrap();
+    assert!(conf.check().is_ok());
+    let invalid_ca: TlsConf = from_str(&format!(
+        "ca = \"{0}/server.crt\"\ncert = \"{0}/server.crt\"\nkey = \"{0}/server.key\"",
+        dir
+    ))
+    .unwrap();
+    assert!(matches!(
+        invalid_ca.check(),
+        Err(coral_net::error::Error::ConfErr(_))
+    ));
+}
@@ -1,50 +1,144 @@
-use std::io::Read;
 
 use crate::error::CoralRes;
+use clap::Arg;
+use clap::ArgAction;
+use clap::CommandFactory;
+use clap::FromArgMatches;
 use clap::Parser;
+use coral_conf::flag::Flag;
 use coral_conf::EnvAssignToml;
+use coral_conf::Flags;
 use coral_macro::EnvAssign;
+use coral_macro::Schema;
+use coral_macro::Validate;
+use coral_runtime::reload::Reloader;
 use serde::Deserialize;
+use serde::Serialize;
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Cli {
-    #[arg(long, help = "toml config file path")]
-    config: String,
+    #[arg(
+        long,
+        help = "toml config file path, later files override earlier ones, optional with flags"
+    )]
+    config: Vec<String>,
+    #[arg(long, value_name = "KEY.PATH=VALUE", help = "override config value")]
+    set: Vec<String>,
+    #[arg(long, help = "print merged config with secrets redacted and exit")]
+    print_config: bool,
+    #[arg(long, help = "print json schema of config and exit")]
+    config_schema: bool,
 }
 
-#[derive(Deserialize, Debug, EnvAssign)]
+#[derive(Deserialize, Serialize, Debug, EnvAssign, Schema, Validate)]
 pub(crate) struct H2Conf {
+    #[validate(nested)]
     pub(crate) server_conf: coral_net::server::ServerConf,
+    #[validate(nested)]
     pub(crate) tls_conf: coral_net::tls::TlsConf,
 }
 
-#[derive(Deserialize, Debug, EnvAssign)]
+#[derive(Deserialize, Serialize, Debug, EnvAssign, Schema, Validate)]
 pub(crate) struct H3Conf {
+    #[validate(nested)]
     pub(crate) server_conf: coral_net::server::ServerConf,
+    #[validate(nested)]
     pub(crate) tls_conf: coral_net::tls::TlsConf,
 }
 
-#[derive(Deserialize, Debug, EnvAssign)]
+#[derive(Deserialize, Serialize, Debug, EnvAssign, Schema, Validate)]
 pub(crate) struct Conf {
+    #[validate(nested)]
     pub(crate) h2: H2Conf,
+    #[validate(nested)]
     pub(crate) h3: H3Conf,
+    #[validate(nested)]
     pub(crate) log_conf: coral_log::LogConf,
+    #[validate(nested)]
     pub(crate) rt_conf: coral_runtime::RuntimeConf,
+    #[validate(nested)]
+    pub(crate) access_log: Option<coral_net::midware::AccessLogConf>,
+    #[validate(nested)]
+    pub(crate) compression: Option<coral_net::midware::CompressionConf>,
+    #[validate(nested)]
+    pub(crate) alt_svc: Option<coral_net::midware::AltSvcConf>,
+    /// listener of metrics and runtime stats, not served if none
+    #[validate(nested)]
+    pub(crate) admin: Option<coral_net::admin::AdminConf>,
+}
+
+/// dotted paths of [`Conf`] applied in place on reload, tls certificates are
+/// read again on every reload
+static RELOADABLE: [&str; 8] = [
+    "log_conf.level",
+    "h2.tls_conf.cert",
+    "h2.tls_conf.key",
+    "h3.tls_conf.cert",
+    "h3.tls_conf.key",
+    "access_log",
+    "compression",
+    "alt_svc",
+];
+
+/// flags of every field of [`Conf`], with the short ones of README
+fn flags() -> Flags {
+    Flags::new::<Conf>()
+        .alias("port", &["h2.server_conf.port", "h3.server_conf.port"])
+        .alias("tls-ca", &["h2.tls_conf.ca", "h3.tls_conf.ca"])
+        .alias("tls-cert", &["h2.tls_conf.cert", "h3.tls_conf.cert"])
+        .alias("tls-key", &["h2.tls_conf.key", "h3.tls_conf.key"])
+        .alias("dir", &["log_conf.dir"])
+        .alias("prefix", &["log_conf.prefix"])
+        .alias("rotation", &["log_conf.rotation"])
+        .alias("otel-endpoint", &["log_conf.otel_endpoint"])
+        .alias("otel-kvs", &["log_conf.otel_kvs"])
+        .alias("cpui", &["rt_conf.cpui"])
+        .alias("nums", &["rt_conf.nums"])
+}
+
+fn arg(flag: &Flag) -> Arg {
+    let arg = Arg::new(flag.name.clone())
+        .long(flag.name.clone())
+        .help(flag.help.clone().unwrap_or_default())
+        .help_heading("Config");
+    match (flag.multiple(), flag.switch()) {
+        (true, _) => arg.action(ArgAction::Append),
+        (_, true) => arg.num_args(0..=1).default_missing_value("true"),
+        _ => arg,
+    }
 }
 
 impl Cli {
-    pub(crate) fn init() -> CoralRes<Conf> {
-        let args = Cli::parse();
-        let mut file = std::fs::File::open(args.config)?;
-        let mut buf = String::new();
-        file.read_to_string(&mut buf)?;
-        let mut conf: Conf = toml::from_str(&buf)?;
-        conf.assign(Some("PROXY"))?;
-        conf.h2.tls_conf.check()?;
-        conf.h3.tls_conf.check()?;
-        conf.log_conf.check()?;
-        conf.rt_conf.check()?;
-        Ok(conf)
+    /// precedence of config values is flag > env > file
+    pub(crate) fn init() -> CoralRes<Reloader<Conf>> {
+        let flags = flags();
+        let matches = flags
+            .iter()
+            .fold(Cli::command(), |cmd, flag| cmd.arg(arg(flag)))
+            .get_matches();
+        let args = Cli::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+        if args.config_schema {
+            println!("{:#}", coral_conf::schema::root::<Conf>("coral-proxy"));
+            std::process::exit(0);
+        }
+        let loader = coral_conf::Loader::new()
+            .env_prefix("PROXY")
+            .files(args.config)
+            .sets(args.set);
+        let loader = flags.apply(loader, |flag| {
+            matches
+                .get_many::<String>(&flag.name)
+                .map(|v| v.cloned().collect())
+        })?;
+        if args.print_config {
+            print!("{}", loader.load::<Conf>()?.dump()?);
+            std::process::exit(0);
+        }
+        let reloader = Reloader::<Conf>::new(loader)?.reloadable(&RELOADABLE);
+        let conf = reloader.conf();
+        conf.log_conf.init_on(conf.rt_conf.pin_background())?;
+        coral_runtime::set_panic_hook();
+        Ok(reloader)
     }
 }
@@ -55,10 +55,26 @@ pub(crate) enum Error {
 
     #[error("parse toml")]
     ParseTomlErr(#[from] toml::de::Error),
+
+    #[error("{0}")]
+    ConfErr(#[from] coral_conf::Error),
+}
+
+impl Error {
+    /// status of the response of the error
+    pub(crate) fn status(&self) -> StatusCode {
+        match self {
+            Error::InvalidUri(_) | Error::Utf8Err(_) | Error::AxumErr(_) => StatusCode::BAD_REQUEST,
+            Error::HyperInner(_) => StatusCode::BAD_GATEWAY,
+            Error::EmptyPool => StatusCode::SERVICE_UNAVAILABLE,
+            Error::CoralNetErr(err) => err.status(),
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
 }
 
 impl IntoResponse for Error {
     fn into_response(self) -> axum::response::Response {
-        (StatusCode::INTERNAL_SERVER_ERROR, format!("{:?}", self)).into_response()
+        coral_net::error::problem(self.status(), &self)
     }
 }
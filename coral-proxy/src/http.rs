@@ -4,10 +4,12 @@ use axum::routing::get;
 use axum::routing::post;
 use coral_macro::trace_error;
 use coral_net::client::Request as CoralNetReq;
-use coral_net::midware::add_header_span_id;
+use coral_net::midware::Upstream;
+use coral_net::propagation::TraceParent;
 use http_body_util::BodyExt;
 use log::info;
 
+use crate::cli::Conf;
 use crate::error::CoralRes;
 use crate::error::Error;
 
@@ -30,6 +32,7 @@ async fn proxy(
         .clone();
     let headers = req.headers().clone();
     let method = req.method().clone();
+    let upstream = req.extensions().get::<TraceParent>().cloned();
 
     let pool = req
         .extensions()
@@ -47,7 +50,7 @@ async fn proxy(
         Error::NoneOption("trans header")
     })?;
     *trans_headers = headers;
-    add_header_span_id(trans_headers);
+    coral_net::propagation::inject_current(trans_headers, upstream.as_ref());
 
     let trans_req = trans_builder.body(body).map_err(|err| {
         trace_error!(e = format!("{:?}", err); "failed to build trans body");
@@ -58,7 +61,9 @@ async fn proxy(
         .load_balance()
         .await?
         .ok_or(crate::error::Error::EmptyPool)?;
-    let rsp = sender.send(trans_req).await?;
+    let mut rsp = sender.send(trans_req).await?;
+    rsp.extensions_mut()
+        .insert(Upstream(sender.authority().to_owned()));
     Ok(rsp)
 }
 
@@ -83,21 +88,50 @@ async fn recv_endpoints(req: Request) -> CoralRes<()> {
 
 pub static RECV_ENDPOINTS: &'static str = "/coral-proxy-endpoints";
 
-pub fn app_h3() -> axum::Router {
+fn layers(router: axum::Router, conf: &Conf) -> CoralRes<axum::Router> {
+    // h2 and h3 share the router, decompressed bodies are bounded by the
+    // larger limit and unbounded if either listener has none
+    let max_body_size = conf
+        .h2
+        .server_conf
+        .max_body_size
+        .zip(conf.h3.server_conf.max_body_size)
+        .map(|(h2, h3)| h2.max(h3));
+    let router = match conf.compression.as_ref() {
+        Some(compression) => router.layer(compression.layer(max_body_size)?),
+        None => router,
+    };
+    let alt_svc = conf.alt_svc.clone().unwrap_or_default().layer(
+        conf.h3.server_conf.port,
+        conf.h3.tls_conf.alpn(),
+        coral_runtime::supervise::drain().clone(),
+    )?;
+    let router = match alt_svc {
+        Some(alt_svc) => router.layer(alt_svc),
+        None => router,
+    };
+    let router = match conf.access_log.as_ref() {
+        Some(conf) => router.layer(conf.layer()?),
+        None => router,
+    };
+    Ok(router.layer(coral_net::midware::TraceLayer::default()))
+}
+
+pub fn app_h3(conf: &Conf) -> CoralRes<axum::Router> {
     let router: axum::Router = axum::Router::new()
         .route(coral_net::hand::HTTP_RESET_URI, post(proxy))
         .route(RECV_ENDPOINTS, post(recv_endpoints))
-        .layer(coral_net::midware::TraceLayer::default());
-    router
+        .layer(coral_net::midware::MetricsLayer::default());
+    layers(router, conf)
 }
 
-pub fn app_h2() -> axum::Router {
+pub fn app_h2(conf: &Conf) -> CoralRes<axum::Router> {
     let router: axum::Router = axum::Router::new()
         .route(
             coral_net::hand::WS_RESET_URI,
             get(coral_net::hand::websocket_upgrade_hand),
         )
         .route(coral_net::hand::HTTP_RESET_URI, post(proxy))
-        .layer(coral_net::midware::TraceLayer::default());
-    router
+        .layer(coral_net::midware::MetricsLayer::default());
+    layers(router, conf)
 }
@@ -2,16 +2,26 @@ use std::convert::Infallible;
 use std::net::Ipv4Addr;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::routing::future::RouteFuture;
 use coral_runtime::spawn;
+use coral_runtime::spawn_named;
 use hyper::body::Incoming;
 use log::error;
+use log::warn;
 
 use crate::cli;
 use crate::cli::Conf;
 use crate::error::CoralRes;
 use crate::http::RECV_ENDPOINTS;
+use coral_net::limit::Limits;
+use coral_net::server::ServerBuiler;
+use coral_net::tls::CertResolver;
+use coral_runtime::reload::Reloader;
+use coral_runtime::supervise::Restart;
+use coral_runtime::tokio::sync::watch;
+use coral_runtime::RuntimeConf;
 
 pub type T = coral_net::udp::H3;
 pub type R = axum::body::Body;
@@ -29,9 +39,70 @@ fn map_req_h3(mut req: hyper::Request<()>, pool: Pool) -> hyper::Request<()> {
     req
 }
 
-async fn server(conf: Conf) -> CoralRes<()> {
-    conf.log_conf.set_traces();
-    let pool = coral_net::client::VecClients::<T, R, H>::default();
+/// applies reloadable parts of configs published by reloader in place
+async fn reload(
+    mut rx: watch::Receiver<Arc<Conf>>,
+    h2_routes: watch::Sender<axum::Router>,
+    h3_routes: watch::Sender<axum::Router>,
+    h2_cert: Arc<CertResolver>,
+    h3_cert: Arc<CertResolver>,
+) {
+    while rx.changed().await.is_ok() {
+        let conf = rx.borrow_and_update().clone();
+        match (crate::http::app_h2(&conf), crate::http::app_h3(&conf)) {
+            (Ok(h2), Ok(h3)) => {
+                h2_routes.send_replace(h2);
+                h3_routes.send_replace(h3);
+            }
+            (Err(err), _) | (_, Err(err)) => {
+                error!(e = format!("{:?}", err); "failed to rebuild router on reload");
+            }
+        }
+        for (resolver, tls_conf) in [(&h2_cert, &conf.h2.tls_conf), (&h3_cert, &conf.h3.tls_conf)] {
+            if let Err(err) = resolver.reload(tls_conf) {
+                error!(e = format!("{:?}", err); "failed to reload certificate");
+            }
+        }
+    }
+}
+
+/// start telemetry, reloading of config and shutdown on signals, once for all
+/// cores
+fn start(reloader: Reloader<Conf>) -> CoralRes<watch::Receiver<Arc<Conf>>> {
+    let conf = reloader.conf();
+    coral_runtime::supervise::cancel_on_signal(conf.rt_conf.drain_timeout())?;
+    if let Some(f) = conf.log_conf.set_traces() {
+        f();
+    }
+    if let Some(f) = conf.log_conf.set_metrics() {
+        f();
+    }
+    if let Some(admin) = conf.admin.clone() {
+        coral_runtime::supervise::root()
+            .child("admin")
+            .supervise(RESTART, move || admin.clone().serve());
+    }
+    let rx = reloader.subscribe();
+    spawn_named("reloader", reloader.run());
+    let mut level_rx = rx.clone();
+    spawn(async move {
+        while level_rx.changed().await.is_ok() {
+            level_rx.borrow_and_update().log_conf.set_level();
+        }
+    });
+    Ok(rx)
+}
+
+/// Serve h2 and h3, `reuse_port` if a server runs on each core. Endpoints of
+/// servers are shared by `pool` across cores, connections per ip by `limits`
+/// of h2 and h3.
+async fn server(
+    rx: watch::Receiver<Arc<Conf>>,
+    pool: Pool,
+    limits: (Limits, Limits),
+    reuse_port: bool,
+) -> CoralRes<()> {
+    let conf = rx.borrow().clone();
     let poolc = pool.clone();
     let map_req_fn_h3 =
         move |req: hyper::Request<()>| -> hyper::Request<()> { map_req_h3(req, poolc.clone()) };
@@ -43,34 +114,84 @@ async fn server(conf: Conf) -> CoralRes<()> {
         std::net::IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
         conf.h3.server_conf.port,
     );
-    let mut transport_config = quinn_proto::TransportConfig::default();
-    transport_config.max_idle_timeout(Some(quinn_proto::VarInt::from_u32(3600000).into()));
-    let h3_server = coral_net::server::ServerBuiler::new(addr_h3, conf.h3.tls_conf.server_conf()?)
-        .set_router(crate::http::app_h3())
-        .h3_server(Some(Arc::new(transport_config)), map_req_fn_h3)?;
-    spawn(async move {
-        if let Err(err) = h3_server.run_server().await {
-            error!(e = format!("{:?}", err); "failed to run h3 server");
-        }
-    });
+    let (h3_routes, h3_rx) = watch::channel(crate::http::app_h3(&conf)?);
+    let (h3_tls, h3_cert) = conf.h3.tls_conf.reloadable_server_conf()?;
+    let h3_server = ServerBuiler::new(addr_h3, h3_tls)
+        .set_reuse_port(reuse_port)
+        .set_limits(limits.1)
+        .set_router(coral_net::hand::reloadable(h3_rx))
+        .h3_server(None, map_req_fn_h3)?;
+    coral_runtime::supervise::root()
+        .child("h3-server")
+        .supervise(RESTART, move || h3_server.clone().run_server());
     let map_req_fn_h2 =
         move |mut req: hyper::Request<Incoming>, router| -> RouteFuture<Infallible> {
             req.extensions_mut().insert(pool.clone());
             coral_net::hand::redirect_h2(req, router)
         };
-    Ok(
-        coral_net::server::ServerBuiler::new(addr_h2, conf.h3.tls_conf.server_conf()?)
-            .set_router(crate::http::app_h2())
-            .h2_server(Some(map_req_fn_h2))
-            .await?,
-    )
+    let (h2_routes, h2_rx) = watch::channel(crate::http::app_h2(&conf)?);
+    let (h2_tls, h2_cert) = conf.h2.tls_conf.reloadable_server_conf()?;
+    spawn_named("reload", reload(rx, h2_routes, h3_routes, h2_cert, h3_cert));
+    Ok(ServerBuiler::new(addr_h2, h2_tls)
+        .set_reuse_port(reuse_port)
+        .set_limits(limits.0)
+        .set_router(coral_net::hand::reloadable(h2_rx))
+        .h2_server(Some(map_req_fn_h2))
+        .await?)
 }
 
+/// restart of servers, which only fail by a bug
+const RESTART: Restart = Restart::OnFailure {
+    max: None,
+    backoff: Duration::from_secs(1),
+};
+
 pub fn run() -> CoralRes<()> {
-    let conf = cli::Cli::init()?;
-    let rt = conf.rt_conf.runtime("coral-proxy")?;
-    if let Err(err) = rt.block_on(server(conf)) {
-        error!(e = format!("{:?}", err); "block on server");
+    let reloader = cli::Cli::init()?;
+    let rt_conf = reloader.conf().rt_conf.clone();
+    // Shared by per core runtimes on purpose: upstreams register on whichever
+    // core accepted them, a pool per core would only reach that core. Senders
+    // in the pool are handles of connections driven by the registering core,
+    // which runs until shutdown like every other core.
+    let pool = Pool::default();
+    let limits = {
+        let conf = reloader.conf();
+        (
+            Limits::from(&conf.h2.server_conf),
+            Limits::from(&conf.h3.server_conf),
+        )
+    };
+    if rt_conf.per_core() {
+        let cores = rt_conf.cores("coral-proxy")?;
+        let rx = {
+            let _guard = cores.handle(0).enter();
+            start(reloader)?
+        };
+        let results = cores.block_on(|_| server(rx.clone(), pool.clone(), limits.clone(), true))?;
+        for err in results.into_iter().filter_map(Result::err) {
+            error!(e = format!("{:?}", err); "block on server");
+        }
+        cores.handle(0).block_on(shutdown(&rt_conf));
+    } else {
+        let rt = rt_conf.runtime("coral-proxy")?;
+        let rx = {
+            let _guard = rt.enter();
+            start(reloader)?
+        };
+        if let Err(err) = rt.block_on(server(rx, pool, limits, false)) {
+            error!(e = format!("{:?}", err); "block on server");
+        }
+        rt.block_on(shutdown(&rt_conf));
     }
     Ok(())
 }
+
+/// wait for supervised tasks once servers stopped accepting
+async fn shutdown(rt_conf: &RuntimeConf) {
+    if !coral_runtime::supervise::root()
+        .shutdown(rt_conf.shutdown_timeout())
+        .await
+    {
+        warn!("tasks not finished before shutdown timeout");
+    }
+}
@@ -0,0 +1,160 @@
+//! thread per core runtimes
+//!
+//! Each core runs a current thread runtime on its own pinned thread, tasks
+//! spawned by [`tokio::spawn`] inside a core stay on it. Servers bind one
+//! listener per core with `SO_REUSEPORT` so that the kernel spreads
+//! connections across cores.
+use std::cell::Cell;
+use std::future::Future;
+use std::sync::mpsc;
+use std::thread::JoinHandle as ThreadHandle;
+
+use fastrace::future::FutureExt;
+use tokio::runtime::Handle;
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+
+use crate::error::CoralRes;
+use crate::error::Error;
+use crate::RuntimeConf;
+
+thread_local! {
+    static CORE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// index of core running the current thread, none out of [`Cores`]
+pub fn current() -> Option<usize> {
+    CORE.with(|v| v.get())
+}
+
+/// Runtimes of cores, threads are stopped once dropped
+pub struct Cores {
+    name: String,
+    handles: Vec<Handle>,
+    threads: Vec<ThreadHandle<()>>,
+    shutdown: Vec<oneshot::Sender<()>>,
+}
+
+impl Cores {
+    /// start `nums` runtimes pinned to cores from `cpui` of `conf`, threads
+    /// are named `{th_name_pre}-{index}`
+    pub(crate) fn new(th_name_pre: &str, conf: &RuntimeConf) -> CoralRes<Self> {
+        let nums = conf.nums;
+        let cores = crate::cpu_cores(conf.cpui, nums);
+        let mut this = Self {
+            name: th_name_pre.to_owned(),
+            handles: Vec::with_capacity(nums),
+            threads: Vec::with_capacity(nums),
+            shutdown: Vec::with_capacity(nums),
+        };
+        for ix in 0..nums {
+            let core = cores.get(ix).cloned();
+            let (handle_tx, handle_rx) = mpsc::channel();
+            let (shutdown_tx, shutdown_rx) = oneshot::channel::<()>();
+            let mut builder = tokio::runtime::Builder::new_current_thread();
+            conf.tune(&mut builder);
+            // the core thread is not started by runtime, so this only pins
+            // threads of the blocking pool
+            if conf.background.is_some() {
+                builder.on_thread_start(conf.pin_background());
+            }
+            let mut thread = std::thread::Builder::new().name(format!("{}-{}", th_name_pre, ix));
+            if let Some(size) = conf.thread_stack_size {
+                thread = thread.stack_size(size);
+            }
+            let thread = thread.spawn(move || {
+                CORE.with(|v| v.set(Some(ix)));
+                if let Some(core) = core {
+                    if !core_affinity::set_for_current(core) {
+                        log::error!("failed to core affinity");
+                    }
+                }
+                match builder.build() {
+                    Ok(rt) => {
+                        let _ = handle_tx.send(Ok(rt.handle().clone()));
+                        let _ = rt.block_on(shutdown_rx);
+                    }
+                    Err(err) => {
+                        let _ = handle_tx.send(Err(err));
+                    }
+                }
+            })?;
+            this.threads.push(thread);
+            this.shutdown.push(shutdown_tx);
+            let handle = handle_rx.recv().map_err(|_| Error::CoreExited(ix))??;
+            crate::stats::register(format!("{}-{}", th_name_pre, ix), handle.clone());
+            this.handles.push(handle);
+        }
+        Ok(this)
+    }
+
+    pub fn len(&self) -> usize {
+        self.handles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handles.is_empty()
+    }
+
+    /// runtime handle of core `ix`, modulo number of cores
+    pub fn handle(&self, ix: usize) -> &Handle {
+        &self.handles[ix % self.handles.len()]
+    }
+
+    /// Spawn `future` on core `ix` in a child span of the local parent span,
+    /// like [`crate::spawn`], used to hand work across cores
+    #[track_caller]
+    pub fn spawn_on<Fut>(&self, ix: usize, future: Fut) -> JoinHandle<Fut::Output>
+    where
+        Fut: Future + Send + 'static,
+        Fut::Output: Send + 'static,
+    {
+        let span = crate::caller_span(std::panic::Location::caller());
+        self.handle(ix).spawn(future.in_span(span))
+    }
+
+    /// spawn future created by `f` with index of each core
+    pub fn each<F, Fut>(&self, f: F) -> Vec<JoinHandle<Fut::Output>>
+    where
+        F: Fn(usize) -> Fut,
+        Fut: Future + Send + 'static,
+        Fut::Output: Send + 'static,
+    {
+        self.handles
+            .iter()
+            .enumerate()
+            .map(|(ix, handle)| handle.spawn(f(ix)))
+            .collect()
+    }
+
+    /// [`Cores::each`] and block the current thread until all finished
+    pub fn block_on<F, Fut>(&self, f: F) -> CoralRes<Vec<Fut::Output>>
+    where
+        F: Fn(usize) -> Fut,
+        Fut: Future + Send + 'static,
+        Fut::Output: Send + 'static,
+    {
+        let tasks = self.each(f);
+        self.handle(0).block_on(async move {
+            let mut outputs = Vec::with_capacity(tasks.len());
+            for task in tasks {
+                outputs.push(task.await?);
+            }
+            Ok(outputs)
+        })
+    }
+}
+
+impl Drop for Cores {
+    fn drop(&mut self) {
+        for ix in 0..self.handles.len() {
+            crate::stats::unregister(&format!("{}-{}", self.name, ix));
+        }
+        self.shutdown.clear();
+        for thread in self.threads.drain(..) {
+            if thread.join().is_err() {
+                log::error!("runtime thread of core panicked");
+            }
+        }
+    }
+}
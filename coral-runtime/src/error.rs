@@ -1,3 +1,4 @@
+use coral_conf::Change;
 use thiserror::Error;
 
 pub type CoralRes<T> = Result<T, Error>;
@@ -24,4 +25,27 @@ pub enum Error {
 
     #[error("invalid cpu param")]
     InvalidCpuNum,
+
+    #[error("background cores overlap cores of workers")]
+    OverlapCores,
+
+    #[error("runtime thread of core {0} exited")]
+    CoreExited(usize),
+
+    #[error("task failed: {0}")]
+    JoinErr(#[from] tokio::task::JoinError),
+
+    #[error("{0}")]
+    ConfErr(#[from] coral_conf::Error),
+
+    #[error("changes can not be reloaded, restart to apply them: [{}]", changes(.0))]
+    NotReloadable(Vec<Change>),
+
+    #[error("reload interval must be non zero")]
+    ZeroInterval,
+}
+
+fn changes(changes: &[Change]) -> String {
+    let changes: Vec<String> = changes.iter().map(ToString::to_string).collect();
+    changes.join(", ")
 }
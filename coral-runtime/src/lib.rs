@@ -1,54 +1,182 @@
+pub mod cores;
 mod error;
+pub mod reload;
+pub mod stats;
+pub mod supervise;
 use crate::error::CoralRes;
 use coral_conf::EnvAssignToml;
 use coral_macro::EnvAssign;
+use coral_macro::Schema;
+use coral_macro::Validate;
+pub use cores::Cores;
 pub use error::Error;
+use fastrace::future::FutureExt;
+use fastrace::Span;
 use serde::Deserialize;
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::Duration;
 use std::{future::Future, sync::atomic};
 pub use tokio;
 
-#[derive(Deserialize, Debug, EnvAssign, Clone)]
+#[derive(Deserialize, Serialize, Debug, EnvAssign, Schema, Validate, Clone)]
+#[validate(custom = "Self::check")]
 pub struct RuntimeConf {
     cpui: usize,
     nums: usize,
+    /// run a current thread runtime on each core instead of one multi thread
+    /// runtime, see [`Cores`], supported by coral-proxy, rejected by
+    /// coral-server
+    per_core: Option<bool>,
+    /// max threads of blocking pool, default 512
+    #[validate(range(min = 1))]
+    max_blocking_threads: Option<usize>,
+    /// stack size of runtime threads in bytes, default 2MiB
+    thread_stack_size: Option<usize>,
+    /// ticks between polls of io and timer events, default 61
+    #[validate(range(min = 1))]
+    event_interval: Option<u32>,
+    /// ticks between polls of the global task queue
+    #[validate(range(min = 1))]
+    global_queue_interval: Option<u32>,
+    /// idle time of blocking threads before exiting, default 10s
+    #[serde(default, with = "coral_conf::duration")]
+    thread_keep_alive: Option<Duration>,
+    /// cores of blocking pool and log writer, apart from cores of workers
+    #[validate(nested)]
+    background: Option<CoreSet>,
+    /// time listeners keep accepting after SIGINT or SIGTERM, while
+    /// [`supervise::drain`] is cancelled, default 5s
+    #[serde(default, with = "coral_conf::duration")]
+    drain_timeout: Option<Duration>,
+    /// time waiting for supervised tasks to finish on shutdown, default 30s
+    #[serde(default, with = "coral_conf::duration")]
+    shutdown_timeout: Option<Duration>,
 }
 
-static ATOMIC_ID: atomic::AtomicUsize = atomic::AtomicUsize::new(0);
+/// `nums` cores from index `cpui`
+#[derive(Deserialize, Serialize, Debug, EnvAssign, Schema, Validate, Clone)]
+pub struct CoreSet {
+    cpui: usize,
+    #[validate(range(min = 1))]
+    nums: usize,
+}
 
 impl RuntimeConf {
     pub fn check(&self) -> CoralRes<()> {
         let limit = num_cpus::get();
         match self.cpui + self.nums {
-            x if x > limit => Err(Error::InvalidCpuNum),
-            x if x == 0 => Err(Error::InvalidCpuNum),
-            _ => Ok(()),
+            x if x > limit => return Err(Error::InvalidCpuNum),
+            x if x == 0 => return Err(Error::InvalidCpuNum),
+            _ => {}
+        }
+        if let Some(background) = self.background.as_ref() {
+            if background.cpui + background.nums > limit {
+                return Err(Error::InvalidCpuNum);
+            }
+            let workers = self.cpui..self.cpui + self.nums;
+            let background = background.cpui..background.cpui + background.nums;
+            if workers.start < background.end && background.start < workers.end {
+                return Err(Error::OverlapCores);
+            }
+        }
+        Ok(())
+    }
+
+    pub fn per_core(&self) -> bool {
+        self.per_core.unwrap_or(false)
+    }
+
+    /// see [`supervise::cancel_on_signal`]
+    pub fn drain_timeout(&self) -> Duration {
+        self.drain_timeout.unwrap_or(Duration::from_secs(5))
+    }
+
+    /// see [`supervise::TaskGroup::shutdown`]
+    pub fn shutdown_timeout(&self) -> Duration {
+        self.shutdown_timeout.unwrap_or(Duration::from_secs(30))
+    }
+
+    /// one runtime per core, `nums` cores from `cpui`
+    pub fn cores(&self, th_name_pre: &str) -> CoralRes<Cores> {
+        Cores::new(th_name_pre, self)
+    }
+
+    /// Pin the calling thread to the next core of `background` round robin,
+    /// noop without `background`, e.g. for the log writer thread
+    pub fn pin_background(&self) -> impl Fn() + Clone + Send + Sync + 'static {
+        let cores = match self.background.as_ref() {
+            Some(background) => cpu_cores(background.cpui, background.nums),
+            None => Vec::new(),
+        };
+        let cores = Arc::new(cores);
+        let next = Arc::new(atomic::AtomicUsize::new(0));
+        move || {
+            if cores.is_empty() {
+                return;
+            }
+            let ix = next.fetch_add(1, atomic::Ordering::Relaxed);
+            if !core_affinity::set_for_current(cores[ix % cores.len()]) {
+                log::error!("failed to core affinity");
+            }
+        }
+    }
+
+    /// apply knobs shared by multi thread runtime and runtimes of cores
+    fn tune(&self, builder: &mut tokio::runtime::Builder) {
+        builder.enable_all();
+        if let Some(v) = self.max_blocking_threads {
+            builder.max_blocking_threads(v);
+        }
+        if let Some(v) = self.thread_stack_size {
+            builder.thread_stack_size(v);
+        }
+        if let Some(v) = self.event_interval {
+            builder.event_interval(v);
+        }
+        if let Some(v) = self.global_queue_interval {
+            builder.global_queue_interval(v);
+        }
+        if let Some(v) = self.thread_keep_alive {
+            builder.thread_keep_alive(v);
         }
     }
 
     pub fn runtime(&self, th_name_pre: &'static str) -> Result<tokio::runtime::Runtime, Error> {
         let mut builder = tokio::runtime::Builder::new_multi_thread();
-        builder
-            .worker_threads(self.nums)
-            .enable_all()
-            .thread_name_fn(move || {
-                let id = ATOMIC_ID.fetch_add(1, atomic::Ordering::SeqCst);
-                format!("{}-{}", th_name_pre, id)
-            });
+        self.tune(&mut builder);
+        // workers are started first, so threads indexed from `nums` are of
+        // the blocking pool
+        let next_id = atomic::AtomicUsize::new(0);
+        builder.worker_threads(self.nums).thread_name_fn(move || {
+            let id = next_id.fetch_add(1, atomic::Ordering::SeqCst);
+            format!("{}-{}", th_name_pre, id)
+        });
         let cores = cpu_cores(self.cpui, self.nums + 1);
-        if cores.len() > 0 {
+        let background = self.background.is_some().then(|| self.pin_background());
+        let nums = self.nums;
+        if !cores.is_empty() || background.is_some() {
             builder.on_thread_start(move || {
                 #[cfg(debug_assertions)]
                 println!("[+] create runtime thread in tokio");
-                if let Ok(index) = get_thread_index() {
-                    if !core_affinity::set_for_current(cores[index % cores.len()].clone()) {
-                        log::error!("failed to core affinity");
+                match (get_thread_index(), background.as_ref()) {
+                    (Ok(index), Some(pin)) if index >= nums => pin(),
+                    (Ok(index), _) => {
+                        if !cores.is_empty()
+                            && !core_affinity::set_for_current(cores[index % cores.len()])
+                        {
+                            log::error!("failed to core affinity");
+                        }
+                    }
+                    (Err(_), _) => {
+                        log::error!("failed to get thread index on thread start");
                     }
-                } else {
-                    log::error!("failed to get thread index on thread start");
                 }
             });
         }
-        Ok(builder.build()?)
+        let rt = builder.build()?;
+        stats::register(th_name_pre.to_owned(), rt.handle().clone());
+        Ok(rt)
     }
 }
 
@@ -75,16 +203,138 @@ fn cpu_cores(start: usize, nums: usize) -> Vec<core_affinity::CoreId> {
     cores
 }
 
+/// Spawn `future` in a child span of the local parent span, so the task keeps
+/// the trace of the caller. The span is noop if there is no local parent.
+#[track_caller]
 pub fn spawn<Fut>(future: Fut) -> tokio::task::JoinHandle<Fut::Output>
 where
     Fut: Future + Send + 'static,
     Fut::Output: Send + 'static,
 {
-    tokio::spawn(future)
+    let span = caller_span(std::panic::Location::caller());
+    tokio::spawn(future.in_span(span))
+}
+
+/// [`spawn`] with `name`, which is counted in [`stats::report`] while the
+/// task is alive and logged on panic
+#[track_caller]
+pub fn spawn_named<Fut>(name: &'static str, future: Fut) -> tokio::task::JoinHandle<Fut::Output>
+where
+    Fut: Future + Send + 'static,
+    Fut::Output: Send + 'static,
+{
+    let span = caller_span(std::panic::Location::caller()).with_property(|| ("task.name", name));
+    tokio::spawn(stats::TaskGuard::new(name).scope(future).in_span(span))
+}
+
+/// Log panics with the name of the panicking task and trace id of the local
+/// parent span before the previous hook
+pub fn set_panic_hook() {
+    let prev = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let thread = std::thread::current();
+        let task = stats::task_name().unwrap_or("-");
+        let thread = thread.name().unwrap_or("-");
+        match fastrace::collector::SpanContext::current_local_parent() {
+            Some(span) => log::error!(
+                task = task,
+                thread = thread,
+                trace_id = span.trace_id.0;
+                "panicked: {}", info
+            ),
+            None => log::error!(task = task, thread = thread; "panicked: {}", info),
+        }
+        prev(info);
+    }));
+}
+
+/// span of spawning at `location`
+fn caller_span(location: &std::panic::Location) -> Span {
+    Span::enter_with_local_parent("spawn").with_properties(|| {
+        [
+            ("code.filepath", location.file().to_owned()),
+            ("code.lineno", location.line().to_string()),
+        ]
+    })
 }
 
 #[cfg(test)]
 mod tests {
+    use std::time::Duration;
+
+    use super::CoreSet;
+    use super::RuntimeConf;
+    use crate::error::Error;
+
+    fn conf(nums: usize, background: Option<CoreSet>) -> RuntimeConf {
+        RuntimeConf {
+            cpui: 0,
+            nums,
+            per_core: None,
+            max_blocking_threads: Some(4),
+            thread_stack_size: None,
+            event_interval: None,
+            global_queue_interval: None,
+            thread_keep_alive: Some(Duration::from_millis(100)),
+            background,
+            drain_timeout: None,
+            shutdown_timeout: None,
+        }
+    }
+
+    #[test]
+    fn check_background() {
+        assert!(conf(1, None).check().is_ok());
+        let overlap = conf(1, Some(CoreSet { cpui: 0, nums: 1 }));
+        assert!(matches!(overlap.check(), Err(Error::OverlapCores)));
+        let beyond = conf(
+            1,
+            Some(CoreSet {
+                cpui: num_cpus::get(),
+                nums: 1,
+            }),
+        );
+        assert!(matches!(beyond.check(), Err(Error::InvalidCpuNum)));
+    }
+
+    #[test]
+    fn per_core() {
+        let conf = conf(2, None);
+        let cores = conf.cores("coral-test").unwrap();
+        assert_eq!(cores.len(), 2);
+        assert_eq!(crate::cores::current(), None);
+        let ixs = cores
+            .block_on(|_| async { crate::cores::current() })
+            .unwrap();
+        assert_eq!(ixs, [Some(0), Some(1)]);
+        let handed = cores.spawn_on(3, async { crate::cores::current() });
+        assert_eq!(cores.handle(0).block_on(handed).unwrap(), Some(1));
+    }
+
+    #[test]
+    fn named_tasks() {
+        let rt = conf(1, None).runtime("coral-stats").unwrap();
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let task = {
+            let _guard = rt.enter();
+            crate::spawn_named("test-named", async move {
+                let _ = rx.await;
+                crate::stats::task_name()
+            })
+        };
+        let report = crate::stats::report();
+        assert_eq!(report.tasks.get("test-named"), Some(&1));
+        let stats = report
+            .runtimes
+            .iter()
+            .find(|v| v.name == "coral-stats")
+            .unwrap();
+        assert_eq!(stats.workers, 1);
+        let _ = tx.send(());
+        assert_eq!(rt.block_on(task).unwrap(), Some("test-named"));
+        assert_eq!(crate::stats::report().tasks.get("test-named"), Some(&0));
+        assert_eq!(crate::stats::task_name(), None);
+    }
 
     #[test]
     fn split_thname() {
@@ -0,0 +1,208 @@
+//! live reload of config
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use std::time::SystemTime;
+
+use coral_conf::Change;
+use coral_conf::EnvAssignToml;
+use coral_conf::Loader;
+use coral_conf::Schema;
+use coral_conf::Validate;
+use log::error;
+use log::info;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use tokio::signal::unix::signal;
+use tokio::signal::unix::SignalKind;
+use tokio::sync::watch;
+
+use crate::error::CoralRes;
+use crate::error::Error;
+
+/// Reload config on SIGHUP or modification of any config file, publishing
+/// accepted configs through a watch channel
+///
+/// A new config is rejected and the current one kept if it fails to load or
+/// validate, or if any value out of the reloadable paths changed. Files only
+/// referenced by config, like tls certificates, are reloaded on SIGHUP.
+pub struct Reloader<T> {
+    loader: Loader,
+    reloadable: Vec<&'static str>,
+    interval: Duration,
+    mtimes: Vec<(PathBuf, Option<SystemTime>)>,
+    tx: watch::Sender<Arc<T>>,
+}
+
+impl<T> Reloader<T>
+where
+    T: DeserializeOwned + Serialize + EnvAssignToml + Validate + Schema + Send + Sync + 'static,
+{
+    /// load and validate the initial config by `loader`
+    pub fn new(loader: Loader) -> CoralRes<Self> {
+        let loaded = loader.clone().load::<T>()?;
+        let mtimes = mtimes(loaded.files.iter());
+        let conf = loaded.validate()?;
+        let (tx, _) = watch::channel(Arc::new(conf));
+        Ok(Self {
+            loader,
+            reloadable: Vec::new(),
+            interval: Duration::from_secs(5),
+            mtimes,
+            tx,
+        })
+    }
+
+    /// dotted paths applied in place, changes of any other value are rejected
+    pub fn reloadable(mut self, paths: &[&'static str]) -> Self {
+        self.reloadable.extend_from_slice(paths);
+        self
+    }
+
+    /// interval of checking modification of config files, default 5s, must
+    /// be non zero
+    pub fn interval(mut self, interval: Duration) -> CoralRes<Self> {
+        if interval.is_zero() {
+            return Err(Error::ZeroInterval);
+        }
+        self.interval = interval;
+        Ok(self)
+    }
+
+    /// current config
+    pub fn conf(&self) -> Arc<T> {
+        self.tx.borrow().clone()
+    }
+
+    pub fn subscribe(&self) -> watch::Receiver<Arc<T>> {
+        self.tx.subscribe()
+    }
+
+    /// Reload and publish the new config even if nothing changed, so that
+    /// referenced files are reloaded, returns the changes
+    pub fn reload(&mut self) -> CoralRes<Vec<Change>> {
+        // a broken file is reported once, not on every check
+        self.mtimes = mtimes(self.mtimes.iter().map(|(path, _)| path));
+        let loaded = self.loader.clone().load::<T>()?;
+        self.mtimes = mtimes(loaded.files.iter());
+        let conf = loaded.validate()?;
+        let changes = coral_conf::diff::diff(self.tx.borrow().as_ref(), &conf)?;
+        let fixed: Vec<Change> = changes
+            .iter()
+            .filter(|c| !c.within(&self.reloadable))
+            .cloned()
+            .collect();
+        if !fixed.is_empty() {
+            return Err(Error::NotReloadable(fixed));
+        }
+        self.tx.send_replace(Arc::new(conf));
+        Ok(changes)
+    }
+
+    fn modified(&self) -> bool {
+        self.mtimes
+            .iter()
+            .any(|(path, mtime)| mtime_of(path) != *mtime)
+    }
+
+    /// reload on SIGHUP and modification of config files until the runtime
+    /// shuts down
+    pub async fn run(mut self) {
+        let mut tick = tokio::time::interval(self.interval);
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(v) => Some(v),
+            Err(err) => {
+                error!(e = format!("{:?}", err); "failed to listen SIGHUP, reload on file change only");
+                None
+            }
+        };
+        loop {
+            tokio::select! {
+                _ = tick.tick() => {
+                    if !self.modified() {
+                        continue;
+                    }
+                }
+                Some(_) = async { hangup.as_mut()?.recv().await } => {}
+            }
+            match self.reload() {
+                Ok(changes) => {
+                    let changes: Vec<String> = changes.iter().map(ToString::to_string).collect();
+                    info!("config reloaded, changes: [{}]", changes.join(", "));
+                }
+                Err(err) => {
+                    error!(e = err.to_string(); "config rejected, keep the current one");
+                }
+            }
+        }
+    }
+}
+
+fn mtime_of(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+fn mtimes<'a, I: Iterator<Item = &'a PathBuf>>(paths: I) -> Vec<(PathBuf, Option<SystemTime>)> {
+    paths.map(|path| (path.clone(), mtime_of(path))).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use coral_conf::EnvAssignToml;
+    use coral_macro::EnvAssign;
+    use coral_macro::Schema;
+    use coral_macro::Validate;
+    use serde::Deserialize;
+    use serde::Serialize;
+
+    use super::Reloader;
+    use crate::error::Error;
+
+    #[derive(Deserialize, Serialize, EnvAssign, Schema, Validate)]
+    struct Conf {
+        #[validate(port)]
+        port: u16,
+        level: String,
+    }
+
+    #[test]
+    fn reload() {
+        let path = std::env::temp_dir().join(format!("coral-reload-{}.toml", std::process::id()));
+        std::fs::write(&path, "port = 80\nlevel = \"info\"").unwrap();
+        let mut reloader = Reloader::<Conf>::new(coral_conf::Loader::new().file(&path))
+            .unwrap()
+            .reloadable(&["level"])
+            .interval(Duration::from_secs(1))
+            .unwrap();
+        let rx = reloader.subscribe();
+        assert!(!reloader.modified());
+
+        std::fs::write(&path, "port = 80\nlevel = \"debug\"").unwrap();
+        let changes = reloader.reload().unwrap();
+        assert_eq!(changes.len(), 1);
+        assert_eq!(changes[0].path, "level");
+        assert_eq!(rx.borrow().level, "debug");
+
+        std::fs::write(&path, "port = 81\nlevel = \"warn\"").unwrap();
+        match reloader.reload() {
+            Err(Error::NotReloadable(fixed)) => {
+                assert_eq!(fixed.len(), 1);
+                assert_eq!(fixed[0].to_string(), "port: 80 -> 81");
+            }
+            _ => panic!("port should not be reloadable"),
+        }
+        assert_eq!(rx.borrow().level, "debug");
+
+        std::fs::write(&path, "port = 0\nlevel = \"warn\"").unwrap();
+        assert!(matches!(reloader.reload(), Err(Error::ConfErr(_))));
+        assert_eq!(reloader.conf().port, 80);
+        assert!(matches!(
+            reloader.interval(Duration::ZERO),
+            Err(Error::ZeroInterval)
+        ));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,153 @@
+//! introspection of runtimes and named tasks
+//!
+//! Runtimes built by [`crate::RuntimeConf`] are registered here. Per worker
+//! stats, queue depths and blocking threads need `--cfg tokio_unstable`, they
+//! are empty otherwise.
+use std::collections::BTreeMap;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use serde::Serialize;
+use tokio::runtime::Handle;
+
+static RUNTIMES: Mutex<Vec<(String, Handle)>> = Mutex::new(Vec::new());
+/// counters of alive tasks by name, looked up once per name or group, so that
+/// spawning only touches an atomic
+static TASKS: Mutex<BTreeMap<&'static str, Arc<AtomicUsize>>> = Mutex::new(BTreeMap::new());
+
+tokio::task_local! {
+    static TASK_NAME: &'static str;
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct WorkerStats {
+    /// total time busy polling tasks
+    pub busy_secs: f64,
+    pub polls: u64,
+    pub local_queue_depth: usize,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct RuntimeStats {
+    pub name: String,
+    pub workers: usize,
+    pub alive_tasks: usize,
+    pub global_queue_depth: Option<usize>,
+    pub blocking_threads: Option<usize>,
+    pub idle_blocking_threads: Option<usize>,
+    pub worker_stats: Vec<WorkerStats>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+pub struct Report {
+    pub runtimes: Vec<RuntimeStats>,
+    /// alive tasks by name of [`crate::spawn_named`]
+    pub tasks: BTreeMap<&'static str, usize>,
+}
+
+/// register runtime `name`, replacing the runtime of the same name
+pub(crate) fn register(name: String, handle: Handle) {
+    if let Ok(mut runtimes) = RUNTIMES.lock() {
+        runtimes.retain(|(v, _)| *v != name);
+        runtimes.push((name, handle));
+    }
+}
+
+pub(crate) fn unregister(name: &str) {
+    if let Ok(mut runtimes) = RUNTIMES.lock() {
+        runtimes.retain(|(v, _)| v != name);
+    }
+}
+
+/// stats of every registered runtime and named task
+pub fn report() -> Report {
+    let runtimes = match RUNTIMES.lock() {
+        Ok(runtimes) => runtimes
+            .iter()
+            .map(|(name, handle)| runtime_stats(name, handle))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    let tasks = TASKS
+        .lock()
+        .map(|v| {
+            v.iter()
+                .map(|(name, count)| (*name, count.load(Ordering::Relaxed)))
+                .collect()
+        })
+        .unwrap_or_default();
+    Report { runtimes, tasks }
+}
+
+pub fn runtime_stats(name: &str, handle: &Handle) -> RuntimeStats {
+    let metrics = handle.metrics();
+    #[allow(unused_mut)]
+    let mut stats = RuntimeStats {
+        name: name.to_owned(),
+        workers: metrics.num_workers(),
+        alive_tasks: metrics.num_alive_tasks(),
+        global_queue_depth: None,
+        blocking_threads: None,
+        idle_blocking_threads: None,
+        worker_stats: Vec::new(),
+    };
+    #[cfg(all(tokio_unstable, target_has_atomic = "64"))]
+    {
+        stats.global_queue_depth = Some(metrics.injection_queue_depth());
+        stats.blocking_threads = Some(metrics.num_blocking_threads());
+        stats.idle_blocking_threads = Some(metrics.num_idle_blocking_threads());
+        stats.worker_stats = (0..stats.workers)
+            .map(|worker| WorkerStats {
+                busy_secs: metrics.worker_total_busy_duration(worker).as_secs_f64(),
+                polls: metrics.worker_poll_count(worker),
+                local_queue_depth: metrics.worker_local_queue_depth(worker),
+            })
+            .collect();
+    }
+    stats
+}
+
+/// name of the current task if spawned by [`crate::spawn_named`]
+pub fn task_name() -> Option<&'static str> {
+    TASK_NAME.try_with(|v| *v).ok()
+}
+
+/// counter of alive tasks `name`, kept at zero once all finished, so that
+/// gauges of the name are reset
+pub(crate) fn task_counter(name: &'static str) -> Arc<AtomicUsize> {
+    match TASKS.lock() {
+        Ok(mut tasks) => tasks.entry(name).or_default().clone(),
+        Err(_) => Arc::default(),
+    }
+}
+
+/// count of alive task `name`, till dropped with the task
+pub(crate) struct TaskGuard {
+    name: &'static str,
+    count: Arc<AtomicUsize>,
+}
+
+impl TaskGuard {
+    pub(crate) fn new(name: &'static str) -> Self {
+        Self::with(name, task_counter(name))
+    }
+
+    /// guard counted by `count` of [`task_counter`]
+    pub(crate) fn with(name: &'static str, count: Arc<AtomicUsize>) -> Self {
+        count.fetch_add(1, Ordering::Relaxed);
+        Self { name, count }
+    }
+
+    /// run `future` with task name of the guard
+    pub(crate) async fn scope<Fut: std::future::Future>(self, future: Fut) -> Fut::Output {
+        TASK_NAME.scope(self.name, future).await
+    }
+}
+
+impl Drop for TaskGuard {
+    fn drop(&mut self) {
+        self.count.fetch_sub(1, Ordering::Relaxed);
+    }
+}
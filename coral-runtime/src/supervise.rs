@@ -0,0 +1,366 @@
+//! supervised tasks
+//!
+//! Cancelling the token of a [`TaskGroup`] stops its supervised loops, like
+//! accept loops of servers, while spawned tasks, like connections, drain and
+//! finish on their own. Tasks left after the timeout of
+//! [`TaskGroup::shutdown`] are dropped. Panics are caught, so that they do not
+//! vanish with the dropped `JoinHandle`, and logged once by
+//! [`crate::set_panic_hook`]. Groups form a tree under [`root`], cancelling
+//! and shutting down a group covers its children. [`drain`] is cancelled
+//! before [`root`], while listeners still accept, e.g. to tell clients to
+//! move away.
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::atomic::AtomicUsize;
+use std::sync::Arc;
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use fastrace::future::FutureExt as _;
+use futures::FutureExt;
+use tokio::signal::unix::signal;
+use tokio::signal::unix::SignalKind;
+use tokio::task::JoinHandle;
+pub use tokio_util::sync::CancellationToken;
+use tokio_util::task::TaskTracker;
+
+use crate::error::CoralRes;
+use crate::stats::TaskGuard;
+
+static ROOT: OnceLock<TaskGroup> = OnceLock::new();
+
+/// group of all groups, cancelled by [`cancel_on_signal`]
+pub fn root() -> &'static TaskGroup {
+    ROOT.get_or_init(|| TaskGroup::new("root"))
+}
+
+static DRAIN: OnceLock<CancellationToken> = OnceLock::new();
+
+/// token cancelled by [`cancel_on_signal`] before [`root`], and with it
+pub fn drain() -> &'static CancellationToken {
+    DRAIN.get_or_init(|| root().token().child_token())
+}
+
+/// restart policy of [`TaskGroup::supervise`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Restart {
+    Never,
+    /// restart after error or panic, at most `max` times if given
+    OnFailure {
+        max: Option<usize>,
+        backoff: Duration,
+    },
+    /// restart also after finished
+    Always {
+        backoff: Duration,
+    },
+}
+
+impl Restart {
+    /// backoff before the next run, none if not restarted
+    fn next(&self, failed: bool, restarts: usize) -> Option<Duration> {
+        match *self {
+            Restart::Never => None,
+            Restart::OnFailure { max, backoff } => {
+                (failed && max.map_or(true, |max| restarts < max)).then_some(backoff)
+            }
+            Restart::Always { backoff } => Some(backoff),
+        }
+    }
+}
+
+/// Tasks sharing a cancellation token, counted by name in
+/// [`crate::stats::report`]
+#[derive(Clone, Debug)]
+pub struct TaskGroup {
+    name: &'static str,
+    token: CancellationToken,
+    /// cancelled once shutdown timed out, dropping tasks left
+    abort: CancellationToken,
+    /// alive tasks of the name, see [`crate::stats::task_counter`]
+    count: Arc<AtomicUsize>,
+    /// trackers of the group and its ancestors
+    trackers: Vec<TaskTracker>,
+}
+
+impl TaskGroup {
+    pub fn new(name: &'static str) -> Self {
+        Self {
+            name,
+            token: CancellationToken::new(),
+            abort: CancellationToken::new(),
+            count: crate::stats::task_counter(name),
+            trackers: vec![TaskTracker::new()],
+        }
+    }
+
+    /// group cancelled with this group and waited by its shutdown
+    pub fn child(&self, name: &'static str) -> Self {
+        let mut trackers = self.trackers.clone();
+        trackers.push(TaskTracker::new());
+        Self {
+            name,
+            token: self.token.child_token(),
+            abort: self.abort.child_token(),
+            count: crate::stats::task_counter(name),
+            trackers,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn token(&self) -> &CancellationToken {
+        &self.token
+    }
+
+    /// alive tasks of the group and its children
+    pub fn len(&self) -> usize {
+        self.tracker().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tracker().is_empty()
+    }
+
+    fn tracker(&self) -> &TaskTracker {
+        &self.trackers[self.trackers.len() - 1]
+    }
+
+    /// Spawn `future` like [`crate::spawn_named`] with name of the group, it
+    /// runs on after cancelled, till finished or dropped by a timed out
+    /// shutdown. The output is none if dropped or panicked.
+    #[track_caller]
+    pub fn spawn<Fut>(&self, future: Fut) -> JoinHandle<Option<Fut::Output>>
+    where
+        Fut: Future + Send + 'static,
+        Fut::Output: Send + 'static,
+    {
+        let abort = self.abort.clone();
+        let future = async move {
+            tokio::select! {
+                biased;
+                _ = abort.cancelled() => None,
+                res = AssertUnwindSafe(future).catch_unwind() => res.ok(),
+            }
+        };
+        self.spawn_tracked(future)
+    }
+
+    /// Run future created by `f` and run it again by `restart`, for long
+    /// lived loops like accept loops of servers, which should return once the
+    /// token is cancelled. Not restarted after cancelled, dropped by a timed
+    /// out shutdown. Errors are logged.
+    #[track_caller]
+    pub fn supervise<F, Fut, E>(&self, restart: Restart, mut f: F) -> JoinHandle<()>
+    where
+        F: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: std::fmt::Debug + Send,
+    {
+        let name = self.name;
+        let token = self.token.clone();
+        let abort = self.abort.clone();
+        let future = async move {
+            let mut restarts = 0;
+            loop {
+                let res = tokio::select! {
+                    biased;
+                    _ = abort.cancelled() => return,
+                    res = AssertUnwindSafe(f()).catch_unwind() => res,
+                };
+                let failed = match res {
+                    Ok(Ok(())) => false,
+                    Ok(Err(err)) => {
+                        log::error!(task = name, e = format!("{:?}", err); "supervised task failed");
+                        true
+                    }
+                    // logged by the panic hook
+                    Err(_) => true,
+                };
+                if token.is_cancelled() {
+                    return;
+                }
+                let Some(backoff) = restart.next(failed, restarts) else {
+                    return;
+                };
+                restarts += 1;
+                log::warn!(task = name, restarts = restarts; "restart supervised task");
+                tokio::select! {
+                    biased;
+                    _ = token.cancelled() => return,
+                    _ = tokio::time::sleep(backoff) => {}
+                }
+            }
+        };
+        self.spawn_tracked(future)
+    }
+
+    #[track_caller]
+    fn spawn_tracked<Fut>(&self, future: Fut) -> JoinHandle<Fut::Output>
+    where
+        Fut: Future + Send + 'static,
+        Fut::Output: Send + 'static,
+    {
+        let name = self.name;
+        let span = crate::caller_span(std::panic::Location::caller())
+            .with_property(|| ("task.name", name));
+        let mut future = TaskGuard::with(name, self.count.clone())
+            .scope(future)
+            .boxed();
+        for tracker in self.trackers.iter() {
+            future = tracker.track_future(future).boxed();
+        }
+        tokio::spawn(future.in_span(span))
+    }
+
+    /// Cancel the group and its children, then wait for all of their tasks
+    /// at most `timeout`. False if timed out, tasks left are dropped then.
+    pub async fn shutdown(&self, timeout: Duration) -> bool {
+        self.token.cancel();
+        let tracker = self.tracker();
+        tracker.close();
+        if tokio::time::timeout(timeout, tracker.wait()).await.is_ok() {
+            return true;
+        }
+        self.abort.cancel();
+        false
+    }
+}
+
+/// Cancel [`drain`] on SIGINT or SIGTERM, then [`root`] after
+/// `drain_timeout` or on the next signal.
+pub fn cancel_on_signal(drain_timeout: Duration) -> CoralRes<()> {
+    let mut interrupt = signal(SignalKind::interrupt())?;
+    let mut terminate = signal(SignalKind::terminate())?;
+    crate::spawn_named("signal", async move {
+        tokio::select! {
+            _ = interrupt.recv() => {}
+            _ = terminate.recv() => {}
+        }
+        log::info!("draining");
+        let drained = drain_then_cancel(drain(), root().token(), drain_timeout);
+        tokio::select! {
+            _ = drained => {}
+            _ = interrupt.recv() => root().token().cancel(),
+            _ = terminate.recv() => root().token().cancel(),
+        }
+        log::info!("shutting down");
+    });
+    Ok(())
+}
+
+async fn drain_then_cancel(
+    drain: &CancellationToken,
+    token: &CancellationToken,
+    drain_timeout: Duration,
+) {
+    drain.cancel();
+    tokio::time::sleep(drain_timeout).await;
+    token.cancel();
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::drain_then_cancel;
+    use super::CancellationToken;
+    use super::Restart;
+    use super::TaskGroup;
+
+    #[test]
+    fn supervise() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let group = TaskGroup::new("test-group");
+            let child = group.child("test-child");
+            let panicked = child.spawn(async { panic!("boom") });
+            assert_eq!(panicked.await.unwrap(), None::<()>);
+            assert_eq!(child.spawn(async { 1 }).await.unwrap(), Some(1));
+
+            let runs = Arc::new(AtomicUsize::new(0));
+            let counter = runs.clone();
+            let restart = Restart::OnFailure {
+                max: Some(2),
+                backoff: Duration::from_millis(1),
+            };
+            let supervised = child.supervise(restart, move || {
+                let run = counter.fetch_add(1, Ordering::SeqCst);
+                async move {
+                    match run {
+                        0 => Err("failed"),
+                        _ => panic!("boom"),
+                    }
+                }
+            });
+            supervised.await.unwrap();
+            assert_eq!(runs.load(Ordering::SeqCst), 3);
+
+            // accept loops stop on cancel, connections drain
+            let accepting = child.supervise(Restart::Never, {
+                let token = child.token().clone();
+                move || {
+                    let token = token.clone();
+                    async move {
+                        token.cancelled().await;
+                        Ok::<_, ()>(())
+                    }
+                }
+            });
+            let token = child.token().clone();
+            let draining = child.spawn(async move {
+                token.cancelled().await;
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                1
+            });
+            assert_eq!(group.len(), 2);
+            assert!(group.shutdown(Duration::from_secs(1)).await);
+            assert!(child.token().is_cancelled());
+            accepting.await.unwrap();
+            assert_eq!(draining.await.unwrap(), Some(1));
+            assert!(group.is_empty());
+
+            // dropped once shutdown timed out
+            let group = TaskGroup::new("test-group");
+            let pending = group.spawn(std::future::pending::<()>());
+            assert!(!group.shutdown(Duration::from_millis(10)).await);
+            assert_eq!(pending.await.unwrap(), None);
+            assert!(group.is_empty());
+        });
+    }
+
+    #[test]
+    fn drain() {
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            let token = CancellationToken::new();
+            let drain = token.child_token();
+            let drained = tokio::spawn({
+                let (drain, token) = (drain.clone(), token.clone());
+                async move { drain_then_cancel(&drain, &token, Duration::from_millis(50)).await }
+            });
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert!(drain.is_cancelled());
+            assert!(!token.is_cancelled());
+            drained.await.unwrap();
+            assert!(token.is_cancelled());
+
+            // cancelled with the root token
+            let token = CancellationToken::new();
+            let drain = token.child_token();
+            token.cancel();
+            assert!(drain.is_cancelled());
+        });
+    }
+}
@@ -0,0 +1,474 @@
+//! static assets
+//!
+//! Every mount of [`AssetsConf`] is served from a directory with its
+//! precompressed variants, and optionally falls back to `index.html` for
+//! client side routing of a single page app. Responses get weak ETags and
+//! `Cache-Control` by glob, the same over h2 and h3.
+use std::collections::hash_map::DefaultHasher;
+use std::convert::Infallible;
+use std::hash::Hash;
+use std::hash::Hasher;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::Request;
+use axum::http::header::CACHE_CONTROL;
+use axum::http::header::CONTENT_ENCODING;
+use axum::http::header::CONTENT_LENGTH;
+use axum::http::header::CONTENT_TYPE;
+use axum::http::header::ETAG;
+use axum::http::header::IF_NONE_MATCH;
+use axum::http::header::LAST_MODIFIED;
+use axum::http::HeaderMap;
+use axum::http::HeaderValue;
+use axum::http::Method;
+use axum::http::StatusCode;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use coral_conf::EnvAssignToml;
+use coral_macro::EnvAssign;
+use coral_macro::Schema;
+use coral_macro::Validate;
+use glob::MatchOptions;
+use glob::Pattern;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::ServiceExt;
+use tower_http::services::ServeDir;
+use tower_http::services::ServeFile;
+
+use crate::error::CoralRes;
+
+static IMMUTABLE: HeaderValue = HeaderValue::from_static("public, max-age=31536000, immutable");
+
+/// `*` stays in a path segment, `**` crosses them
+const GLOB_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: true,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// `Cache-Control` of assets matching `glob`
+#[derive(Deserialize, Serialize, Debug, EnvAssign, Schema, Validate, Clone)]
+pub struct CacheRule {
+    /// glob of paths under the mount, e.g. `**/*.html`
+    #[validate(custom = "CacheRule::check_glob")]
+    glob: String,
+    /// value of `Cache-Control`, e.g. `no-cache`
+    value: String,
+}
+
+impl CacheRule {
+    fn check_glob(glob: &str) -> Result<(), glob::PatternError> {
+        Pattern::new(glob).map(|_| ())
+    }
+}
+
+#[derive(Deserialize, Serialize, Debug, EnvAssign, Schema, Validate, Clone)]
+pub struct AssetsConf {
+    /// route prefix of the mount
+    path: String,
+    dir: String,
+    /// serve `index.html` of `dir` for missing paths without extension,
+    /// default false
+    spa: Option<bool>,
+    /// list directories without `index.html`, default false
+    listing: Option<bool>,
+    /// first matching rule applies before `immutable_hashed`
+    #[validate(nested)]
+    cache_control: Option<Vec<CacheRule>>,
+    /// cache files with a content hash in name like `app.3f9a0c1b.js`
+    /// forever, default true
+    immutable_hashed: Option<bool>,
+}
+
+impl AssetsConf {
+    /// router of all mounts
+    pub fn router(mounts: &[AssetsConf]) -> CoralRes<axum::Router> {
+        mounts.iter().try_fold(axum::Router::new(), |router, conf| {
+            let mount = Arc::new(Mount::new(conf)?);
+            let service = tower::service_fn(move |req| serve(mount.clone(), req));
+            Ok(router.nest_service(&conf.path, service))
+        })
+    }
+}
+
+struct Mount {
+    dir: PathBuf,
+    serve_dir: ServeDir,
+    spa: bool,
+    listing: bool,
+    rules: Vec<(Pattern, HeaderValue)>,
+    immutable_hashed: bool,
+}
+
+impl Mount {
+    fn new(conf: &AssetsConf) -> CoralRes<Self> {
+        let rules = conf
+            .cache_control
+            .iter()
+            .flatten()
+            .map(|rule| {
+                Ok((
+                    Pattern::new(&rule.glob)?,
+                    HeaderValue::from_str(&rule.value)?,
+                ))
+            })
+            .collect::<CoralRes<Vec<_>>>()?;
+        Ok(Self {
+            dir: PathBuf::from(&conf.dir),
+            serve_dir: ServeDir::new(&conf.dir)
+                .precompressed_gzip()
+                .precompressed_br()
+                .precompressed_deflate()
+                .precompressed_zstd(),
+            spa: conf.spa.unwrap_or(false),
+            listing: conf.listing.unwrap_or(false),
+            rules,
+            immutable_hashed: conf.immutable_hashed.unwrap_or(true),
+        })
+    }
+
+    fn cache_control(&self, path: &str) -> Option<HeaderValue> {
+        let path = path.trim_start_matches('/');
+        let name = path.rsplit('/').next().unwrap_or_default();
+        self.rules
+            .iter()
+            .find(|(pattern, _)| pattern.matches_with(path, GLOB_OPTIONS))
+            .map(|(_, value)| value.clone())
+            .or_else(|| (self.immutable_hashed && is_hashed(name)).then(|| IMMUTABLE.clone()))
+    }
+}
+
+async fn serve(mount: Arc<Mount>, req: Request) -> Result<Response, Infallible> {
+    let mut path = req.uri().path().to_owned();
+    let method = req.method().clone();
+    let headers = req.headers().clone();
+    let mut rsp = match mount.serve_dir.clone().oneshot(req).await {
+        Ok(rsp) => rsp.map(Body::new),
+        Err(err) => match err {},
+    };
+    let readable = method == Method::GET || method == Method::HEAD;
+    if rsp.status() == StatusCode::NOT_FOUND && readable {
+        if let Some(listing) = listing(&mount, &path).await {
+            return Ok(listing);
+        }
+        if mount.spa && !path.rsplit('/').next().unwrap_or_default().contains('.') {
+            let mut req = Request::new(Body::empty());
+            *req.method_mut() = method;
+            *req.headers_mut() = headers.clone();
+            let index = ServeFile::new(mount.dir.join("index.html"))
+                .precompressed_gzip()
+                .precompressed_br()
+                .precompressed_deflate()
+                .precompressed_zstd();
+            rsp = match index.oneshot(req).await {
+                Ok(rsp) => rsp.map(Body::new),
+                Err(err) => match err {},
+            };
+            path = String::from("/index.html");
+        }
+    }
+    if rsp.status() == StatusCode::OK {
+        if let Some(etag) = etag(rsp.headers()) {
+            if matches_etag(&headers, &etag) {
+                let mut not_modified = StatusCode::NOT_MODIFIED.into_response();
+                for name in [LAST_MODIFIED, CONTENT_ENCODING] {
+                    if let Some(value) = rsp.headers().get(&name) {
+                        not_modified.headers_mut().insert(name, value.clone());
+                    }
+                }
+                rsp = not_modified;
+            }
+            rsp.headers_mut().insert(ETAG, etag);
+        }
+    }
+    let cacheable = matches!(
+        rsp.status(),
+        StatusCode::OK | StatusCode::PARTIAL_CONTENT | StatusCode::NOT_MODIFIED
+    );
+    if cacheable {
+        if let Some(value) = mount.cache_control(&path) {
+            rsp.headers_mut().insert(CACHE_CONTROL, value);
+        }
+    }
+    Ok(rsp)
+}
+
+/// weak ETag of the modified time, size and encoding of a file
+fn etag(headers: &HeaderMap) -> Option<HeaderValue> {
+    let mut hasher = DefaultHasher::new();
+    headers.get(LAST_MODIFIED)?.as_bytes().hash(&mut hasher);
+    headers
+        .get(CONTENT_ENCODING)
+        .map(HeaderValue::as_bytes)
+        .hash(&mut hasher);
+    let len = headers.get(CONTENT_LENGTH)?.to_str().ok()?;
+    HeaderValue::from_str(&format!("W/\"{}-{:x}\"", len, hasher.finish())).ok()
+}
+
+/// `If-None-Match` of `headers` has `etag`, compared weakly
+fn matches_etag(headers: &HeaderMap, etag: &HeaderValue) -> bool {
+    let etag = etag.to_str().unwrap_or_default().trim_start_matches("W/");
+    headers
+        .get_all(IF_NONE_MATCH)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|v| v.trim())
+        .any(|v| v == "*" || v.trim_start_matches("W/") == etag)
+}
+
+/// file names with a content hash, like `app.3f9a0c1b.js` or
+/// `index-BXk3Z9aQ.js`
+fn is_hashed(name: &str) -> bool {
+    let Some((stem, _)) = name.rsplit_once('.') else {
+        return false;
+    };
+    let Some((_, hash)) = stem.rsplit_once(['.', '-']) else {
+        return false;
+    };
+    (8..=64).contains(&hash.len())
+        && hash.bytes().all(|v| v.is_ascii_alphanumeric() || v == b'_')
+        && hash.bytes().any(|v| v.is_ascii_digit())
+}
+
+/// html listing of the directory of `path` if enabled and the directory has
+/// no `index.html`
+async fn listing(mount: &Mount, path: &str) -> Option<Response> {
+    if !mount.listing || !path.ends_with('/') {
+        return None;
+    }
+    let decoded = percent_decode(path)?;
+    if decoded.split('/').any(|v| v == ".." || v.contains('\\')) {
+        return None;
+    }
+    let dir = mount.dir.join(decoded.trim_start_matches('/'));
+    let mut read_dir = coral_runtime::tokio::fs::read_dir(&dir).await.ok()?;
+    let mut entries = Vec::new();
+    while let Ok(Some(entry)) = read_dir.next_entry().await {
+        let is_dir = entry.file_type().await.is_ok_and(|v| v.is_dir());
+        let name = entry.file_name().to_string_lossy().into_owned();
+        entries.push((name, is_dir));
+    }
+    entries.sort();
+    let title = format!("Index of {}", escape(&decoded));
+    let items: String = entries
+        .iter()
+        .map(|(name, is_dir)| {
+            let slash = if *is_dir { "/" } else { "" };
+            format!(
+                "<li><a href=\"{}{2}\">{}{2}</a></li>",
+                percent_encode(name),
+                escape(name),
+                slash
+            )
+        })
+        .collect();
+    let html = format!(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>{0}</title></head>\
+         <body><h1>{0}</h1><ul>{1}</ul></body></html>",
+        title, items
+    );
+    Some(([(CONTENT_TYPE, "text/html; charset=utf-8")], html).into_response())
+}
+
+fn percent_decode(path: &str) -> Option<String> {
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut ix = 0;
+    while ix < bytes.len() {
+        match bytes[ix] {
+            b'%' => {
+                let hex = std::str::from_utf8(bytes.get(ix + 1..ix + 3)?).ok()?;
+                decoded.push(u8::from_str_radix(hex, 16).ok()?);
+                ix += 3;
+            }
+            v => {
+                decoded.push(v);
+                ix += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok()
+}
+
+/// `name` as a relative path segment, e.g. `a%20b` of `a b` and `a%3Ab` of
+/// `a:b`, which is not taken for a scheme
+fn percent_encode(name: &str) -> String {
+    name.bytes()
+        .map(|v| match v {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                char::from(v).to_string()
+            }
+            v => format!("%{:02X}", v),
+        })
+        .collect()
+}
+
+fn escape(text: &str) -> String {
+    text.chars()
+        .map(|v| match v {
+            '&' => String::from("&amp;"),
+            '<' => String::from("&lt;"),
+            '>' => String::from("&gt;"),
+            '"' => String::from("&quot;"),
+            '\'' => String::from("&#39;"),
+            v => v.to_string(),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use axum::body::Body;
+    use axum::http::header::CACHE_CONTROL;
+    use axum::http::header::ETAG;
+    use axum::http::header::IF_NONE_MATCH;
+    use axum::http::HeaderMap;
+    use axum::http::HeaderValue;
+    use axum::http::Request;
+    use axum::http::StatusCode;
+    use axum::response::Response;
+    use http_body_util::BodyExt;
+    use tower::ServiceExt;
+
+    use super::is_hashed;
+    use super::matches_etag;
+    use super::percent_decode;
+    use super::percent_encode;
+    use super::AssetsConf;
+
+    #[test]
+    fn hashed() {
+        assert!(is_hashed("app.3f9a0c1b.js"));
+        assert!(is_hashed("index-BXk3Z9aQ.css"));
+        assert!(!is_hashed("index.html"));
+        assert!(!is_hashed("vendor-libraries.js"));
+        assert!(!is_hashed("app.3f9a0c1b.js.map"));
+    }
+
+    #[test]
+    fn etag() {
+        let etag = HeaderValue::from_static("W/\"12-af\"");
+        let mut headers = HeaderMap::new();
+        assert!(!matches_etag(&headers, &etag));
+        headers.insert(
+            "if-none-match",
+            HeaderValue::from_static("\"1-0\", \"12-af\""),
+        );
+        assert!(matches_etag(&headers, &etag));
+        headers.insert("if-none-match", HeaderValue::from_static("*"));
+        assert!(matches_etag(&headers, &etag));
+    }
+
+    #[test]
+    fn decode() {
+        assert_eq!(percent_decode("/a%20b/").unwrap(), "/a b/");
+        assert!(percent_decode("/a%2").is_none());
+    }
+
+    #[test]
+    fn encode() {
+        assert_eq!(percent_encode("a b#c.txt"), "a%20b%23c.txt");
+        assert_eq!(percent_encode("a:b"), "a%3Ab");
+        let name = "\u{6587}.txt";
+        assert_eq!(percent_decode(&percent_encode(name)).unwrap(), name);
+    }
+
+    async fn get(router: &axum::Router, uri: &str, headers: &[(&str, &str)]) -> Response {
+        let mut req = Request::get(uri);
+        for (name, value) in headers {
+            req = req.header(*name, *value);
+        }
+        let req = req.body(Body::empty()).unwrap();
+        router.clone().oneshot(req).await.unwrap()
+    }
+
+    async fn text(rsp: Response) -> String {
+        let body = rsp.into_body().collect().await.unwrap().to_bytes();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    fn write(dir: &Path, path: &str, text: &str) {
+        let path = dir.join(path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, text).unwrap();
+    }
+
+    #[test]
+    fn serve() {
+        let dir = std::env::temp_dir().join(format!("coral-assets-{}", std::process::id()));
+        let (app, docs) = (dir.join("app"), dir.join("docs"));
+        write(&app, "index.html", "app index");
+        write(&app, "app.3f9a0c1b.js", "app js");
+        write(&app, "logo.svg", "<svg/>");
+        write(&docs, "a b#c.txt", "docs text");
+        write(&docs, "sub dir/x.txt", "x");
+        let app_conf = format!(
+            "path = \"/\"\ndir = \"{}\"\nspa = true\n\
+             [[cache_control]]\nglob = \"**/*.html\"\nvalue = \"no-cache\"",
+            app.display()
+        );
+        let docs_conf = format!(
+            "path = \"/docs\"\ndir = \"{}\"\nlisting = true",
+            docs.display()
+        );
+        let mounts: Vec<AssetsConf> = [app_conf, docs_conf]
+            .iter()
+            .map(|v| toml::from_str(v).unwrap())
+            .collect();
+        let router = AssetsConf::router(&mounts).unwrap();
+
+        let rt = coral_runtime::tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            // hashed names are immutable, rules apply by glob
+            let rsp = get(&router, "/app.3f9a0c1b.js", &[]).await;
+            assert_eq!(rsp.status(), StatusCode::OK);
+            assert_eq!(
+                rsp.headers()[CACHE_CONTROL],
+                "public, max-age=31536000, immutable"
+            );
+            let rsp = get(&router, "/logo.svg", &[]).await;
+            assert!(rsp.headers().get(CACHE_CONTROL).is_none());
+
+            // spa falls back to index.html for paths without extension only
+            let rsp = get(&router, "/users/1", &[]).await;
+            assert_eq!(rsp.status(), StatusCode::OK);
+            assert_eq!(rsp.headers()[CACHE_CONTROL], "no-cache");
+            let etag = rsp.headers()[ETAG].to_str().unwrap().to_owned();
+            assert_eq!(text(rsp).await, "app index");
+            let rsp = get(&router, "/missing.js", &[]).await;
+            assert_eq!(rsp.status(), StatusCode::NOT_FOUND);
+
+            // etag of the fallback is that of index.html
+            let rsp = get(&router, "/index.html", &[(IF_NONE_MATCH.as_str(), &etag)]).await;
+            assert_eq!(rsp.status(), StatusCode::NOT_MODIFIED);
+            assert_eq!(rsp.headers()[ETAG], etag.as_str());
+            assert_eq!(rsp.headers()[CACHE_CONTROL], "no-cache");
+            assert!(text(rsp).await.is_empty());
+            let rsp = get(&router, "/", &[(IF_NONE_MATCH.as_str(), "W/\"0-0\"")]).await;
+            assert_eq!(rsp.status(), StatusCode::OK);
+
+            // second mount with listing, no spa
+            let rsp = get(&router, "/docs/a%20b%23c.txt", &[]).await;
+            assert_eq!(text(rsp).await, "docs text");
+            let rsp = get(&router, "/docs/users", &[]).await;
+            assert_eq!(rsp.status(), StatusCode::NOT_FOUND);
+            let rsp = get(&router, "/docs/", &[]).await;
+            assert_eq!(rsp.status(), StatusCode::OK);
+            let listing = text(rsp).await;
+            assert!(listing.contains(r#"<a href="a%20b%23c.txt">a b#c.txt</a>"#));
+            assert!(listing.contains(r#"<a href="sub%20dir/">sub dir/</a>"#));
+            let rsp = get(&router, "/docs/sub%20dir/", &[]).await;
+            assert!(text(rsp).await.contains(r#"<a href="x.txt">x.txt</a>"#));
+        });
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
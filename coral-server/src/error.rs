@@ -33,12 +33,28 @@ pub enum Error {
     #[error("parse toml")]
     ParseTomlErr(#[from] toml::de::Error),
 
+    #[error("{0}")]
+    ConfErr(#[from] coral_conf::Error),
+
     #[error("serde json error")]
     JsonErr(#[from] serde_json::error::Error),
+
+    #[error("invalid glob")]
+    GlobErr(#[from] glob::PatternError),
+}
+
+impl Error {
+    /// status of the response of the error
+    pub fn status(&self) -> StatusCode {
+        match self {
+            Error::CoralNetErr(err) => err.status(),
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
 }
 
 impl IntoResponse for Error {
     fn into_response(self) -> axum::response::Response {
-        (StatusCode::INTERNAL_SERVER_ERROR, format!("{:?}", self)).into_response()
+        coral_net::error::problem(self.status(), &self)
     }
 }
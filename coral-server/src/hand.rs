@@ -2,7 +2,6 @@ use axum::extract::Request;
 use axum::http::HeaderMap;
 use axum::http::HeaderName;
 use axum::http::HeaderValue;
-use axum::middleware::map_response;
 use axum::response::IntoResponse;
 use axum::routing::post;
 use bytes::Bytes;
@@ -12,12 +11,8 @@ use fastrace::future::FutureExt;
 use fastrace::local::LocalSpan;
 use fastrace::Span;
 use http_body_util::BodyExt;
-use hyper::header::ALT_SVC;
 use hyper::StatusCode;
 use log::info;
-use std::sync::OnceLock;
-
-pub static H3_PORT: OnceLock<u16> = OnceLock::new();
 
 /// 健康检查
 async fn heartbeat() -> hyper::Response<axum::body::Body> {
@@ -103,34 +98,50 @@ async fn other_job() {
     }
 }
 
-async fn alt_svc_header<B>(mut rsp: hyper::Response<B>) -> hyper::Response<B> {
-    // tokio::time::sleep(std::time::Duration::from_secs(2)).await;
-    rsp.headers_mut()
-        // .insert(ALT_SVC, HeaderValue::from_str(&alt_svc).unwrap());
-        .insert(
-            ALT_SVC,
-            HeaderValue::from_static(
-                "h3-27=\":443\"; ma=86400, h3-28=\":443\"; ma=86400, h3-29=\":443\"; ma=86400, h3=\":443\"; ma=86400",
-            ),
-        );
-    rsp
-}
-
 // TODO: will test wasm protobuf in frontend
 async fn test_payload(req: Request) {
     // req.body()
 }
 
-pub fn router(conf: &crate::cli::Conf) -> axum::Router {
+pub fn router(conf: &crate::cli::Conf) -> crate::error::CoralRes<axum::Router> {
     let router = match conf.assets.as_ref() {
-        Some(f) => f.service(),
+        Some(mounts) => crate::assets::AssetsConf::router(mounts)?,
         None => axum::Router::new(),
     };
-    router
-        .layer(map_response(alt_svc_header))
+    let router = router
         .route("/heartbeat", post(heartbeat))
         .route("/testhand", post(test_hand))
         .route("/benchmark", post(benchmark))
-        .route("/trace", post(test_trace))
-        .layer(coral_net::midware::TraceLayer::default())
+        .route("/trace", post(test_trace));
+    let router = match conf.rate_limit.as_ref() {
+        Some(rate_limit) => router.layer(rate_limit.layer()?),
+        None => router,
+    };
+    let router = router.layer(coral_net::midware::MetricsLayer::default());
+    // h2 and h3 share the router, decompressed bodies are bounded by the
+    // larger limit and unbounded if either listener has none
+    let max_body_size = conf
+        .h2
+        .server_conf
+        .max_body_size
+        .zip(conf.h3.server_conf.max_body_size)
+        .map(|(h2, h3)| h2.max(h3));
+    let router = match conf.compression.as_ref() {
+        Some(compression) => router.layer(compression.layer(max_body_size)?),
+        None => router,
+    };
+    let alt_svc = conf.alt_svc.clone().unwrap_or_default().layer(
+        conf.h3.server_conf.port,
+        conf.h3.tls_conf.alpn(),
+        coral_runtime::supervise::drain().clone(),
+    )?;
+    let router = match alt_svc {
+        Some(alt_svc) => router.layer(alt_svc),
+        None => router,
+    };
+    let router = match conf.access_log.as_ref() {
+        Some(access_log) => router.layer(access_log.layer()?),
+        None => router,
+    };
+    Ok(router.layer(coral_net::midware::TraceLayer::default()))
 }
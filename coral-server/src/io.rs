@@ -1,14 +1,20 @@
 use bytes::Bytes;
-use coral_runtime::spawn;
+use coral_runtime::spawn_named;
 use log::error;
 use std::net::Ipv4Addr;
 use std::net::SocketAddr;
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::cli::Conf;
 use crate::error::CoralRes;
 use coral_net::db;
 use coral_net::error::Error as NetErr;
+use coral_net::server::ServerBuiler;
+use coral_net::tls::CertResolver;
+use coral_runtime::reload::Reloader;
+use coral_runtime::supervise::Restart;
+use coral_runtime::tokio::sync::watch;
 use futures::future::BoxFuture;
 
 pub struct App<F> {
@@ -18,13 +24,66 @@ pub struct App<F> {
     rdh: Option<BoxFuture<'static, Result<db::RedisClient, NetErr>>>,
     h2_builder: Option<coral_net::server::ServerBuiler>,
     h3_builder: Option<coral_net::server::ServerBuiler>,
+    reload: Option<Reload>,
 }
 
+/// applies reloadable parts of configs published by reloader in place
+struct Reload {
+    reloader: Reloader<Conf>,
+    routes: watch::Sender<axum::Router>,
+    h2_cert: Arc<CertResolver>,
+    h3_cert: Arc<CertResolver>,
+}
+
+impl Reload {
+    async fn run(self) {
+        let mut rx = self.reloader.subscribe();
+        spawn_named("reloader", self.reloader.run());
+        while rx.changed().await.is_ok() {
+            let conf = rx.borrow_and_update().clone();
+            conf.log_conf.set_level();
+            match crate::hand::router(&conf) {
+                Ok(router) => {
+                    self.routes.send_replace(router);
+                }
+                Err(err) => {
+                    error!(e = format!("{:?}", err); "failed to rebuild router on reload");
+                }
+            }
+            for (resolver, tls_conf) in [
+                (&self.h2_cert, &conf.h2.tls_conf),
+                (&self.h3_cert, &conf.h3.tls_conf),
+            ] {
+                if let Err(err) = resolver.reload(tls_conf) {
+                    error!(e = format!("{:?}", err); "failed to reload certificate");
+                }
+            }
+        }
+    }
+}
+
+/// restart of servers, which only fail by a bug
+const RESTART: Restart = Restart::OnFailure {
+    max: None,
+    backoff: Duration::from_secs(1),
+};
+
 impl<F: FnOnce() -> ()> App<F> {
+    /// Serve h2 and h3 until SIGINT or SIGTERM, servers are restarted if
+    /// failed
     pub async fn run(mut self) -> CoralRes<()> {
+        coral_runtime::supervise::cancel_on_signal(self.conf.rt_conf.drain_timeout())?;
         while let Some(f) = self.fns.pop() {
             f();
         }
+        if let Some(reload) = self.reload.take() {
+            spawn_named("reload", reload.run());
+        }
+        if let Some(admin) = self.conf.admin.clone() {
+            coral_runtime::supervise::root()
+                .child("admin")
+                .supervise(RESTART, move || admin.clone().serve());
+        }
         let dbh = if let Some(dbh) = self.dbh.take() {
             Some(dbh.await?)
         } else {
@@ -48,25 +107,22 @@ impl<F: FnOnce() -> ()> App<F> {
                 }
                 coral_net::hand::redirect_h2(req, router)
             };
-            spawn(async {
-                if let Err(err) = h2_builder.h2_server(Some(map_req)).await {
-                    log::error!(e = format!("{:?}", err); "h2 server run error");
-                }
-            });
+            coral_runtime::supervise::root()
+                .child("h2-server")
+                .supervise(RESTART, move || {
+                    h2_builder.clone().h2_server(Some(map_req.clone()))
+                });
         }
         let h3_builder = self.h3_builder.take().unwrap();
-        let mut transport_config = quinn_proto::TransportConfig::default();
-        transport_config.max_idle_timeout(Some(quinn_proto::VarInt::from_u32(3600000).into()));
-        let h3_server =
-            h3_builder.h3_server(Some(Arc::new(transport_config)), move |mut req| {
-                if let Some(h) = dbh.clone() {
-                    req.extensions_mut().insert(h);
-                }
-                if let Some(h) = rdh.clone() {
-                    req.extensions_mut().insert(h);
-                }
-                req
-            })?;
+        let h3_server = h3_builder.h3_server(None, move |mut req| {
+            if let Some(h) = dbh.clone() {
+                req.extensions_mut().insert(h);
+            }
+            if let Some(h) = rdh.clone() {
+                req.extensions_mut().insert(h);
+            }
+            req
+        })?;
         if self.conf.h3.server_conf.domain.is_some() && self.conf.h3.service_address.is_some() {
             let authorith = format!(
                 "{}:{}",
@@ -80,24 +136,42 @@ impl<F: FnOnce() -> ()> App<F> {
             )
             .await?;
         }
-        h3_server.run_server().await?;
+        coral_runtime::supervise::root()
+            .child("h3-server")
+            .supervise(RESTART, move || h3_server.clone().run_server())
+            .await
+            .map_err(coral_runtime::Error::from)?;
         Ok(())
     }
 }
 
 impl Conf {
-    pub fn app(&self, router: axum::Router) -> CoralRes<App<impl FnOnce()>> {
-        let mut fns = Vec::new();
+    /// app serving routes of [`crate::hand::router`], rebuilt on every reload
+    pub fn app(&self, reloader: Reloader<Conf>) -> CoralRes<App<Box<dyn FnOnce()>>> {
+        let mut fns: Vec<Box<dyn FnOnce()>> = Vec::new();
         if let Some(f) = self.log_conf.set_traces() {
-            fns.push(f);
+            fns.push(Box::new(f));
+        }
+        if let Some(f) = self.log_conf.set_metrics() {
+            fns.push(Box::new(f));
         }
+        let (routes, rx) = watch::channel(crate::hand::router(self)?);
+        let router = coral_net::hand::reloadable(rx);
+        let (h2_builder, h2_cert) = self.h2_builder(router.clone())?;
+        let (h3_builder, h3_cert) = self.h3_server(router)?;
         Ok(App {
             conf: self.clone(),
             fns,
             dbh: self.dbh(),
             rdh: self.rdh(),
-            h2_builder: Some(self.h2_builder(router.clone())?),
-            h3_builder: Some(self.h3_server(router)?),
+            h2_builder: Some(h2_builder),
+            h3_builder: Some(h3_builder),
+            reload: Some(Reload {
+                reloader,
+                routes,
+                h2_cert,
+                h3_cert,
+            }),
         })
     }
 
@@ -129,28 +203,29 @@ impl Conf {
         }
     }
 
-    fn h2_builder(&self, router: axum::Router) -> CoralRes<coral_net::server::ServerBuiler> {
+    fn h2_builder(&self, router: axum::Router) -> CoralRes<(ServerBuiler, Arc<CertResolver>)> {
         let addr_h2 = SocketAddr::new(
             std::net::IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
             self.h2.server_conf.port,
         );
-        let tls_conf = self.h2.tls_conf.server_conf()?;
+        let (tls_conf, cert) = self.h2.tls_conf.reloadable_server_conf()?;
 
-        let h2_builder =
-            coral_net::server::ServerBuiler::new(addr_h2, tls_conf).set_router(router.clone());
-        Ok(h2_builder)
+        let h2_builder = ServerBuiler::new(addr_h2, tls_conf)
+            .set_server_conf(&self.h2.server_conf)
+            .set_router(router.clone());
+        Ok((h2_builder, cert))
     }
 
-    fn h3_server(&self, router: axum::Router) -> CoralRes<coral_net::server::ServerBuiler> {
-        crate::hand::H3_PORT.set(self.h3.server_conf.port).unwrap();
+    fn h3_server(&self, router: axum::Router) -> CoralRes<(ServerBuiler, Arc<CertResolver>)> {
         let addr_h3 = SocketAddr::new(
             std::net::IpAddr::V4(Ipv4Addr::new(0, 0, 0, 0)),
             self.h3.server_conf.port,
         );
-        let builder =
-            coral_net::server::ServerBuiler::new(addr_h3, self.h3.tls_conf.server_conf()?)
-                .set_router(router);
-        Ok(builder)
+        let (tls_conf, cert) = self.h3.tls_conf.reloadable_server_conf()?;
+        let builder = ServerBuiler::new(addr_h3, tls_conf)
+            .set_server_conf(&self.h3.server_conf)
+            .set_router(router);
+        Ok((builder, cert))
     }
 }
 
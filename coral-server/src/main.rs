@@ -1,5 +1,6 @@
 use error::CoralRes;
 
+mod assets;
 mod cli;
 mod error;
 mod hand;
@@ -9,12 +10,16 @@ mod payload;
 mod user;
 use log::error;
 fn main() -> CoralRes<()> {
-    let conf = cli::Cli::init()?;
+    let reloader = cli::Cli::init()?;
+    let conf = reloader.conf();
     let rt = conf.rt_conf.runtime("coral_server")?;
-    let router = hand::router(&conf);
-    let app = conf.app(router)?;
+    let app = conf.app(reloader)?;
     if let Err(err) = rt.block_on(app.run()) {
         error!(e = format!("{:?}", err); "block on server");
     }
+    let root = coral_runtime::supervise::root();
+    if !rt.block_on(root.shutdown(conf.rt_conf.shutdown_timeout())) {
+        log::warn!("tasks not finished before shutdown timeout");
+    }
     Ok(())
 }